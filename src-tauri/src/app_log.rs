@@ -0,0 +1,185 @@
+use lazy_static::lazy_static;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{reload, Registry};
+
+/// Directory under the app data dir holding the app's own log files.
+pub const APP_LOG_DIR: &str = "logs";
+// Rotated daily to `logtoolpro.log.YYYY-MM-DD`
+const APP_LOG_FILE_PREFIX: &str = "logtoolpro.log";
+
+// Keys whose values never reach the log file
+const SECRET_KEYS: [&str; 4] = ["password", "passphrase", "passwd", "secret"];
+
+struct AppLog {
+    dir: PathBuf,
+    level: reload::Handle<LevelFilter, Registry>,
+    // Flushes buffered lines when dropped, so it lives as long as the app
+    _guard: WorkerGuard,
+}
+
+lazy_static! {
+    static ref APP_LOG: Mutex<Option<AppLog>> = Mutex::new(None);
+}
+
+/// Replaces the value after `password=`, `"passphrase": "…"` and the like
+/// with `***`.
+pub fn redact(text: &str) -> String {
+    let lower = text.to_ascii_lowercase();
+    let bytes = text.as_bytes();
+    let mut out = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let key = match SECRET_KEYS.iter().find(|k| lower.as_bytes()[i..].starts_with(k.as_bytes())) {
+            Some(key) => key,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        // Skip a closing quote and spaces up to the separator
+        let mut j = i + key.len();
+        while j < bytes.len() && matches!(bytes[j], b'"' | b'\'' | b' ') {
+            j += 1;
+        }
+        if j >= bytes.len() || !matches!(bytes[j], b'=' | b':') {
+            i += key.len();
+            continue;
+        }
+        j += 1;
+        while j < bytes.len() && bytes[j] == b' ' {
+            j += 1;
+        }
+        // A quoted value runs to its closing quote, spaces included
+        let quote = bytes.get(j).copied().filter(|b| matches!(b, b'"' | b'\''));
+        if quote.is_some() {
+            j += 1;
+        }
+        let mut end = j;
+        while end < bytes.len() {
+            let at_end = match quote {
+                Some(q) => bytes[end] == q,
+                None => matches!(bytes[end], b' ' | b'\t' | b'\n' | b',' | b'}' | b'&'),
+            };
+            if at_end {
+                break;
+            }
+            end += 1;
+        }
+        out.push_str(&text[copied..j]);
+        if end > j {
+            out.push_str("***");
+        }
+        copied = end;
+        i = end.max(j);
+    }
+    out.push_str(&text[copied..]);
+    out
+}
+
+// Redacts every formatted line on its way to the file. The fmt layer writes
+// each event with a single call, so a secret is never split across writes.
+struct RedactingWriter<W: Write>(W);
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = redact(&String::from_utf8_lossy(buf));
+        self.0.write_all(line.as_bytes())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    match level.trim().to_ascii_lowercase().as_str() {
+        "off" => Ok(LevelFilter::OFF),
+        "error" => Ok(LevelFilter::ERROR),
+        "warn" => Ok(LevelFilter::WARN),
+        "info" => Ok(LevelFilter::INFO),
+        "debug" => Ok(LevelFilter::DEBUG),
+        "trace" => Ok(LevelFilter::TRACE),
+        other => Err(format!(
+            "Invalid log level {:?}: use off, error, warn, info, debug or trace",
+            other
+        )),
+    }
+}
+
+/// Starts writing `tracing` events and span timings to a daily-rotating file
+/// in `dir`, at info level until `set_level` changes it.
+pub fn init(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let (writer, guard) = tracing_appender::non_blocking(tracing_appender::rolling::daily(dir, APP_LOG_FILE_PREFIX));
+    let (level, handle) = reload::Layer::new(LevelFilter::INFO);
+    tracing_subscriber::registry()
+        .with(level)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                // Logs each span's duration when it closes
+                .with_span_events(FmtSpan::CLOSE)
+                .with_writer(move || RedactingWriter(writer.clone())),
+        )
+        .try_init()
+        .map_err(|e| format!("Failed to start app logging: {}", e))?;
+
+    *APP_LOG.lock().map_err(|_| "Lock failed")? = Some(AppLog {
+        dir: dir.to_path_buf(),
+        level: handle,
+        _guard: guard,
+    });
+    Ok(())
+}
+
+/// Changes which events are written: off, error, warn, info, debug or trace.
+pub fn set_level(level: &str) -> Result<(), String> {
+    let level = parse_level(level)?;
+    let app_log = APP_LOG.lock().map_err(|_| "Lock failed")?;
+    let app_log = app_log.as_ref().ok_or("App logging is not running")?;
+    app_log
+        .level
+        .modify(|filter| *filter = level)
+        .map_err(|e| format!("Failed to set log level: {}", e))
+}
+
+/// The directory the log files are written to.
+pub fn log_dir() -> Result<PathBuf, String> {
+    let app_log = APP_LOG.lock().map_err(|_| "Lock failed")?;
+    app_log
+        .as_ref()
+        .map(|log| log.dir.clone())
+        .ok_or_else(|| "App logging is not running".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact() {
+        assert_eq!(redact("connect password=hunter2 host=a"), "connect password=*** host=a");
+        assert_eq!(
+            redact(r#"SshTarget { host: "a", password: "hunter2", passphrase: None }"#),
+            r#"SshTarget { host: "a", password: "***", passphrase: *** }"#
+        );
+        assert_eq!(redact(r#"{"Password":"x y","port":22}"#), r#"{"Password":"***","port":22}"#);
+        // Mentions without a value are left alone
+        assert_eq!(redact("Authentication failed: bad password"), "Authentication failed: bad password");
+        assert_eq!(redact("mot de passe: pässwörd=ü"), "mot de passe: pässwörd=ü");
+
+        let mut out = Vec::new();
+        RedactingWriter(&mut out).write_all(b"secret: abc\n").unwrap();
+        assert_eq!(out, b"secret: ***\n");
+    }
+}
@@ -0,0 +1,209 @@
+use serde::Serialize;
+
+use crate::{ChainNode, ChainTraceResult};
+
+#[derive(Serialize)]
+struct AdjacencyNode<'a> {
+    id: usize,
+    dus_id: &'a str,
+    ip: &'a str,
+    filename: &'a str,
+    log_path: &'a str,
+    fallback: bool,
+}
+
+#[derive(Serialize)]
+struct AdjacencyEdge {
+    from: usize,
+    to: usize,
+}
+
+#[derive(Serialize)]
+struct AdjacencyList<'a> {
+    roots: Vec<usize>,
+    nodes: Vec<AdjacencyNode<'a>>,
+    edges: Vec<AdjacencyEdge>,
+}
+
+/// Numbers the tree depth-first. Every occurrence gets its own id, so the same
+/// ip or dus_id appearing on several branches never collapses into one node.
+fn flatten<'a>(
+    nodes: &'a [ChainNode],
+    parent: Option<usize>,
+    out: &mut AdjacencyList<'a>,
+) {
+    for node in nodes {
+        let id = out.nodes.len();
+        out.nodes.push(AdjacencyNode {
+            id,
+            dus_id: &node.dus_id,
+            ip: &node.ip,
+            filename: &node.filename,
+            log_path: &node.log_path,
+            fallback: node.fallback,
+        });
+        match parent {
+            Some(from) => out.edges.push(AdjacencyEdge { from, to: id }),
+            None => out.roots.push(id),
+        }
+        flatten(&node.children, Some(id), out);
+    }
+}
+
+fn adjacency_list(result: &ChainTraceResult) -> AdjacencyList<'_> {
+    let mut list = AdjacencyList {
+        roots: Vec::new(),
+        nodes: Vec::new(),
+        edges: Vec::new(),
+    };
+    flatten(&result.nodes, None, &mut list);
+    list
+}
+
+// DOT quoted strings only need `"` and `\` escaped; newlines become `\n` label breaks
+fn dot_escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders the chain as a Graphviz digraph. Fallback nodes are drawn dashed.
+pub fn to_dot(result: &ChainTraceResult) -> String {
+    let list = adjacency_list(result);
+    let mut dot = String::from("digraph chain {\n    rankdir=LR;\n    node [shape=box];\n");
+    for node in &list.nodes {
+        let style = if node.fallback { ", style=dashed" } else { "" };
+        dot.push_str(&format!(
+            "    n{} [label=\"{}\\n{}\"{}];\n",
+            node.id,
+            dot_escape(node.dus_id),
+            dot_escape(node.ip),
+            style
+        ));
+    }
+    for edge in &list.edges {
+        dot.push_str(&format!("    n{} -> n{};\n", edge.from, edge.to));
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+/// Renders the chain as `{ roots, nodes, edges }` with numeric node ids.
+pub fn to_json(result: &ChainTraceResult) -> Result<String, String> {
+    serde_json::to_string_pretty(&adjacency_list(result))
+        .map_err(|e| format!("Failed to serialize chain: {}", e))
+}
+
+/// A chain node of `flatten_chain`, without its children.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FlatNode {
+    pub id: usize,
+    pub dus_id: String,
+    pub ip: String,
+    pub filename: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FlatEdge {
+    pub from_id: usize,
+    pub to_id: usize,
+}
+
+/// The chain tree as a node and edge list, ready for a graph layout library.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FlatChain {
+    pub nodes: Vec<FlatNode>,
+    pub edges: Vec<FlatEdge>,
+}
+
+/// Flattens the chain with the same depth-first ids as `to_json`, so the
+/// same result always gets the same ids.
+pub fn to_flat(result: &ChainTraceResult) -> FlatChain {
+    let list = adjacency_list(result);
+    FlatChain {
+        nodes: list
+            .nodes
+            .iter()
+            .map(|node| FlatNode {
+                id: node.id,
+                dus_id: node.dus_id.to_string(),
+                ip: node.ip.to_string(),
+                filename: node.filename.to_string(),
+            })
+            .collect(),
+        edges: list
+            .edges
+            .iter()
+            .map(|edge| FlatEdge {
+                from_id: edge.from,
+                to_id: edge.to,
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(dus_id: &str, ip: &str, children: Vec<ChainNode>) -> ChainNode {
+        ChainNode {
+            filename: "gw.log".to_string(),
+            dus_id: dus_id.to_string(),
+            ip: ip.to_string(),
+            log_path: "/logs".to_string(),
+            children,
+            fallback: false,
+        }
+    }
+
+    fn sample() -> ChainTraceResult {
+        let mut fallback = node("B\"9", "10.0.0.2", Vec::new());
+        fallback.fallback = true;
+        ChainTraceResult {
+            nodes: vec![
+                node("B001", "10.0.0.1", vec![node("C002", "10.0.0.2", Vec::new()), fallback]),
+                node("G100", "10.0.0.1", Vec::new()),
+            ],
+            trace_log: Vec::new(),
+            total_hops: 2,
+            duration_ms: 0,
+            error: None,
+            loops: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_dot_keeps_duplicate_ips_apart() {
+        let dot = to_dot(&sample());
+        assert!(dot.starts_with("digraph chain {"));
+        assert!(dot.contains("n0 [label=\"B001\\n10.0.0.1\"];"));
+        assert!(dot.contains("n3 [label=\"G100\\n10.0.0.1\"];"));
+        assert!(dot.contains("n2 [label=\"B\\\"9\\n10.0.0.2\", style=dashed];"));
+        assert!(dot.contains("n0 -> n1;") && dot.contains("n0 -> n2;"));
+        assert!(!dot.contains("-> n3"));
+    }
+
+    #[test]
+    fn test_json_adjacency_list() {
+        let value: serde_json::Value = serde_json::from_str(&to_json(&sample()).unwrap()).unwrap();
+        assert_eq!(value["roots"], serde_json::json!([0, 3]));
+        assert_eq!(value["nodes"].as_array().unwrap().len(), 4);
+        assert_eq!(value["edges"][1], serde_json::json!({ "from": 0, "to": 2 }));
+        assert_eq!(value["nodes"][2]["fallback"], true);
+    }
+
+    #[test]
+    fn test_flat_chain() {
+        let flat = to_flat(&sample());
+        let ids: Vec<(usize, &str)> = flat.nodes.iter().map(|n| (n.id, n.dus_id.as_str())).collect();
+        assert_eq!(ids, [(0, "B001"), (1, "C002"), (2, "B\"9"), (3, "G100")]);
+        assert_eq!(
+            flat.edges,
+            [FlatEdge { from_id: 0, to_id: 1 }, FlatEdge { from_id: 0, to_id: 2 }]
+        );
+        assert_eq!(flat.nodes[3].ip, "10.0.0.1");
+        assert_eq!(flat, to_flat(&sample()));
+    }
+}
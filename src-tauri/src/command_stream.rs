@@ -0,0 +1,205 @@
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use ssh2::{Channel, Session};
+use std::io::{self, Read};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+use crate::ssh_connect::{self, SshTarget};
+use crate::ssh_session::Utf8StreamDecoder;
+
+#[derive(Clone, Serialize)]
+pub struct CommandOutput {
+    pub exec_id: String,
+    pub stream: String, // "stdout" or "stderr"
+    pub data: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct CommandExit {
+    pub exec_id: String,
+    pub exit_status: i32, // -1 if the server didn't report one
+    pub cancelled: bool,
+}
+
+pub struct RunningCommand {
+    channel: Channel,
+    session: Session,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl RunningCommand {
+    fn close(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Back to blocking so EOF/close are actually sent rather than hitting WouldBlock
+        self.session.set_blocking(true);
+        let _ = self.channel.send_eof();
+        // Most commands ignore stdin EOF, so close the channel to stop them remotely
+        let _ = self.channel.close();
+        let _ = self.session.disconnect(None, "Command cancelled", None);
+    }
+}
+
+// Reads whatever is buffered without blocking; 0 means nothing right now
+fn read_available(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+    match reader.read(buffer) {
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(0),
+        other => other,
+    }
+}
+
+lazy_static! {
+    pub static ref COMMAND_MANAGER: CommandManager = CommandManager::new();
+}
+
+pub struct CommandManager {
+    commands: DashMap<String, Arc<Mutex<RunningCommand>>>,
+}
+
+impl CommandManager {
+    pub fn new() -> Self {
+        Self {
+            commands: DashMap::new(),
+        }
+    }
+
+    /// Runs `command` and streams its output as `command-output` events as it
+    /// arrives, followed by one `command-exit` event. Returns the exec id.
+    pub fn start_command(
+        &self,
+        app_handle: AppHandle,
+        target: SshTarget,
+        command: String,
+    ) -> Result<String, String> {
+        let exec_id = Uuid::new_v4().to_string();
+
+        let sess = ssh_connect::connect(&target, Duration::from_secs(30))?;
+        let mut channel = sess
+            .channel_session()
+            .map_err(|e| format!("Channel failed: {}", e))?;
+        channel
+            .exec(&command)
+            .map_err(|e| format!("Exec failed: {}", e))?;
+
+        // Non-blocking so stdout and stderr can be polled in turn
+        sess.set_blocking(false);
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_clone = shutdown.clone();
+        let exec_id_clone = exec_id.clone();
+
+        let command_arc = Arc::new(Mutex::new(RunningCommand {
+            channel,
+            session: sess,
+            shutdown,
+        }));
+        self.commands.insert(exec_id.clone(), command_arc.clone());
+
+        thread::spawn(move || {
+            let mut buffer = [0u8; 8192];
+            let mut stdout_decoder = Utf8StreamDecoder::default();
+            let mut stderr_decoder = Utf8StreamDecoder::default();
+
+            // Whether the command ran to completion
+            let finished = loop {
+                if shutdown_clone.load(Ordering::SeqCst) {
+                    break false;
+                }
+
+                let (stdout, stderr, eof) = {
+                    let mut running = match command_arc.lock() {
+                        Ok(c) => c,
+                        Err(_) => break false,
+                    };
+
+                    let n = match read_available(&mut running.channel, &mut buffer) {
+                        Ok(n) => n,
+                        Err(_) => break false,
+                    };
+                    let stdout = stdout_decoder.decode(&buffer[..n]);
+                    let got_stdout = n > 0;
+
+                    let n = match read_available(&mut running.channel.stderr(), &mut buffer) {
+                        Ok(n) => n,
+                        Err(_) => break false,
+                    };
+                    let stderr = stderr_decoder.decode(&buffer[..n]);
+
+                    // Only done once both streams have been drained
+                    let eof = !got_stdout && n == 0 && running.channel.eof();
+                    (stdout, stderr, eof)
+                };
+
+                let idle = stdout.is_empty() && stderr.is_empty();
+                for (stream, data) in [("stdout", stdout), ("stderr", stderr)] {
+                    if !data.is_empty() {
+                        let _ = app_handle.emit(
+                            "command-output",
+                            CommandOutput {
+                                exec_id: exec_id_clone.clone(),
+                                stream: stream.to_string(),
+                                data,
+                            },
+                        );
+                    }
+                }
+
+                if eof {
+                    break true;
+                }
+                if idle {
+                    thread::sleep(Duration::from_millis(20));
+                }
+            };
+
+            let exit_status = if finished {
+                command_arc
+                    .lock()
+                    .ok()
+                    .and_then(|mut running| {
+                        running.session.set_blocking(true);
+                        running.channel.wait_close().ok()?;
+                        running.channel.exit_status().ok()
+                    })
+                    .unwrap_or(-1)
+            } else {
+                -1
+            };
+
+            COMMAND_MANAGER.commands.remove(&exec_id_clone);
+            let _ = app_handle.emit(
+                "command-exit",
+                CommandExit {
+                    exec_id: exec_id_clone,
+                    exit_status,
+                    cancelled: shutdown_clone.load(Ordering::SeqCst),
+                },
+            );
+        });
+
+        Ok(exec_id)
+    }
+
+    /// Cancels every running command. Returns how many there were.
+    pub fn cancel_all(&self) -> usize {
+        let ids: Vec<String> = self.commands.iter().map(|entry| entry.key().clone()).collect();
+        for id in &ids {
+            let _ = self.cancel_command(id);
+        }
+        ids.len()
+    }
+
+    pub fn cancel_command(&self, exec_id: &str) -> Result<(), String> {
+        if let Some((_, command)) = self.commands.remove(exec_id) {
+            if let Ok(mut c) = command.lock() {
+                c.close();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,285 @@
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use ssh2::Session;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::ssh_connect::{self, AuthMethod, SshTarget};
+
+const DEFAULT_IDLE_TTL: Duration = Duration::from_secs(300);
+
+// A cached session only serves targets with exactly the settings it was opened
+// with, so edited credentials, jump host, compression or timeouts take effect
+// at once. The settings are kept as a digest so no secret lives in the key.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+struct PoolKey {
+    host: String,
+    port: u16,
+    username: String,
+    settings: [u8; 32],
+}
+
+impl PoolKey {
+    fn new(target: &SshTarget, read_timeout: Duration) -> Self {
+        let mut digest = Sha256::new();
+        let mut field = |value: &[u8]| {
+            digest.update((value.len() as u64).to_le_bytes());
+            digest.update(value);
+        };
+        let auth_method = |method: Option<AuthMethod>| format!("{:?}", method);
+        field(target.password.as_bytes());
+        field(target.private_key_path.as_deref().unwrap_or_default().as_bytes());
+        field(target.passphrase.as_deref().unwrap_or_default().as_bytes());
+        field(auth_method(target.auth_method).as_bytes());
+        field(format!("{:?}", target.connect_timeout()).as_bytes());
+        field(format!("{:?}", target.read_timeout(read_timeout)).as_bytes());
+        field(&[target.use_compression as u8]);
+        if let Some(jump) = &target.jump_host {
+            field(jump.host.as_bytes());
+            field(&jump.port.to_le_bytes());
+            field(jump.username.as_bytes());
+            field(jump.password.as_bytes());
+            field(jump.private_key_path.as_deref().unwrap_or_default().as_bytes());
+            field(jump.passphrase.as_deref().unwrap_or_default().as_bytes());
+            field(auth_method(jump.auth_method).as_bytes());
+        }
+        Self {
+            host: target.host.clone(),
+            port: target.port,
+            username: target.username.clone(),
+            settings: digest.finalize().into(),
+        }
+    }
+}
+
+struct PooledSession {
+    session: Session,
+    last_used: Instant,
+}
+
+lazy_static! {
+    pub static ref CONNECTION_POOL: ConnectionPool = ConnectionPool::new(DEFAULT_IDLE_TTL);
+}
+
+/// Keeps authenticated sessions alive between search/read calls so repeated
+/// clicks on the same host skip the TCP connect, handshake and auth.
+pub struct ConnectionPool {
+    sessions: DashMap<PoolKey, PooledSession>,
+    idle_ttl: Mutex<Duration>,
+}
+
+impl ConnectionPool {
+    pub fn new(idle_ttl: Duration) -> Self {
+        Self {
+            sessions: DashMap::new(),
+            idle_ttl: Mutex::new(idle_ttl),
+        }
+    }
+
+    pub fn set_idle_ttl(&self, idle_ttl: Duration) {
+        if let Ok(mut ttl) = self.idle_ttl.lock() {
+            *ttl = idle_ttl;
+        }
+    }
+
+    fn idle_ttl(&self) -> Duration {
+        self.idle_ttl
+            .lock()
+            .map(|ttl| *ttl)
+            .unwrap_or(DEFAULT_IDLE_TTL)
+    }
+
+    /// Returns a cached session for `key`, first closing every session that
+    /// has been idle longer than the TTL (including ones left behind under
+    /// settings that have since changed).
+    fn checkout(&self, key: &PoolKey) -> Option<Session> {
+        let ttl = self.idle_ttl();
+        let expired: Vec<PoolKey> = self
+            .sessions
+            .iter()
+            .filter(|entry| entry.last_used.elapsed() > ttl)
+            .map(|entry| entry.key().clone())
+            .collect();
+        for expired_key in &expired {
+            self.disconnect_key(expired_key);
+        }
+
+        let mut entry = self.sessions.get_mut(key)?;
+        entry.last_used = Instant::now();
+        Some(entry.session.clone())
+    }
+
+    /// Runs `op` on a pooled session for `target`, connecting if needed.
+    /// If `op` fails on a cached session whose connection turns out to be gone
+    /// (the server may simply have dropped it while idle), the session is
+    /// evicted and `op` retried once on a fresh connection. Failures of a
+    /// still-working session, such as a remote command erroring, are returned
+    /// as they are.
+    pub fn with_session<T>(
+        &self,
+        target: &SshTarget,
+        read_timeout: Duration,
+        op: impl Fn(&Session) -> Result<T, String>,
+    ) -> Result<T, String> {
+        let key = PoolKey::new(target, read_timeout);
+
+        if let Some(sess) = self.checkout(&key) {
+            match op(&sess) {
+                Ok(value) => return Ok(value),
+                Err(e) if is_alive(&sess) => return Err(e),
+                Err(_) => {
+                    self.disconnect_key(&key);
+                }
+            }
+        }
+
+        let sess = ssh_connect::connect(target, read_timeout)?;
+        let result = op(&sess);
+        if result.is_ok() {
+            self.sessions.insert(
+                key,
+                PooledSession {
+                    session: sess,
+                    last_used: Instant::now(),
+                },
+            );
+        }
+        result
+    }
+
+    fn disconnect_key(&self, key: &PoolKey) -> bool {
+        match self.sessions.remove(key) {
+            Some((_, pooled)) => {
+                let _ = pooled.session.disconnect(None, "Connection closed", None);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Closes every cached connection. Returns how many were open.
+    pub fn close_all(&self) -> usize {
+        let keys: Vec<PoolKey> = self.sessions.iter().map(|entry| entry.key().clone()).collect();
+        keys.iter().filter(|key| self.disconnect_key(key)).count()
+    }
+
+    /// Closes the cached connections for `host:port` as `username`, whatever
+    /// settings they were opened with. Returns whether any was actually open.
+    pub fn disconnect(&self, host: &str, port: u16, username: &str) -> bool {
+        let keys: Vec<PoolKey> = self
+            .sessions
+            .iter()
+            .map(|entry| entry.key().clone())
+            .filter(|key| key.host == host && key.port == port && key.username == username)
+            .collect();
+        keys.iter().filter(|key| self.disconnect_key(key)).count() > 0
+    }
+}
+
+// Whether the transport still works, checked by opening (and closing) a channel
+fn is_alive(sess: &Session) -> bool {
+    match sess.channel_session() {
+        Ok(mut channel) => {
+            let _ = channel.close();
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ssh_connect::JumpConfig;
+    use std::cell::Cell;
+
+    fn target(port: u16) -> SshTarget {
+        let mut target = SshTarget::default();
+        target.host = "127.0.0.1".to_string();
+        target.port = port;
+        target.username = "app".to_string();
+        target.password = "secret".to_string();
+        target
+    }
+
+    // A session over a socket the peer already closed, like one the server
+    // dropped while it sat idle
+    fn dropped() -> PooledSession {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        drop(listener.accept().unwrap());
+        let mut session = Session::new().unwrap();
+        session.set_tcp_stream(stream);
+        PooledSession {
+            session,
+            last_used: Instant::now(),
+        }
+    }
+
+    #[test]
+    fn test_key_covers_connection_settings() {
+        let timeout = Duration::from_secs(30);
+        let base = PoolKey::new(&target(22), timeout);
+        assert_eq!(PoolKey::new(&target(22), timeout), base);
+
+        let changes: [fn(&mut SshTarget); 6] = [
+            |t| t.password = "rotated".to_string(),
+            |t| t.private_key_path = Some("/home/app/.ssh/id_ed25519".to_string()),
+            |t| t.auth_method = Some(AuthMethod::Agent),
+            |t| t.use_compression = true,
+            |t| t.connect_timeout_secs = Some(3),
+            |t| t.jump_host = Some(JumpConfig::default()),
+        ];
+        for change in changes {
+            let mut changed = target(22);
+            change(&mut changed);
+            assert_ne!(PoolKey::new(&changed, timeout), base);
+        }
+        assert_ne!(PoolKey::new(&target(22), Duration::from_secs(5)), base);
+    }
+
+    #[test]
+    fn test_disconnect_closes_every_variant() {
+        let pool = ConnectionPool::new(DEFAULT_IDLE_TTL);
+        let mut rotated = target(22);
+        rotated.password = "rotated".to_string();
+        pool.sessions.insert(PoolKey::new(&target(22), DEFAULT_IDLE_TTL), dropped());
+        pool.sessions.insert(PoolKey::new(&rotated, DEFAULT_IDLE_TTL), dropped());
+        pool.sessions.insert(PoolKey::new(&target(2222), DEFAULT_IDLE_TTL), dropped());
+
+        assert!(pool.disconnect("127.0.0.1", 22, "app"));
+        assert!(!pool.disconnect("127.0.0.1", 22, "app"));
+        assert_eq!(pool.sessions.len(), 1);
+    }
+
+    #[test]
+    fn test_idle_sessions_are_closed() {
+        let pool = ConnectionPool::new(Duration::from_millis(1));
+        pool.sessions.insert(PoolKey::new(&target(22), DEFAULT_IDLE_TTL), dropped());
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(pool.checkout(&PoolKey::new(&target(2222), DEFAULT_IDLE_TTL)).is_none());
+        assert!(pool.sessions.is_empty());
+    }
+
+    #[test]
+    fn test_dead_session_is_replaced() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = target(listener.local_addr().unwrap().port());
+        drop(listener);
+        let timeout = Duration::from_secs(1);
+        let pool = ConnectionPool::new(DEFAULT_IDLE_TTL);
+        pool.sessions.insert(PoolKey::new(&target, timeout), dropped());
+
+        // The cached session can't open a channel, so it's evicted and a fresh
+        // connection tried (which fails here, as nothing listens)
+        let calls = Cell::new(0);
+        let result: Result<(), String> = pool.with_session(&target, timeout, |_| {
+            calls.set(calls.get() + 1);
+            Err("command failed".to_string())
+        });
+        assert_eq!(calls.get(), 1);
+        assert_ne!(result, Err("command failed".to_string()));
+        assert!(pool.sessions.is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+use serde::Serialize;
+use ssh2::Session;
+use std::io::Read;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::known_hosts::KNOWN_HOSTS;
+use crate::ssh_connect::{self, JumpConfig, SshTarget};
+
+// Duration and status of the TCP, handshake, auth and exec phases
+type Phases = [(u64, PhaseStatus); 4];
+
+const CANCELLED_MESSAGE: &str = "Connection test cancelled";
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PhaseStatus {
+    Ok,
+    Failed,
+    // Not reached because an earlier phase failed
+    Skipped,
+}
+
+/// Result of `test_ssh_connection`: the friendly summary, plus how long each
+/// phase took and whether it succeeded, to show where a flaky link stalls.
+#[derive(Serialize, Clone, Debug)]
+pub struct ConnectionTest {
+    pub success: bool,
+    pub message: String,
+    pub tcp_connect_ms: u64,
+    pub tcp_connect_status: PhaseStatus,
+    pub handshake_ms: u64,
+    pub handshake_status: PhaseStatus,
+    pub auth_ms: u64,
+    pub auth_status: PhaseStatus,
+    pub exec_ms: u64,
+    pub exec_status: PhaseStatus,
+}
+
+fn check_cancelled(cancelled: &AtomicBool) -> Result<(), String> {
+    if cancelled.load(Ordering::SeqCst) {
+        return Err(CANCELLED_MESSAGE.to_string());
+    }
+    Ok(())
+}
+
+// Runs one phase, recording its duration and status
+fn timed<T>(phase: &mut (u64, PhaseStatus), f: impl FnOnce() -> Result<T, String>) -> Result<T, String> {
+    let started = Instant::now();
+    let result = f();
+    *phase = (
+        started.elapsed().as_millis() as u64,
+        if result.is_ok() { PhaseStatus::Ok } else { PhaseStatus::Failed },
+    );
+    result
+}
+
+fn exec_echo(sess: &Session) -> Result<(), String> {
+    let mut channel = sess.channel_session()
+        .map_err(|e| format!("Failed to open channel: {}", e))?;
+    channel.exec("echo 'Connection test successful'")
+        .map_err(|e| format!("Failed to execute command: {}", e))?;
+    let mut output = String::new();
+    channel.read_to_string(&mut output)
+        .map_err(|e| format!("Failed to read output: {}", e))?;
+    channel.wait_close().ok();
+    Ok(())
+}
+
+// The TCP (over the stream from `open`), handshake and host key check, and
+// authentication phases. Stops before the next phase once `cancelled` is set.
+fn login(
+    target: &SshTarget,
+    phases: &mut Phases,
+    cancelled: &AtomicBool,
+    open: impl FnOnce() -> Result<TcpStream, String>,
+) -> Result<Session, String> {
+    let [tcp, handshake, auth, _] = phases;
+    timed(tcp, open)
+        .and_then(|stream| {
+            check_cancelled(cancelled)?;
+            timed(handshake, || {
+                let sess = ssh_connect::start_session(stream, target, Duration::from_secs(10))?;
+                KNOWN_HOSTS.verify(&sess, &target.host, target.port)?;
+                Ok(sess)
+            })
+        })
+        .and_then(|sess| {
+            check_cancelled(cancelled)?;
+            timed(auth, || ssh_connect::authenticate(&sess, target)).map(|_| sess)
+        })
+}
+
+/// Connects to `target` phase by phase (TCP, handshake and host key check,
+/// authentication, running a command) and reports each phase.
+pub fn run(target: &SshTarget) -> ConnectionTest {
+    run_cancellable(target, &AtomicBool::new(false))
+}
+
+/// Like `run`, but gives up between phases once `cancelled` is set. A phase
+/// already under way (e.g. a TCP connect to a black-holed host) still runs
+/// to its timeout.
+pub fn run_cancellable(target: &SshTarget, cancelled: &AtomicBool) -> ConnectionTest {
+    let mut phases = [(0, PhaseStatus::Skipped); 4];
+    let result = login(target, &mut phases, cancelled, || ssh_connect::open_stream(target)).and_then(|sess| {
+        check_cancelled(cancelled)?;
+        timed(&mut phases[3], || exec_echo(&sess))
+    });
+    report(target, phases, result)
+}
+
+/// The result reported for a test cancelled before it finished.
+pub fn cancelled() -> ConnectionTest {
+    let skipped = (0, PhaseStatus::Skipped);
+    ConnectionTest {
+        success: false,
+        message: CANCELLED_MESSAGE.to_string(),
+        tcp_connect_ms: skipped.0,
+        tcp_connect_status: skipped.1,
+        handshake_ms: skipped.0,
+        handshake_status: skipped.1,
+        auth_ms: skipped.0,
+        auth_status: skipped.1,
+        exec_ms: skipped.0,
+        exec_status: skipped.1,
+    }
+}
+
+fn report(target: &SshTarget, phases: Phases, result: Result<(), String>) -> ConnectionTest {
+    let message = match &result {
+        Ok(()) => format!("✓ Successfully connected to {} as {}", target.host, target.username),
+        Err(e) => e.clone(),
+    };
+    let [tcp, handshake, auth, exec] = phases;
+    ConnectionTest {
+        success: result.is_ok(),
+        message,
+        tcp_connect_ms: tcp.0,
+        tcp_connect_status: tcp.1,
+        handshake_ms: handshake.0,
+        handshake_status: handshake.1,
+        auth_ms: auth.0,
+        auth_status: auth.1,
+        exec_ms: exec.0,
+        exec_status: exec.1,
+    }
+}
+
+/// Result of `test_jump_connection`, one report per hop so a failure can be
+/// pinned on the bastion or on the target behind it. The bastion runs no
+/// command; the target's TCP phase is the tunnel opened through the bastion.
+#[derive(Serialize, Debug)]
+pub struct JumpConnectionTest {
+    pub success: bool,
+    pub message: String,
+    pub bastion: ConnectionTest,
+    pub target: ConnectionTest,
+}
+
+/// Tests `target`'s jump host on its own, then `target` through a tunnel
+/// from it, phase by phase.
+pub fn run_through_jump(target: &SshTarget) -> Result<JumpConnectionTest, String> {
+    let jump = target
+        .jump_host
+        .as_ref()
+        .map(JumpConfig::target)
+        .ok_or("No jump host configured")?;
+    let mut bastion_phases = [(0, PhaseStatus::Skipped); 4];
+    let mut target_phases = [(0, PhaseStatus::Skipped); 4];
+    let never = AtomicBool::new(false);
+
+    let bastion = login(&jump, &mut bastion_phases, &never, || ssh_connect::open_stream(&jump))
+        .map_err(|e| format!("Jump host {}: {}", jump.host, e));
+    let bastion_report = report(&jump, bastion_phases, bastion.as_ref().map(|_| ()).map_err(Clone::clone));
+    let result = bastion
+        .and_then(|bastion| {
+            login(target, &mut target_phases, &never, || {
+                ssh_connect::open_tunnel(bastion, &jump.host, &target.host, target.port)
+            })
+        })
+        .and_then(|sess| timed(&mut target_phases[3], || exec_echo(&sess)));
+
+    let message = match &result {
+        Ok(()) => format!(
+            "✓ Successfully connected to {} as {} via {}",
+            target.host, target.username, jump.host
+        ),
+        Err(e) => e.clone(),
+    };
+    Ok(JumpConnectionTest {
+        success: result.is_ok(),
+        message,
+        bastion: bastion_report,
+        target: report(target, target_phases, result),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failed_phase_skips_the_rest() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let mut target = SshTarget::default();
+        target.host = "127.0.0.1".to_string();
+        target.port = port;
+        let report = run(&target);
+        assert!(!report.success);
+        assert!(report.message.starts_with("TCP connection to 127.0.0.1 failed"));
+        assert_eq!(report.tcp_connect_status, PhaseStatus::Failed);
+        assert_eq!(report.handshake_status, PhaseStatus::Skipped);
+        assert_eq!(report.auth_status, PhaseStatus::Skipped);
+        assert_eq!(report.exec_status, PhaseStatus::Skipped);
+    }
+
+    #[test]
+    fn test_cancelled_test_stops_after_current_phase() {
+        // Accepts the TCP connection, so only the cancel flag stops the handshake
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut target = SshTarget::default();
+        target.host = "127.0.0.1".to_string();
+        target.port = listener.local_addr().unwrap().port();
+
+        let report = run_cancellable(&target, &AtomicBool::new(true));
+        assert!(!report.success);
+        assert_eq!(report.message, CANCELLED_MESSAGE);
+        assert_eq!(report.tcp_connect_status, PhaseStatus::Ok);
+        assert_eq!(report.handshake_status, PhaseStatus::Skipped);
+        assert_eq!(cancelled().message, CANCELLED_MESSAGE);
+    }
+
+    #[test]
+    fn test_unreachable_bastion_skips_the_target() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let mut target = SshTarget::default();
+        target.host = "10.0.0.9".to_string();
+        target.port = 22;
+        assert!(run_through_jump(&target).is_err());
+
+        let mut jump = JumpConfig::default();
+        jump.host = "127.0.0.1".to_string();
+        jump.port = port;
+        target.jump_host = Some(jump);
+        let report = run_through_jump(&target).unwrap();
+        assert!(!report.success);
+        assert!(report.message.starts_with("Jump host 127.0.0.1: TCP connection to 127.0.0.1 failed"));
+        assert_eq!(report.bastion.tcp_connect_status, PhaseStatus::Failed);
+        assert_eq!(report.bastion.handshake_status, PhaseStatus::Skipped);
+        assert_eq!(report.target.tcp_connect_status, PhaseStatus::Skipped);
+        assert_eq!(report.target.exec_status, PhaseStatus::Skipped);
+    }
+}
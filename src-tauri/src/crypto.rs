@@ -1,44 +1,481 @@
 use aes_gcm::{
-    aead::{Aead, KeyInit, OsRng},
+    aead::{consts::U12, Aead, KeyInit, OsRng, Payload},
     Aes256Gcm, Nonce,
 };
+use argon2::Argon2;
+use chacha20poly1305::ChaCha20Poly1305;
+use sha2::Sha256;
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use lazy_static::lazy_static;
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use zeroize::{Zeroize, Zeroizing};
 
-// 32-byte encryption key (256 bits for AES-256)
-// In production, this should be stored more securely (e.g., OS Keychain, environment variable)
-const ENCRYPTION_KEY: &[u8; 32] = b"TauriAppSecureKey2024SecretK!@#$";
+// Key that older builds compiled into the binary. Still accepted when decrypting
+// so passwords saved before the master password existed keep working.
+const LEGACY_ENCRYPTION_KEY: &[u8; 32] = b"TauriAppSecureKey2024SecretK!@#$";
 
-/// Encrypts a plaintext password using AES-256-GCM.
-/// Returns a Base64-encoded string containing the nonce (12 bytes) + ciphertext.
+// Where builds between the built-in key and the master password kept their key.
+// Only read now, to decrypt passwords saved by those builds.
+const KEYCHAIN_SERVICE: &str = "LogToolPro";
+const KEYCHAIN_ACCOUNT: &str = "server-password-key";
+
+// Leading byte of versioned payloads. Version 1 (`encrypt_with_key`) is
+// AES-256-GCM: version, nonce, ciphertext. Version 2 (`encrypt_password`) adds
+// an `Algorithm` byte after the version. Version 3 (`encrypt_password_with_aad`)
+// has the same layout as 2 but is authenticated with associated data, so it
+// only decrypts when given the same data again. Payloads without a version
+// byte (bare nonce + ciphertext) are legacy version 0, also AES-256-GCM.
+const FORMAT_V1: u8 = 1;
+const FORMAT_V2: u8 = 2;
+const FORMAT_V3: u8 = 3;
+const NONCE_LEN: usize = 12;
+const SALT_LEN: usize = 16;
+
+// Encrypted under the derived key and stored next to the salt, so a wrong
+// master password is rejected instead of silently producing garbage.
+const CHECK_PLAINTEXT: &str = "LogToolPro master key check";
+
+pub const MASTER_KEY_FILE: &str = "master_key.json";
+
+// Round-tripped by `self_test`
+const SELF_TEST_PLAINTEXT: &str = "LogToolPro crypto self-test";
+// Nonce plus AES-GCM tag: no ciphertext written by `encrypt_password` is shorter
+const MIN_CIPHERTEXT_LEN: usize = NONCE_LEN + 16;
+
+// Password-protected bundles (see `seal_with_password`)
+const BUNDLE_VERSION: u32 = 1;
+const BUNDLE_KDF: &str = "pbkdf2-sha256";
+const BUNDLE_PBKDF2_ROUNDS: u32 = 600_000;
+// Upper bound on rounds read from a bundle, so a crafted file can't stall the app
+const BUNDLE_MAX_ROUNDS: u32 = 10_000_000;
+
+// Owned key material is scrubbed from memory when dropped
+type Key = Zeroizing<[u8; 32]>;
+
+lazy_static! {
+    pub static ref MASTER_KEY: MasterKey = MasterKey::new();
+    // Key from the OS keychain, if an earlier build stored one there
+    static ref KEYCHAIN_KEY: Option<[u8; 32]> = keychain_storage_key();
+}
+
+fn keychain_storage_key() -> Option<[u8; 32]> {
+    let entry = keyring::Entry::new(KEYCHAIN_SERVICE, KEYCHAIN_ACCOUNT).ok()?;
+    match entry.get_password() {
+        Ok(encoded) => {
+            let encoded = Zeroizing::new(encoded);
+            let bytes = Zeroizing::new(BASE64.decode(encoded.trim()).ok()?);
+            bytes.as_slice().try_into().ok()
+        }
+        Err(keyring::Error::NoEntry) => None,
+        Err(e) => {
+            tracing::warn!(error = %e, "OS keychain unavailable; skipping the keychain key");
+            None
+        }
+    }
+}
+
+/// Cipher used for new encryptions under the master key. Both take a 12-byte
+/// nonce and a 256-bit key; ciphertext records which one was used, so a store
+/// can mix them while being migrated.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Algorithm {
+    #[default]
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Algorithm {
+    // The byte recorded after the version in a version 2 payload
+    fn id(self) -> u8 {
+        match self {
+            Algorithm::Aes256Gcm => 1,
+            Algorithm::ChaCha20Poly1305 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Algorithm> {
+        match id {
+            1 => Some(Algorithm::Aes256Gcm),
+            2 => Some(Algorithm::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    fn encrypt(self, key: &[u8; 32], nonce: &Nonce<U12>, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+        let payload = Payload { msg: plaintext, aad };
+        let ciphertext = match self {
+            Algorithm::Aes256Gcm => Aes256Gcm::new_from_slice(key)
+                .map_err(|e| format!("Failed to create cipher: {}", e))?
+                .encrypt(nonce, payload),
+            Algorithm::ChaCha20Poly1305 => ChaCha20Poly1305::new_from_slice(key)
+                .map_err(|e| format!("Failed to create cipher: {}", e))?
+                .encrypt(nonce, payload),
+        };
+        ciphertext.map_err(|e| format!("Encryption failed: {}", e))
+    }
+
+    fn decrypt(self, key: &[u8; 32], nonce: &Nonce<U12>, ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>, String> {
+        let payload = Payload { msg: ciphertext, aad };
+        let plaintext = match self {
+            Algorithm::Aes256Gcm => Aes256Gcm::new_from_slice(key)
+                .map_err(|e| format!("Failed to create cipher: {}", e))?
+                .decrypt(nonce, payload),
+            Algorithm::ChaCha20Poly1305 => ChaCha20Poly1305::new_from_slice(key)
+                .map_err(|e| format!("Failed to create cipher: {}", e))?
+                .decrypt(nonce, payload),
+        };
+        plaintext.map_err(|e| format!("Decryption failed: {}", e))
+    }
+}
+
+/// Salt, check value and cipher choice persisted in `master_key.json`.
+#[derive(Serialize, Deserialize)]
+pub struct MasterKeyRecord {
+    salt: String,
+    check: String,
+    // Records from before the choice existed use AES-256-GCM
+    #[serde(default)]
+    algorithm: Algorithm,
+}
+
+/// Derives a 256-bit key from the master password with Argon2id.
+fn derive_key(master_password: &str, salt: &[u8]) -> Result<Key, String> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(master_password.as_bytes(), salt, key.as_mut())
+        .map_err(|e| format!("Key derivation failed: {}", e))?;
+    Ok(key)
+}
+
+/// Picks a fresh salt for `master_password` and derives its key.
+pub fn new_master_key(master_password: &str) -> Result<(MasterKeyRecord, Key), String> {
+    if master_password.is_empty() {
+        return Err("Master password must not be empty".to_string());
+    }
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = derive_key(master_password, &salt)?;
+    let record = MasterKeyRecord {
+        salt: BASE64.encode(salt),
+        check: encrypt_with_key(&key, CHECK_PLAINTEXT)?,
+        algorithm: Algorithm::default(),
+    };
+    Ok((record, key))
+}
+
+fn read_record(path: &Path) -> Result<MasterKeyRecord, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| e.to_string())
+}
+
+fn write_record(path: &Path, record: &MasterKeyRecord) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(record).map_err(|e| e.to_string())?;
+    fs::write(path, content).map_err(|e| e.to_string())
+}
+
+/// Holds the key derived from the master password for the lifetime of the session.
+pub struct MasterKey {
+    key: Mutex<Option<Key>>,
+    algorithm: Mutex<Algorithm>,
+}
+
+impl MasterKey {
+    pub fn new() -> Self {
+        Self {
+            key: Mutex::new(None),
+            algorithm: Mutex::new(Algorithm::default()),
+        }
+    }
+
+    /// The cipher new encryptions use, as recorded in the key file.
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm.lock().map(|a| *a).unwrap_or_default()
+    }
+
+    /// Makes `algorithm` the cipher for new encryptions and records it in
+    /// `path`. Existing ciphertext keeps decrypting; `rotate` re-encrypts it.
+    pub fn set_algorithm(&self, path: &Path, algorithm: Algorithm) -> Result<(), String> {
+        self.key()?;
+        let mut record = read_record(path)?;
+        record.algorithm = algorithm;
+        write_record(path, &record)?;
+        *self.algorithm.lock().map_err(|_| "Lock failed")? = algorithm;
+        Ok(())
+    }
+
+    /// Derives the key from `master_password` and the salt in `path`.
+    /// The first unlock creates the salt, which sets the master password.
+    pub fn unlock(&self, path: &Path, master_password: &str) -> Result<(), String> {
+        let (key, algorithm) = if path.exists() {
+            let record = read_record(path)?;
+            let salt = BASE64
+                .decode(&record.salt)
+                .map_err(|e| format!("Invalid salt: {}", e))?;
+            let key = derive_key(master_password, &salt)?;
+            if decrypt_with_key(&key, &record.check).as_deref() != Ok(CHECK_PLAINTEXT) {
+                return Err("Incorrect master password".to_string());
+            }
+            (key, record.algorithm)
+        } else {
+            let (record, key) = new_master_key(master_password)?;
+            write_record(path, &record)?;
+            (key, record.algorithm)
+        };
+        self.set(key);
+        if let Ok(mut slot) = self.algorithm.lock() {
+            *slot = algorithm;
+        }
+        Ok(())
+    }
+
+    /// Scrubs the derived key from memory; storage calls fail until the next unlock.
+    pub fn lock(&self) {
+        if let Ok(mut slot) = self.key.lock() {
+            *slot = None;
+        }
+    }
+
+    fn set(&self, key: Key) {
+        if let Ok(mut slot) = self.key.lock() {
+            *slot = Some(key);
+        }
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.key.lock().map(|k| k.is_some()).unwrap_or(false)
+    }
+
+    fn key(&self) -> Result<Key, String> {
+        self.key
+            .lock()
+            .map_err(|_| "Lock failed")?
+            .clone()
+            .ok_or_else(|| "locked".to_string())
+    }
+
+    /// Switches to a new salt (and optionally a new master password). `persist`
+    /// receives the new key and must re-encrypt and save everything; if it fails
+    /// the previous salt file is restored and the old key stays active.
+    pub fn rotate(
+        &self,
+        path: &Path,
+        master_password: &str,
+        persist: impl FnOnce(&[u8; 32]) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.key()?;
+        let (mut record, key) = new_master_key(master_password)?;
+        record.algorithm = self.algorithm();
+        let previous = fs::read(path).ok();
+        write_record(path, &record)?;
+        if let Err(e) = persist(&key) {
+            match previous {
+                Some(content) => {
+                    let _ = fs::write(path, content);
+                }
+                None => {
+                    let _ = fs::remove_file(path);
+                }
+            }
+            return Err(e);
+        }
+        self.set(key);
+        Ok(())
+    }
+}
+
+/// Fails with "locked" until the master password has been entered.
+pub fn ensure_unlocked() -> Result<(), String> {
+    MASTER_KEY.key().map(|_| ())
+}
+
+/// Encrypts a plaintext password under the master key with the cipher chosen
+/// for the store. Returns a Base64-encoded string containing the format
+/// version (1 byte), the algorithm (1 byte), the nonce (12 bytes) and the
+/// ciphertext.
 pub fn encrypt_password(plaintext: &str) -> Result<String, String> {
     if plaintext.is_empty() {
         return Ok(String::new());
     }
+    let key = MASTER_KEY.key()?;
+    encrypt_with_algorithm(&key, MASTER_KEY.algorithm(), plaintext)
+}
+
+/// Encrypts with AES-256-GCM in the version 1 format, which every build
+/// since versioning can read (export bundles, the key check value).
+pub fn encrypt_with_key(key: &[u8; 32], plaintext: &str) -> Result<String, String> {
+    seal_payload(key, Algorithm::Aes256Gcm, &[FORMAT_V1], plaintext, b"")
+}
+
+/// Encrypts with `algorithm`, recording it in a version 2 header.
+pub fn encrypt_with_algorithm(key: &[u8; 32], algorithm: Algorithm, plaintext: &str) -> Result<String, String> {
+    seal_payload(key, algorithm, &[FORMAT_V2, algorithm.id()], plaintext, b"")
+}
+
+/// Like `encrypt_password`, but bound to `aad` (a server id): the result only
+/// decrypts through `decrypt_password_with_aad` with the same `aad`, so it
+/// can't be copied onto another record.
+pub fn encrypt_password_with_aad(plaintext: &str, aad: &str) -> Result<String, String> {
+    if plaintext.is_empty() {
+        return Ok(String::new());
+    }
+    let key = MASTER_KEY.key()?;
+    encrypt_with_aad(&key, MASTER_KEY.algorithm(), plaintext, aad)
+}
 
-    let cipher = Aes256Gcm::new_from_slice(ENCRYPTION_KEY)
-        .map_err(|e| format!("Failed to create cipher: {}", e))?;
+/// Encrypts with `algorithm` and associated data `aad` in the version 3 format.
+pub fn encrypt_with_aad(key: &[u8; 32], algorithm: Algorithm, plaintext: &str, aad: &str) -> Result<String, String> {
+    seal_payload(key, algorithm, &[FORMAT_V3, algorithm.id()], plaintext, aad.as_bytes())
+}
+
+// `header`, a random nonce and the ciphertext, Base64-encoded
+fn seal_payload(
+    key: &[u8; 32],
+    algorithm: Algorithm,
+    header: &[u8],
+    plaintext: &str,
+    aad: &[u8],
+) -> Result<String, String> {
+    if plaintext.is_empty() {
+        return Ok(String::new());
+    }
 
     // Generate a random 12-byte nonce
-    let mut nonce_bytes = [0u8; 12];
+    let mut nonce_bytes = [0u8; NONCE_LEN];
     OsRng.fill_bytes(&mut nonce_bytes);
     let nonce = Nonce::from_slice(&nonce_bytes);
 
-    // Encrypt the plaintext
-    let ciphertext = cipher
-        .encrypt(nonce, plaintext.as_bytes())
-        .map_err(|e| format!("Encryption failed: {}", e))?;
+    let ciphertext = algorithm.encrypt(key, nonce, plaintext.as_bytes(), aad)?;
 
-    // Combine nonce + ciphertext and encode as Base64
-    let mut combined = Vec::with_capacity(12 + ciphertext.len());
+    // Combine header + nonce + ciphertext and encode as Base64
+    let mut combined = Vec::with_capacity(header.len() + NONCE_LEN + ciphertext.len());
+    combined.extend_from_slice(header);
     combined.extend_from_slice(&nonce_bytes);
     combined.extend_from_slice(&ciphertext);
 
     Ok(BASE64.encode(&combined))
 }
 
-/// Decrypts a Base64-encoded ciphertext (nonce + encrypted data) back to plaintext.
+/// Envelope for data encrypted under a user-supplied password rather than the
+/// master key, so it can be opened on another machine.
+#[derive(Serialize, Deserialize)]
+struct PasswordBundle {
+    version: u32,
+    kdf: String,
+    iterations: u32,
+    salt: String,
+    data: String,
+}
+
+fn bundle_key(password: &str, salt: &[u8], rounds: u32) -> Key {
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, rounds, key.as_mut());
+    key
+}
+
+/// Encrypts `plaintext` under a PBKDF2 key derived from `password` and returns
+/// the bundle as JSON.
+pub fn seal_with_password(plaintext: &str, password: &str) -> Result<String, String> {
+    seal_with_rounds(plaintext, password, BUNDLE_PBKDF2_ROUNDS)
+}
+
+fn seal_with_rounds(plaintext: &str, password: &str, rounds: u32) -> Result<String, String> {
+    if password.is_empty() {
+        return Err("Export password must not be empty".to_string());
+    }
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+    let key = bundle_key(password, &salt, rounds);
+    let bundle = PasswordBundle {
+        version: BUNDLE_VERSION,
+        kdf: BUNDLE_KDF.to_string(),
+        iterations: rounds,
+        salt: BASE64.encode(salt),
+        data: encrypt_with_key(&key, plaintext)?,
+    };
+    serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())
+}
+
+/// Opens a bundle produced by `seal_with_password`.
+pub fn open_with_password(bundle: &str, password: &str) -> Result<Zeroizing<String>, String> {
+    let bundle: PasswordBundle =
+        serde_json::from_str(bundle).map_err(|e| format!("Invalid bundle: {}", e))?;
+    if bundle.version != BUNDLE_VERSION || bundle.kdf != BUNDLE_KDF {
+        return Err(format!(
+            "Unsupported bundle format: version {} ({})",
+            bundle.version, bundle.kdf
+        ));
+    }
+    if bundle.iterations == 0 || bundle.iterations > BUNDLE_MAX_ROUNDS {
+        return Err("Invalid bundle: bad iteration count".to_string());
+    }
+    let salt = BASE64
+        .decode(&bundle.salt)
+        .map_err(|e| format!("Invalid salt: {}", e))?;
+    let key = bundle_key(password, &salt, bundle.iterations);
+    decrypt_with_key(&key, &bundle.data)
+        .map(Zeroizing::new)
+        .map_err(|_| "Incorrect password or corrupted bundle".to_string())
+}
+
+/// Decrypts a Base64-encoded payload produced by `encrypt_password` back to plaintext.
+/// Ciphertext written under the keychain key or the legacy built-in key is still accepted.
 pub fn decrypt_password(ciphertext_b64: &str) -> Result<String, String> {
+    decrypt_password_with_aad(ciphertext_b64, "")
+}
+
+/// Decrypts a payload from `encrypt_password_with_aad`, which must be given the
+/// same `aad`. Unbound payloads from older builds still decrypt, ignoring `aad`.
+pub fn decrypt_password_with_aad(ciphertext_b64: &str, aad: &str) -> Result<String, String> {
+    let key = MASTER_KEY.key()?;
+    decrypt_with_aad(&key, ciphertext_b64, aad).or_else(|e| {
+        KEYCHAIN_KEY
+            .iter()
+            .chain(std::iter::once(LEGACY_ENCRYPTION_KEY))
+            .find_map(|k| decrypt_with_aad(k, ciphertext_b64, aad).ok())
+            .ok_or(e)
+    })
+}
+
+/// Whether `value` was written by `encrypt_password_with_aad`. Anything else
+/// still needs binding to its record.
+pub fn is_bound(value: &str) -> bool {
+    BASE64
+        .decode(value)
+        .map(|bytes| bytes.len() >= 2 + MIN_CIPHERTEXT_LEN && bytes[0] == FORMAT_V3)
+        .unwrap_or(false)
+}
+
+/// Encrypts and decrypts a known sentinel under the master key, to catch a
+/// broken cipher setup before anything is written with it.
+pub fn self_test() -> Result<(), String> {
+    let encrypted = encrypt_password(SELF_TEST_PLAINTEXT)?;
+    let decrypted = Zeroizing::new(decrypt_password(&encrypted)?);
+    if decrypted.as_str() != SELF_TEST_PLAINTEXT {
+        return Err("Self-test ciphertext decrypted to the wrong value".to_string());
+    }
+    Ok(())
+}
+
+/// Whether `value` has the shape of `encrypt_password` output. Legacy
+/// plaintext passwords almost never do, and are skipped by the self-test.
+pub fn looks_encrypted(value: &str) -> bool {
+    BASE64
+        .decode(value)
+        .map(|bytes| bytes.len() >= MIN_CIPHERTEXT_LEN)
+        .unwrap_or(false)
+}
+
+pub fn decrypt_with_key(key: &[u8; 32], ciphertext_b64: &str) -> Result<String, String> {
+    decrypt_with_aad(key, ciphertext_b64, "")
+}
+
+/// Decrypts any payload format; `aad` is only used by version 3 payloads.
+pub fn decrypt_with_aad(key: &[u8; 32], ciphertext_b64: &str, aad: &str) -> Result<String, String> {
     if ciphertext_b64.is_empty() {
         return Ok(String::new());
     }
@@ -47,29 +484,67 @@ pub fn decrypt_password(ciphertext_b64: &str) -> Result<String, String> {
         .decode(ciphertext_b64)
         .map_err(|e| format!("Base64 decode failed: {}", e))?;
 
-    if combined.len() < 12 {
+    // A v0 nonce can start with the v1 marker byte by chance, so fall back to
+    // reading the payload as v0 when the versioned read does not authenticate.
+    let versioned = match combined.split_first() {
+        Some((&FORMAT_V1, payload)) => decrypt_payload(key, Algorithm::Aes256Gcm, payload, b""),
+        Some((&version @ (FORMAT_V2 | FORMAT_V3), rest)) => {
+            let aad = if version == FORMAT_V3 { aad.as_bytes() } else { b"" };
+            match rest.split_first() {
+                Some((&id, payload)) => Algorithm::from_id(id)
+                    .ok_or_else(|| format!("Unsupported cipher id {}", id))
+                    .and_then(|algorithm| decrypt_payload(key, algorithm, payload, aad)),
+                None => Err("Invalid ciphertext: too short".to_string()),
+            }
+        }
+        _ => return decrypt_payload(key, Algorithm::Aes256Gcm, &combined, b""),
+    };
+    versioned.or_else(|e| decrypt_payload(key, Algorithm::Aes256Gcm, &combined, b"").map_err(|_| e))
+}
+
+// Decrypts nonce (12 bytes) + ciphertext; a version 0 payload is exactly that
+fn decrypt_payload(key: &[u8; 32], algorithm: Algorithm, combined: &[u8], aad: &[u8]) -> Result<String, String> {
+    if combined.len() < NONCE_LEN {
         return Err("Invalid ciphertext: too short".to_string());
     }
 
-    let (nonce_bytes, ciphertext) = combined.split_at(12);
+    let (nonce_bytes, ciphertext) = combined.split_at(NONCE_LEN);
     let nonce = Nonce::from_slice(nonce_bytes);
 
-    let cipher = Aes256Gcm::new_from_slice(ENCRYPTION_KEY)
-        .map_err(|e| format!("Failed to create cipher: {}", e))?;
-
-    let plaintext_bytes = cipher
-        .decrypt(nonce, ciphertext)
-        .map_err(|e| format!("Decryption failed: {}", e))?;
+    let plaintext_bytes = algorithm.decrypt(key, nonce, ciphertext, aad)?;
 
-    String::from_utf8(plaintext_bytes).map_err(|e| format!("UTF-8 decode failed: {}", e))
+    String::from_utf8(plaintext_bytes).map_err(|e| {
+        let message = format!("UTF-8 decode failed: {}", e);
+        e.into_bytes().zeroize();
+        message
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Tests share the global master key, so they only ever unlock it with a fixed key
+    fn unlocked() {
+        MASTER_KEY.set(Zeroizing::new([42u8; 32]));
+    }
+
+    fn temp_key_file() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("master_key_{}.json", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn test_self_test_and_ciphertext_shape() {
+        unlocked();
+        assert_eq!(self_test(), Ok(()));
+        assert!(looks_encrypted(&encrypt_password("x").unwrap()));
+        assert!(!looks_encrypted("hunter2"));
+        assert!(!looks_encrypted(""));
+    }
+
     #[test]
     fn test_encrypt_decrypt_roundtrip() {
+        unlocked();
         let original = "my_secret_password_123!";
         let encrypted = encrypt_password(original).expect("Encryption should succeed");
         
@@ -83,6 +558,7 @@ mod tests {
 
     #[test]
     fn test_empty_password() {
+        unlocked();
         let encrypted = encrypt_password("").expect("Should handle empty");
         assert_eq!(encrypted, "");
         
@@ -92,9 +568,150 @@ mod tests {
 
     #[test]
     fn test_unicode_password() {
+        unlocked();
         let original = "密码测试 Пароль 🔐";
         let encrypted = encrypt_password(original).expect("Should handle unicode");
         let decrypted = decrypt_password(&encrypted).expect("Should decrypt unicode");
         assert_eq!(decrypted, original);
     }
+
+    // Strips the version byte, giving the bare payload older builds wrote
+    fn as_v0(encrypted: &str) -> String {
+        BASE64.encode(&BASE64.decode(encrypted).unwrap()[1..])
+    }
+
+    #[test]
+    fn test_payload_is_versioned() {
+        unlocked();
+        let encrypted = encrypt_password("secret").expect("Should encrypt");
+        assert_eq!(BASE64.decode(&encrypted).unwrap()[..2], [FORMAT_V2, Algorithm::Aes256Gcm.id()]);
+        let encrypted = encrypt_with_key(&[3u8; 32], "secret").expect("Should encrypt");
+        assert_eq!(BASE64.decode(&encrypted).unwrap()[0], FORMAT_V1);
+    }
+
+    #[test]
+    fn test_algorithms_recorded_in_header() {
+        let key = [5u8; 32];
+        let aes = encrypt_with_algorithm(&key, Algorithm::Aes256Gcm, "secret").unwrap();
+        let chacha = encrypt_with_algorithm(&key, Algorithm::ChaCha20Poly1305, "secret").unwrap();
+        assert_eq!(BASE64.decode(&chacha).unwrap()[..2], [FORMAT_V2, 2]);
+        // Each decrypts by its own header, so a store can hold both
+        assert_eq!(decrypt_with_key(&key, &aes).unwrap(), "secret");
+        assert_eq!(decrypt_with_key(&key, &chacha).unwrap(), "secret");
+        assert!(decrypt_with_key(&[6u8; 32], &chacha).is_err());
+
+        let mut unknown = BASE64.decode(&chacha).unwrap();
+        unknown[1] = 9;
+        assert!(decrypt_with_key(&key, &BASE64.encode(unknown)).is_err());
+    }
+
+    #[test]
+    fn test_unversioned_payload_still_decrypts() {
+        let key = [3u8; 32];
+        let encrypted = encrypt_with_key(&key, "secret").expect("Should encrypt");
+        assert_eq!(decrypt_with_key(&key, &as_v0(&encrypted)).unwrap(), "secret");
+    }
+
+    #[test]
+    fn test_legacy_ciphertext_still_decrypts() {
+        unlocked();
+        let original = "saved_by_an_older_build";
+        let legacy = as_v0(&encrypt_with_key(LEGACY_ENCRYPTION_KEY, original).expect("Should encrypt"));
+        let decrypted = decrypt_password(&legacy).expect("Legacy ciphertext should decrypt");
+        assert_eq!(decrypted, original);
+    }
+
+    #[test]
+    fn test_wrong_key_fails() {
+        let encrypted = encrypt_with_key(&[7u8; 32], "secret").expect("Should encrypt");
+        assert!(decrypt_with_key(&[8u8; 32], &encrypted).is_err());
+    }
+
+    #[test]
+    fn test_aad_binds_ciphertext_to_record() {
+        let key = [5u8; 32];
+        let bound = encrypt_with_aad(&key, Algorithm::ChaCha20Poly1305, "secret", "server-1").unwrap();
+        assert!(is_bound(&bound));
+        assert_eq!(decrypt_with_aad(&key, &bound, "server-1").unwrap(), "secret");
+        // Copied onto another record, or read without the id, it no longer decrypts
+        assert!(decrypt_with_aad(&key, &bound, "server-2").is_err());
+        assert!(decrypt_with_key(&key, &bound).is_err());
+
+        // Unbound payloads from before still decrypt whatever id is given
+        let legacy = encrypt_with_algorithm(&key, Algorithm::Aes256Gcm, "secret").unwrap();
+        assert!(!is_bound(&legacy));
+        assert_eq!(decrypt_with_aad(&key, &legacy, "server-2").unwrap(), "secret");
+        assert!(!is_bound("hunter2"));
+    }
+
+    #[test]
+    fn test_algorithm_choice_is_persisted() {
+        let path = temp_key_file();
+        let vault = MasterKey::new();
+        assert_eq!(vault.set_algorithm(&path, Algorithm::ChaCha20Poly1305), Err("locked".to_string()));
+        vault.unlock(&path, "pw").unwrap();
+        assert_eq!(vault.algorithm(), Algorithm::Aes256Gcm);
+        vault.set_algorithm(&path, Algorithm::ChaCha20Poly1305).unwrap();
+
+        // Kept across unlocks and key rotation
+        let reopened = MasterKey::new();
+        reopened.unlock(&path, "pw").unwrap();
+        assert_eq!(reopened.algorithm(), Algorithm::ChaCha20Poly1305);
+        reopened.rotate(&path, "pw", |_| Ok(())).unwrap();
+        assert_eq!(read_record(&path).unwrap().algorithm, Algorithm::ChaCha20Poly1305);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_unlock_sets_and_checks_master_password() {
+        let path = temp_key_file();
+        let vault = MasterKey::new();
+        assert_eq!(vault.key(), Err("locked".to_string()));
+
+        vault.unlock(&path, "correct horse").expect("First unlock sets the password");
+        let key = vault.key().unwrap();
+
+        vault.lock();
+        assert!(!vault.is_unlocked());
+        assert_eq!(
+            vault.unlock(&path, "wrong"),
+            Err("Incorrect master password".to_string())
+        );
+        assert!(!vault.is_unlocked());
+
+        vault.unlock(&path, "correct horse").unwrap();
+        assert_eq!(vault.key().unwrap(), key);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_failed_rotation_keeps_old_key() {
+        let path = temp_key_file();
+        let vault = MasterKey::new();
+        vault.unlock(&path, "first").unwrap();
+        let key = vault.key().unwrap();
+
+        let result = vault.rotate(&path, "second", |_| Err("disk full".to_string()));
+        assert_eq!(result, Err("disk full".to_string()));
+        assert_eq!(vault.key().unwrap(), key);
+        vault.lock();
+        vault.unlock(&path, "first").expect("Old salt file should be restored");
+
+        vault.rotate(&path, "second", |_| Ok(())).unwrap();
+        assert_ne!(vault.key().unwrap(), key);
+        vault.lock();
+        vault.unlock(&path, "second").unwrap();
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_password_bundle_roundtrip() {
+        let bundle = seal_with_rounds("[{\"id\":\"1\"}]", "export-pw", 1000).unwrap();
+        assert_eq!(open_with_password(&bundle, "export-pw").unwrap().as_str(), "[{\"id\":\"1\"}]");
+        assert_eq!(
+            open_with_password(&bundle, "other").map(|_| ()),
+            Err("Incorrect password or corrupted bundle".to_string())
+        );
+        assert!(seal_with_rounds("x", "", 1000).is_err());
+    }
 }
@@ -0,0 +1,158 @@
+use md5::Md5;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::time::Duration;
+
+use crate::connection_pool::CONNECTION_POOL;
+use crate::run_remote;
+use crate::shell::shell_quote;
+use crate::ssh_connect::SshTarget;
+
+/// Returned by `remote_hash` when the server has neither the coreutils tool
+/// nor `openssl`.
+pub const HASH_UNAVAILABLE: &str = "unavailable";
+// Printed by the remote command when the file can't be read
+const UNREADABLE: &str = "unreadable";
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HashAlgo {
+    Sha256,
+    Md5,
+}
+
+impl HashAlgo {
+    // The coreutils tool, and the `openssl dgst` flag used when it's missing
+    fn tools(self) -> (&'static str, &'static str) {
+        match self {
+            HashAlgo::Sha256 => ("sha256sum", "-sha256"),
+            HashAlgo::Md5 => ("md5sum", "-md5"),
+        }
+    }
+
+    fn hex_len(self) -> usize {
+        match self {
+            HashAlgo::Sha256 => 64,
+            HashAlgo::Md5 => 32,
+        }
+    }
+}
+
+// Hashes `path` with the coreutils tool, or `openssl dgst` without it. Both
+// print the digest first (`openssl -r` mimics the coreutils layout).
+fn build_hash_command(path: &str, algo: HashAlgo) -> String {
+    let (tool, openssl_flag) = algo.tools();
+    let path = shell_quote(path);
+    format!(
+        "if [ ! -r {path} ]; then echo {UNREADABLE}; \
+elif command -v {tool} >/dev/null 2>&1; then {tool} -- {path}; \
+elif command -v openssl >/dev/null 2>&1; then openssl dgst {openssl_flag} -r {path}; \
+else echo {HASH_UNAVAILABLE}; fi"
+    )
+}
+
+fn parse_hash_output(output: &str, path: &str, algo: HashAlgo) -> Result<String, String> {
+    let first = output.split_whitespace().next().unwrap_or_default();
+    match first {
+        UNREADABLE => Err(format!("Cannot read {}", path)),
+        HASH_UNAVAILABLE => Ok(HASH_UNAVAILABLE.to_string()),
+        digest if digest.len() == algo.hex_len() && digest.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            Ok(digest.to_ascii_lowercase())
+        }
+        _ => Err(format!("Unexpected hash output for {}: {}", path, output.trim())),
+    }
+}
+
+/// The hex digest of `path` on `target`, or `HASH_UNAVAILABLE`.
+pub fn remote_hash(target: &SshTarget, path: &str, algo: HashAlgo) -> Result<String, String> {
+    let command = build_hash_command(path, algo);
+    let output = CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| run_remote(sess, &command))?;
+    parse_hash_output(&output, path, algo)
+}
+
+fn hash_reader(mut reader: impl Read, mut digest: impl Digest) -> io::Result<String> {
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        digest.update(&buffer[..n]);
+    }
+    Ok(digest.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// The hex digest of the local file at `path`.
+pub fn local_hash(path: &str, algo: HashAlgo) -> Result<String, String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+    match algo {
+        HashAlgo::Sha256 => hash_reader(file, Sha256::new()),
+        HashAlgo::Md5 => hash_reader(file, Md5::new()),
+    }
+    .map_err(|e| format!("Failed to read {}: {}", path, e))
+}
+
+/// Checks a finished download against the remote original. A mismatching
+/// local copy is deleted. A log still being written will differ by the time
+/// it is hashed, so only rotated or closed files verify reliably.
+pub fn verify_download(target: &SshTarget, remote_path: &str, local_path: &str, algo: HashAlgo) -> Result<(), String> {
+    let remote = remote_hash(target, remote_path, algo)?;
+    if remote == HASH_UNAVAILABLE {
+        return Err(format!(
+            "Downloaded {} but could not verify it: the server has no {} or openssl",
+            local_path,
+            algo.tools().0
+        ));
+    }
+    let local = local_hash(local_path, algo)?;
+    if local != remote {
+        let _ = fs::remove_file(local_path);
+        return Err(format!(
+            "Checksum mismatch for {}: remote {}, downloaded {}; the download was removed",
+            remote_path, remote, local
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_command_and_output() {
+        assert_eq!(
+            build_hash_command("/logs/a b.log", HashAlgo::Md5),
+            "if [ ! -r '/logs/a b.log' ]; then echo unreadable; \
+elif command -v md5sum >/dev/null 2>&1; then md5sum -- '/logs/a b.log'; \
+elif command -v openssl >/dev/null 2>&1; then openssl dgst -md5 -r '/logs/a b.log'; \
+else echo unavailable; fi"
+        );
+
+        let md5 = "D41D8CD98F00B204E9800998ECF8427E";
+        assert_eq!(
+            parse_hash_output(&format!("{}  /logs/a.log\n", md5), "/logs/a.log", HashAlgo::Md5).unwrap(),
+            md5.to_ascii_lowercase()
+        );
+        // openssl's `-r` layout, and the fallbacks
+        assert!(parse_hash_output(&format!("{} */logs/a.log\n", md5), "/logs/a.log", HashAlgo::Md5).is_ok());
+        assert_eq!(parse_hash_output("unavailable\n", "/x", HashAlgo::Sha256).unwrap(), HASH_UNAVAILABLE);
+        assert_eq!(parse_hash_output("unreadable\n", "/x", HashAlgo::Sha256), Err("Cannot read /x".to_string()));
+        assert!(parse_hash_output(&format!("{}  /x\n", md5), "/x", HashAlgo::Sha256).is_err());
+    }
+
+    #[test]
+    fn test_local_hash() {
+        let path = std::env::temp_dir().join(format!("hash_{}.log", uuid::Uuid::new_v4()));
+        fs::write(&path, "abc").unwrap();
+        let path = path.to_string_lossy().to_string();
+        assert_eq!(
+            local_hash(&path, HashAlgo::Sha256).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(local_hash(&path, HashAlgo::Md5).unwrap(), "900150983cd24fb0d6963f7d28e17f72");
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,74 @@
+use serde::Serialize;
+use std::time::Instant;
+
+use crate::ssh_connect::{self, SshTarget};
+
+/// Connect timeout for a probe when the server doesn't set a shorter one.
+pub const HEALTH_CHECK_TIMEOUT_SECS: u64 = 3;
+/// Probes run at the same time by `check_servers_health`.
+pub const HEALTH_CHECK_PARALLEL: usize = 16;
+
+#[derive(Clone, Serialize)]
+pub struct ServerHealth {
+    pub id: String,
+    pub reachable: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Opens a TCP connection to the server's SSH port (through its jump host if it
+/// has one) and reports how long that took. No handshake or login is attempted,
+/// so a reachable server may still reject the credentials.
+pub fn probe(id: String, mut target: SshTarget) -> ServerHealth {
+    let timeout = target
+        .connect_timeout_secs
+        .filter(|&secs| secs > 0)
+        .map_or(HEALTH_CHECK_TIMEOUT_SECS, |secs| secs.min(HEALTH_CHECK_TIMEOUT_SECS));
+    target.connect_timeout_secs = Some(timeout);
+
+    let started = Instant::now();
+    match ssh_connect::open_stream(&target) {
+        Ok(_stream) => ServerHealth {
+            id,
+            reachable: true,
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+            error: None,
+        },
+        Err(e) => ServerHealth {
+            id,
+            reachable: false,
+            latency_ms: None,
+            error: Some(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    fn local_target(port: u16) -> SshTarget {
+        let mut target = SshTarget::default();
+        target.host = "127.0.0.1".to_string();
+        target.port = port;
+        target
+    }
+
+    #[test]
+    fn test_probe_open_and_closed_ports() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let open_port = listener.local_addr().unwrap().port();
+        let health = probe("up".to_string(), local_target(open_port));
+        assert!(health.reachable);
+        assert!(health.latency_ms.is_some());
+        assert!(health.error.is_none());
+
+        drop(listener);
+        let health = probe("down".to_string(), local_target(open_port));
+        assert_eq!(health.id, "down");
+        assert!(!health.reachable);
+        assert!(health.latency_ms.is_none());
+        assert!(health.error.is_some());
+    }
+}
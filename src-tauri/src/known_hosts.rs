@@ -0,0 +1,132 @@
+use base64::{engine::general_purpose::STANDARD_NO_PAD as BASE64_NO_PAD, Engine};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use ssh2::{HashType, HostKeyType, Session};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct KnownHost {
+    pub host: String,
+    pub port: u16,
+    pub key_type: String,
+    pub fingerprint: String,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct KnownHostsStore {
+    hosts: Vec<KnownHost>,
+}
+
+// Emitted when a server presents a host key we have never seen,
+// so the frontend can ask the user whether to trust it.
+#[derive(Clone, Serialize)]
+pub struct HostKeyUnknown {
+    pub host: String,
+    pub port: u16,
+    pub key_type: String,
+    pub fingerprint: String,
+}
+
+lazy_static! {
+    pub static ref KNOWN_HOSTS: KnownHostsManager = KnownHostsManager::new();
+}
+
+pub struct KnownHostsManager {
+    path: Mutex<Option<PathBuf>>,
+    app_handle: Mutex<Option<AppHandle>>,
+}
+
+impl KnownHostsManager {
+    pub fn new() -> Self {
+        Self {
+            path: Mutex::new(None),
+            app_handle: Mutex::new(None),
+        }
+    }
+
+    /// Points the manager at `known_hosts.json` and keeps the handle used for prompts.
+    pub fn init(&self, app_handle: AppHandle, path: PathBuf) {
+        *self.path.lock().unwrap() = Some(path);
+        *self.app_handle.lock().unwrap() = Some(app_handle);
+    }
+
+    fn load(&self) -> Result<(PathBuf, KnownHostsStore), String> {
+        let path = self
+            .path
+            .lock()
+            .map_err(|_| "Lock failed")?
+            .clone()
+            .ok_or("Known hosts store is not initialized")?;
+        if !path.exists() {
+            return Ok((path, KnownHostsStore::default()));
+        }
+        let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        let store = serde_json::from_str(&content).map_err(|e| e.to_string())?;
+        Ok((path, store))
+    }
+
+    /// Checks the session's host key against the stored entry for `host:port`.
+    /// Unknown keys are reported to the frontend and rejected until trusted.
+    pub fn verify(&self, sess: &Session, host: &str, port: u16) -> Result<(), String> {
+        let (key_type, fingerprint) = host_key_fingerprint(sess)?;
+        let (_, store) = self.load()?;
+
+        match store.hosts.iter().find(|h| h.host == host && h.port == port) {
+            Some(known) if known.fingerprint == fingerprint => Ok(()),
+            Some(known) => Err(format!(
+                "Host key mismatch for {}:{}! Expected {} {}, but the server presented {} {}. \
+                 This may be a man-in-the-middle attack.",
+                host, port, known.key_type, known.fingerprint, key_type, fingerprint
+            )),
+            None => {
+                if let Some(app_handle) = self.app_handle.lock().map_err(|_| "Lock failed")?.as_ref() {
+                    let _ = app_handle.emit(
+                        "host-key-unknown",
+                        HostKeyUnknown {
+                            host: host.to_string(),
+                            port,
+                            key_type: key_type.clone(),
+                            fingerprint: fingerprint.clone(),
+                        },
+                    );
+                }
+                Err(format!(
+                    "Host key for {}:{} is not trusted yet ({} {})",
+                    host, port, key_type, fingerprint
+                ))
+            }
+        }
+    }
+
+    /// Persists an accepted fingerprint, replacing any previous entry for `host:port`.
+    pub fn trust(&self, entry: KnownHost) -> Result<(), String> {
+        let (path, mut store) = self.load()?;
+        store
+            .hosts
+            .retain(|h| !(h.host == entry.host && h.port == entry.port));
+        store.hosts.push(entry);
+        let content = serde_json::to_string_pretty(&store).map_err(|e| e.to_string())?;
+        fs::write(&path, content).map_err(|e| e.to_string())
+    }
+}
+
+/// Returns the host key type and its OpenSSH-style `SHA256:` fingerprint.
+pub fn host_key_fingerprint(sess: &Session) -> Result<(String, String), String> {
+    let (_, key_type) = sess.host_key().ok_or("Server did not present a host key")?;
+    let hash = sess
+        .host_key_hash(HashType::Sha256)
+        .ok_or("Failed to hash host key")?;
+    let key_type = match key_type {
+        HostKeyType::Rsa => "ssh-rsa",
+        HostKeyType::Dss => "ssh-dss",
+        HostKeyType::Ecdsa256 => "ecdsa-sha2-nistp256",
+        HostKeyType::Ecdsa384 => "ecdsa-sha2-nistp384",
+        HostKeyType::Ecdsa521 => "ecdsa-sha2-nistp521",
+        HostKeyType::Ed25519 => "ssh-ed25519",
+        HostKeyType::Unknown => "unknown",
+    };
+    Ok((key_type.to_string(), format!("SHA256:{}", BASE64_NO_PAD.encode(hash))))
+}
@@ -1,16 +1,50 @@
 // Learn more about Tauri commands at https://tauri.app/develop/calling-rust/
 mod ssh_session;
+mod log_follow;
+mod ssh_connect;
+mod known_hosts;
+mod connection_pool;
+mod shell;
+mod sftp;
+mod recording;
+mod chain_export;
 mod crypto;
+mod remote_info;
+mod remote_process;
+mod command_stream;
+mod health;
+mod connection_test;
+mod port_forward;
+mod ssh_limit;
+mod app_log;
+mod log_diff;
+mod log_time;
+mod file_hash;
 
 use serde::{Deserialize, Serialize};
+use command_stream::COMMAND_MANAGER;
+use connection_pool::CONNECTION_POOL;
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use log_follow::FOLLOW_MANAGER;
+use port_forward::PORT_FORWARD_MANAGER;
+use known_hosts::{KnownHost, KNOWN_HOSTS};
+use shell::shell_quote;
+use ssh_limit::SSH_LIMITER;
 use ssh2::Session;
-use ssh_session::SESSION_MANAGER;
+use ssh_connect::{AuthMethod, JumpConfig, RetryPolicy, SshTarget};
+use ssh_session::{PtyOptions, SESSION_MANAGER};
 use std::fs;
 use std::io::{Read, Write};
-use std::net::TcpStream;
-use std::path::PathBuf;
-use sysinfo::System;
-use tauri::Manager;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use sysinfo::{Disks, Networks, System};
+use zeroize::{Zeroize, Zeroizing};
+use tauri::{Emitter, Manager};
+use tokio::sync::Semaphore;
 
 #[derive(Serialize)]
 pub struct SystemInfo {
@@ -18,6 +52,18 @@ pub struct SystemInfo {
     memory_used_gb: f64,
     memory_total_gb: f64,
     memory_usage_percent: f32,
+    per_core: Vec<f32>,
+    // `None` where the platform (or a remote probe) can't provide them
+    disks: Option<Vec<DiskUsage>>,
+    net_rx_bytes_per_sec: Option<f64>,
+    net_tx_bytes_per_sec: Option<f64>,
+}
+
+#[derive(Serialize)]
+pub struct DiskUsage {
+    mount: String,
+    total_gb: f64,
+    available_gb: f64,
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -31,146 +77,1193 @@ pub struct ServerConfig {
     #[serde(default)]
     pub environment: String,
     pub status: String,
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub auth_method: Option<AuthMethod>,
+    #[serde(default)]
+    pub jump_host: Option<JumpConfig>,
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+    // Folder shown in the server list; ungrouped servers come first
+    #[serde(default)]
+    pub group: Option<String>,
+    // Position within the group, rewritten by `reorder_servers`
+    #[serde(default)]
+    pub sort_index: i32,
+    // Searched when a search or trace is started with an empty log path
+    #[serde(default)]
+    pub default_log_path: Option<String>,
+    // Compress the SSH stream; worth it over slow WAN links, not on a LAN
+    #[serde(default)]
+    pub use_compression: bool,
+}
+
+// Decrypted secrets are scrubbed when the config goes away
+impl Drop for ServerConfig {
+    fn drop(&mut self) {
+        self.password.zeroize();
+        self.passphrase.zeroize();
+    }
+}
+
+impl ServerConfig {
+    // Copy for servers.json with the secrets encrypted. Built field by field so
+    // the plaintext password is never cloned.
+    fn encrypted(&self) -> Result<ServerConfig, String> {
+        Ok(ServerConfig {
+            id: self.id.clone(),
+            host: self.host.clone(),
+            port: self.port,
+            username: self.username.clone(),
+            password: crypto::encrypt_password_with_aad(&self.password, &self.id)?,
+            description: self.description.clone(),
+            environment: self.environment.clone(),
+            status: self.status.clone(),
+            private_key_path: self.private_key_path.clone(),
+            passphrase: self
+                .passphrase
+                .as_deref()
+                .map(|passphrase| crypto::encrypt_password_with_aad(passphrase, &self.id))
+                .transpose()?,
+            auth_method: self.auth_method,
+            jump_host: self
+                .jump_host
+                .as_ref()
+                .map(|jump| jump.with_secrets(|v| crypto::encrypt_password_with_aad(v, &self.id)))
+                .transpose()?,
+            connect_timeout_secs: self.connect_timeout_secs,
+            read_timeout_secs: self.read_timeout_secs,
+            use_compression: self.use_compression,
+            group: self.group.clone(),
+            sort_index: self.sort_index,
+            default_log_path: self.default_log_path.clone(),
+        })
+    }
+
+    // Decrypts the stored secrets, which are bound to the server id. If
+    // decryption fails (e.g., legacy plaintext password), the original value is kept.
+    fn decrypted(mut self) -> ServerConfig {
+        if let Ok(password) = crypto::decrypt_password_with_aad(&self.password, &self.id) {
+            self.password = password;
+        }
+        if let Some(passphrase) = self.passphrase.as_mut() {
+            if let Ok(decrypted) = crypto::decrypt_password_with_aad(passphrase, &self.id) {
+                *passphrase = decrypted;
+            }
+        }
+        if let Some(jump) = self.jump_host.as_mut() {
+            if let Ok(decrypted) = jump.with_secrets(|v| {
+                Ok(crypto::decrypt_password_with_aad(v, &self.id).unwrap_or_else(|_| v.to_string()))
+            }) {
+                *jump = decrypted;
+            }
+        }
+        self
+    }
+
+    // Encrypts the stored secrets that are legacy plaintext. Values that are
+    // already ciphertext are left alone, including ones `decrypt` can't open
+    // (e.g. written under the keychain key while the keychain is unavailable),
+    // since wrapping them again would lose them. Returns whether anything was
+    // encrypted.
+    fn encrypt_plaintext_secrets(
+        &mut self,
+        decrypt: impl Fn(&str) -> Result<String, String>,
+        encrypt: impl Fn(&str) -> Result<String, String>,
+    ) -> Result<bool, String> {
+        let migrated = std::cell::Cell::new(false);
+        let migrate = |value: &str| {
+            if value.is_empty() || crypto::looks_encrypted(value) || decrypt(value).map(Zeroizing::new).is_ok() {
+                return Ok(value.to_string());
+            }
+            migrated.set(true);
+            encrypt(value)
+        };
+        let password = migrate(&self.password)?;
+        let passphrase = self.passphrase.as_deref().map(migrate).transpose()?;
+        let jump_host = self.jump_host.as_ref().map(|jump| jump.with_secrets(migrate)).transpose()?;
+        // Scrub the plaintext being replaced
+        self.password.zeroize();
+        self.password = password;
+        self.passphrase.zeroize();
+        self.passphrase = passphrase;
+        self.jump_host = jump_host;
+        Ok(migrated.get())
+    }
+
+    // Re-encrypts ciphertext written before secrets were bound to the server
+    // id, so it only decrypts under this record. Plaintext, and values that
+    // don't decrypt (e.g. while locked), are left alone.
+    fn bind_legacy_secrets(
+        &mut self,
+        decrypt: impl Fn(&str) -> Result<String, String>,
+        encrypt: impl Fn(&str, &str) -> Result<String, String>,
+    ) -> Result<(), String> {
+        let bind = |value: &str| {
+            if value.is_empty() || crypto::is_bound(value) {
+                return Ok(value.to_string());
+            }
+            match decrypt(value).map(Zeroizing::new) {
+                Ok(plaintext) => encrypt(&plaintext, &self.id),
+                Err(_) => Ok(value.to_string()),
+            }
+        };
+        let password = bind(&self.password)?;
+        let passphrase = self.passphrase.as_deref().map(bind).transpose()?;
+        let jump_host = self.jump_host.as_ref().map(|jump| jump.with_secrets(bind)).transpose()?;
+        self.password = password;
+        self.passphrase = passphrase;
+        self.jump_host = jump_host;
+        Ok(())
+    }
+
+    pub fn target(&self) -> SshTarget {
+        SshTarget {
+            host: self.host.clone(),
+            port: self.port,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            private_key_path: self.private_key_path.clone(),
+            passphrase: self.passphrase.clone(),
+            auth_method: self.auth_method,
+            jump_host: self.jump_host.clone(),
+            connect_timeout_secs: self.connect_timeout_secs,
+            read_timeout_secs: self.read_timeout_secs,
+            use_compression: self.use_compression,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Default)]
 struct ServerStore {
     servers: Vec<ServerConfig>,
+    // Whether servers.json is written as one encrypted blob (see `SealedServerStore`)
+    #[serde(skip)]
+    encrypted: bool,
 }
 
-fn get_servers_file_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+// servers.json with whole-file encryption on: the serialized `ServerStore`
+// encrypted under the master key, so not even hostnames are readable on disk.
+// Passwords inside stay encrypted on their own as well.
+#[derive(Serialize, Deserialize)]
+struct SealedServerStore {
+    sealed: String,
+}
+
+fn decode_server_store(
+    content: &str,
+    decrypt: impl Fn(&str) -> Result<String, String>,
+) -> Result<ServerStore, String> {
+    if let Ok(sealed) = serde_json::from_str::<SealedServerStore>(content) {
+        let json = Zeroizing::new(decrypt(&sealed.sealed)?);
+        let mut store: ServerStore = serde_json::from_str(&json).map_err(|e| e.to_string())?;
+        store.encrypted = true;
+        return Ok(store);
+    }
+    serde_json::from_str(content).map_err(|e| e.to_string())
+}
+
+fn encode_server_store(
+    store: &ServerStore,
+    encrypt: impl Fn(&str) -> Result<String, String>,
+) -> Result<String, String> {
+    let json = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
+    if !store.encrypted {
+        return Ok(json);
+    }
+    let sealed = SealedServerStore { sealed: encrypt(&json)? };
+    serde_json::to_string_pretty(&sealed).map_err(|e| e.to_string())
+}
+
+fn get_app_file_path(app_handle: &tauri::AppHandle, file_name: &str) -> Result<PathBuf, String> {
     let app_dir = app_handle
         .path()
         .app_data_dir()
         .map_err(|e| e.to_string())?;
     fs::create_dir_all(&app_dir).map_err(|e| e.to_string())?;
-    Ok(app_dir.join("servers.json"))
+    Ok(app_dir.join(file_name))
+}
+
+fn get_servers_file_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    get_app_file_path(app_handle, "servers.json")
+}
+
+// Sent when servers.json could not be parsed and its backup was loaded instead
+#[derive(Clone, Serialize)]
+pub struct ServersRestored {
+    pub path: String,
+    pub backup_path: String,
+    pub error: String,
+}
+
+fn backup_path(path: &Path) -> PathBuf {
+    path.with_extension("json.bak")
+}
+
+fn parse_server_store(path: &Path) -> Result<ServerStore, String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    decode_server_store(&content, crypto::decrypt_password)
+}
+
+// Reads the store at `path`, falling back to its `.bak` copy when the main file
+// is corrupt. The error is returned alongside the store if the backup was used.
+fn read_server_store(path: &Path) -> Result<(ServerStore, Option<String>), String> {
+    if !path.exists() {
+        return Ok((ServerStore::default(), None));
+    }
+    match parse_server_store(path) {
+        Ok(store) => Ok((store, None)),
+        // An encrypted store can't be read before unlocking; that's not corruption
+        Err(e) if e == "locked" => Err(e),
+        Err(e) => {
+            let backup = backup_path(path);
+            match parse_server_store(&backup) {
+                Ok(store) => Ok((store, Some(e))),
+                Err(_) => Err(format!("{} is corrupt and has no usable backup: {}", path.display(), e)),
+            }
+        }
+    }
+}
+
+// Writes to a temporary file and renames it over `path`, so a crash mid-write
+// never leaves a truncated store behind. The previous version is kept as `.bak`
+// unless it is itself corrupt, so a bad file never replaces a good backup.
+fn write_server_store(path: &Path, store: &ServerStore) -> Result<(), String> {
+    write_server_store_with(path, store, crypto::encrypt_password)
+}
+
+// `write_server_store`, encrypting a whole-file encrypted store with `encrypt`
+fn write_server_store_with(
+    path: &Path,
+    store: &ServerStore,
+    encrypt: impl Fn(&str) -> Result<String, String>,
+) -> Result<(), String> {
+    let tmp_path = path.with_extension("json.tmp");
+    let content = encode_server_store(store, encrypt)?;
+    let mut file = fs::File::create(&tmp_path).map_err(|e| e.to_string())?;
+    file.write_all(content.as_bytes())
+        .and_then(|_| file.sync_all())
+        .map_err(|e| e.to_string())?;
+    if parse_server_store(path).is_ok() {
+        fs::copy(path, backup_path(path))
+            .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+    }
+    fs::rename(&tmp_path, path).map_err(|e| e.to_string())?;
+    Ok(())
 }
 
 fn load_servers(app_handle: &tauri::AppHandle) -> Result<ServerStore, String> {
     let path = get_servers_file_path(app_handle)?;
+    let (store, recovered_from) = read_server_store(&path)?;
+    if let Some(error) = recovered_from {
+        tracing::warn!(path = %path.display(), error = %error, "server store is corrupt; loaded the backup");
+        let _ = app_handle.emit(
+            "servers-restored-from-backup",
+            ServersRestored {
+                path: path.display().to_string(),
+                backup_path: backup_path(&path).display().to_string(),
+                error,
+            },
+        );
+    }
+    Ok(store)
+}
+
+// Secrets stored before they were bound to their server id are migrated here,
+// on the next save after an upgrade
+fn save_servers(app_handle: &tauri::AppHandle, store: &mut ServerStore) -> Result<(), String> {
+    ensure_key_matches()?;
+    for server in store.servers.iter_mut() {
+        server.bind_legacy_secrets(crypto::decrypt_password, crypto::encrypt_password_with_aad)?;
+    }
+    write_server_store(&get_servers_file_path(app_handle)?, store)
+}
+
+const KEY_MISMATCH_MESSAGE: &str =
+    "Encryption key mismatch — restore backup: stored passwords no longer decrypt with the current key";
+
+// Set by `crypto_self_test` when stored secrets don't decrypt. Saving then
+// would re-encrypt undecryptable values as if they were plaintext and lose them.
+static KEY_MISMATCH: AtomicBool = AtomicBool::new(false);
+
+fn ensure_key_matches() -> Result<(), String> {
+    if KEY_MISMATCH.load(Ordering::SeqCst) {
+        return Err(format!("{}; refusing to overwrite servers.json", KEY_MISMATCH_MESSAGE));
+    }
+    Ok(())
+}
+
+/// Outcome of `crypto_self_test`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct CryptoHealth {
+    // "ok", "locked" (nothing can be checked before `unlock`) or "key_mismatch"
+    pub status: String,
+    // The server whose stored password was checked, if any had one
+    pub checked_server: Option<String>,
+    pub error: Option<String>,
+}
+
+// Decrypts the first stored password that is ciphertext rather than legacy
+// plaintext. Returns that server's id, or None when there was nothing to check.
+fn check_stored_secret(
+    store: &ServerStore,
+    decrypt: impl Fn(&str, &str) -> Result<String, String>,
+) -> Result<Option<String>, String> {
+    let server = match store.servers.iter().find(|s| crypto::looks_encrypted(&s.password)) {
+        Some(server) => server,
+        None => return Ok(None),
+    };
+    decrypt(&server.password, &server.id)
+        .map(Zeroizing::new)
+        .map_err(|e| format!("Password of server {} does not decrypt: {}", server.id, e))?;
+    Ok(Some(server.id.clone()))
+}
+
+fn check_crypto(app_handle: &tauri::AppHandle) -> CryptoHealth {
+    let health = |status: &str, checked_server, error| CryptoHealth {
+        status: status.to_string(),
+        checked_server,
+        error,
+    };
+    if !crypto::MASTER_KEY.is_unlocked() {
+        return health("locked", None, None);
+    }
+    let checked = crypto::self_test().and_then(|_| {
+        let path = get_servers_file_path(app_handle)?;
+        let (store, _) = read_server_store(&path)?;
+        check_stored_secret(&store, crypto::decrypt_password_with_aad)
+    });
+    match checked {
+        Ok(checked_server) => {
+            KEY_MISMATCH.store(false, Ordering::SeqCst);
+            health("ok", checked_server, None)
+        }
+        Err(e) => {
+            KEY_MISMATCH.store(true, Ordering::SeqCst);
+            tracing::error!(error = %e, "crypto self-test failed");
+            health("key_mismatch", None, Some(format!("{} ({})", KEY_MISMATCH_MESSAGE, e)))
+        }
+    }
+}
+
+/// Checks that the master key round-trips a sentinel and still decrypts a
+/// stored password. On a mismatch, servers.json is no longer written until a
+/// later self-test passes.
+#[tauri::command]
+fn crypto_self_test(app_handle: tauri::AppHandle) -> CryptoHealth {
+    check_crypto(&app_handle)
+}
+
+// Default log path per environment, for servers without their own `default_log_path`
+type LogPathTemplates = BTreeMap<String, String>;
+
+fn get_log_path_templates_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    get_app_file_path(app_handle, "log_path_templates.json")
+}
+
+fn load_log_path_templates(app_handle: &tauri::AppHandle) -> Result<LogPathTemplates, String> {
+    let path = get_log_path_templates_path(app_handle)?;
     if !path.exists() {
-        return Ok(ServerStore::default());
+        return Ok(LogPathTemplates::new());
     }
     let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
-    serde_json::from_str(&content).map_err(|e| e.to_string())
+    serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))
 }
 
-fn save_servers(app_handle: &tauri::AppHandle, store: &ServerStore) -> Result<(), String> {
-    let path = get_servers_file_path(app_handle)?;
-    let content = serde_json::to_string_pretty(store).map_err(|e| e.to_string())?;
-    let mut file = fs::File::create(&path).map_err(|e| e.to_string())?;
-    file.write_all(content.as_bytes())
-        .map_err(|e| e.to_string())?;
+// The log path to use on `server` when none was given: its own default, else
+// the template of its environment. Never falls back to the home directory.
+fn resolve_default_log_path(server: Option<&ServerConfig>, templates: &LogPathTemplates) -> Result<String, String> {
+    let server = server.ok_or("No log path given and the server is not configured")?;
+    server
+        .default_log_path
+        .as_deref()
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .or_else(|| templates.get(&server.environment).map(|path| path.trim()).filter(|path| !path.is_empty()))
+        .map(str::to_string)
+        .ok_or_else(|| {
+            format!(
+                "No log path given and none configured for {} or environment {:?}",
+                server.host, server.environment
+            )
+        })
+}
+
+// `log_path` itself unless it's empty, in which case the server's default
+fn log_path_or_default(
+    app_handle: &tauri::AppHandle,
+    log_path: String,
+    server: Option<&ServerConfig>,
+) -> Result<String, String> {
+    if !log_path.trim().is_empty() {
+        return Ok(log_path);
+    }
+    resolve_default_log_path(server, &load_log_path_templates(app_handle)?)
+}
+
+/// The default log path of every environment that has one.
+#[tauri::command]
+fn get_log_path_templates(app_handle: tauri::AppHandle) -> Result<LogPathTemplates, String> {
+    load_log_path_templates(&app_handle)
+}
+
+/// Sets the default log path of `environment`; an empty path removes it.
+#[tauri::command]
+fn set_log_path_template(app_handle: tauri::AppHandle, environment: String, log_path: String) -> Result<(), String> {
+    let mut templates = load_log_path_templates(&app_handle)?;
+    if log_path.trim().is_empty() {
+        templates.remove(&environment);
+    } else {
+        templates.insert(environment, log_path.trim().to_string());
+    }
+    let content = serde_json::to_string_pretty(&templates).map_err(|e| e.to_string())?;
+    write_file_at(&get_log_path_templates_path(&app_handle)?, &content, false, false)?;
+    Ok(())
+}
+
+const MAX_RECENT_TRACE_IDS: usize = 10;
+
+/// The last query parameters used on one server, kept across restarts in
+/// `recent.json`. Only what was searched for, never what was found.
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+struct RecentQuery {
+    #[serde(default)]
+    log_path: Option<String>,
+    // Most recent first, without duplicates
+    #[serde(default)]
+    trace_ids: Vec<String>,
+}
+
+impl RecentQuery {
+    fn record(&mut self, log_path: &str, trace_id: &str) {
+        if !log_path.trim().is_empty() {
+            self.log_path = Some(log_path.to_string());
+        }
+        if !trace_id.is_empty() {
+            self.trace_ids.retain(|id| id != trace_id);
+            self.trace_ids.insert(0, trace_id.to_string());
+            self.trace_ids.truncate(MAX_RECENT_TRACE_IDS);
+        }
+    }
+}
+
+// Recent queries by server id
+type RecentQueries = BTreeMap<String, RecentQuery>;
+
+lazy_static! {
+    // Concurrent searches update recent.json read-modify-write
+    static ref RECENT_LOCK: Mutex<()> = Mutex::new(());
+}
+
+fn get_recent_path(app_handle: &tauri::AppHandle) -> Result<PathBuf, String> {
+    get_app_file_path(app_handle, "recent.json")
+}
+
+fn load_recent(app_handle: &tauri::AppHandle) -> Result<RecentQueries, String> {
+    let path = get_recent_path(app_handle)?;
+    if !path.exists() {
+        return Ok(RecentQueries::new());
+    }
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))
+}
+
+fn update_recent(app_handle: &tauri::AppHandle, server_id: &str, log_path: &str, trace_id: &str) -> Result<(), String> {
+    let _lock = RECENT_LOCK.lock().map_err(|_| "Lock failed")?;
+    // A corrupt file only holds convenience data, so it is started over
+    let mut recent = load_recent(app_handle).unwrap_or_default();
+    recent.entry(server_id.to_string()).or_default().record(log_path, trace_id);
+    let content = serde_json::to_string_pretty(&recent).map_err(|e| e.to_string())?;
+    write_file_at(&get_recent_path(app_handle)?, &content, false, false)?;
     Ok(())
 }
 
+// Remembers the query on `server_id`; failing to do so never fails the search
+fn record_recent_query(app_handle: &tauri::AppHandle, server_id: &str, log_path: &str, trace_id: &str) {
+    if server_id.is_empty() {
+        return;
+    }
+    if let Err(e) = update_recent(app_handle, server_id, log_path, trace_id) {
+        tracing::warn!(error = %e, "failed to update recent.json");
+    }
+}
+
+/// The last log path and recent trace ids used on `server_id`.
 #[tauri::command]
-async fn test_ssh_connection(host: String, port: u16, username: String, password: String) -> Result<String, String> {
-    // Run the blocking SSH operations in a separate thread
+fn get_recent(app_handle: tauri::AppHandle, server_id: String) -> Result<RecentQuery, String> {
+    Ok(load_recent(&app_handle)?.remove(&server_id).unwrap_or_default())
+}
+
+// Order shown in the server list: by group, then by `sort_index`
+fn sort_servers(servers: &mut [ServerConfig]) {
+    servers.sort_by(|a, b| (&a.group, a.sort_index).cmp(&(&b.group, b.sort_index)));
+}
+
+// Gives servers listed in `ordered_ids` their position as `sort_index`; any
+// others keep their relative order after them
+fn apply_server_order(servers: &mut [ServerConfig], ordered_ids: &[String]) -> Result<(), String> {
+    if let Some(unknown) = ordered_ids.iter().find(|id| !servers.iter().any(|s| &s.id == *id)) {
+        return Err(format!("Unknown server id: {}", unknown));
+    }
+    sort_servers(servers);
+    let mut next_unlisted = ordered_ids.len() as i32;
+    for server in servers.iter_mut() {
+        server.sort_index = match ordered_ids.iter().position(|id| *id == server.id) {
+            Some(pos) => pos as i32,
+            None => {
+                next_unlisted += 1;
+                next_unlisted - 1
+            }
+        };
+    }
+    Ok(())
+}
+
+// Runs blocking SSH work for `target` on a separate thread while holding an
+// `SSH_LIMITER` permit. Errors go to the frontend as they are, so the target's
+// secrets are scrubbed from them here.
+async fn run_ssh<T: Send + 'static>(
+    target: SshTarget,
+    work: impl FnOnce(&SshTarget) -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    let permit = SSH_LIMITER.acquire().await?;
     tokio::task::spawn_blocking(move || {
-        let addr = format!("{}:{}", host, port);
-        
-        // Connect TCP
-        let tcp = TcpStream::connect(&addr)
-            .map_err(|e| format!("TCP connection failed: {}", e))?;
-        
-        tcp.set_read_timeout(Some(std::time::Duration::from_secs(10)))
-            .map_err(|e| format!("Failed to set timeout: {}", e))?;
-        
-        // Create SSH session
-        let mut sess = Session::new()
-            .map_err(|e| format!("Failed to create SSH session: {}", e))?;
-        
-        sess.set_tcp_stream(tcp);
-        sess.handshake()
-            .map_err(|e| format!("SSH handshake failed: {}", e))?;
-        
-        // Authenticate with password
-        sess.userauth_password(&username, &password)
-            .map_err(|e| format!("Authentication failed: {}", e))?;
-        
-        if sess.authenticated() {
-            // Try to execute a simple command
-            let mut channel = sess.channel_session()
-                .map_err(|e| format!("Failed to open channel: {}", e))?;
-            channel.exec("echo 'Connection test successful'")
-                .map_err(|e| format!("Failed to execute command: {}", e))?;
-            
-            let mut output = String::new();
-            channel.read_to_string(&mut output)
-                .map_err(|e| format!("Failed to read output: {}", e))?;
-            channel.wait_close().ok();
-            
-            Ok(format!("✓ Successfully connected to {} as {}", host, username))
-        } else {
-            Err("Authentication failed".to_string())
+        let _permit = permit;
+        work(&target).map_err(|e| target.redact(&e))
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e))?
+}
+
+#[tauri::command]
+async fn test_ssh_connection(
+    target: SshTarget,
+) -> Result<connection_test::ConnectionTest, String> {
+    run_ssh(target, |target| Ok(connection_test::run(target))).await
+}
+
+lazy_static! {
+    // Cancellation flags of tests started with `start_test_connection`, keyed by
+    // handle id. Whoever removes the entry sends the completion event.
+    static ref ACTIVE_CONNECTION_TESTS: DashMap<String, Arc<AtomicBool>> = DashMap::new();
+}
+
+// Sent when a test started with `start_test_connection` finishes or is cancelled
+#[derive(Clone, Serialize)]
+pub struct ConnectionTestComplete {
+    pub handle_id: String,
+    pub result: connection_test::ConnectionTest,
+}
+
+/// Starts `test_ssh_connection` in the background and returns its handle id
+/// right away. The result arrives as a `connection-test-complete` event;
+/// `cancel_test_connection` ends it early with a "cancelled" result.
+#[tauri::command]
+async fn start_test_connection(
+    app_handle: tauri::AppHandle,
+    target: SshTarget,
+) -> Result<String, String> {
+    let handle_id = uuid::Uuid::new_v4().to_string();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_CONNECTION_TESTS.insert(handle_id.clone(), cancelled.clone());
+
+    let registered_id = handle_id.clone();
+    tokio::spawn(async move {
+        let result = match SSH_LIMITER.acquire().await {
+            Ok(permit) => tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                connection_test::run_cancellable(&target, &cancelled)
+            })
+            .await
+            .unwrap_or_else(|e| connection_test::ConnectionTest {
+                message: format!("Task failed: {}", e),
+                ..connection_test::cancelled()
+            }),
+            Err(e) => connection_test::ConnectionTest {
+                message: e,
+                ..connection_test::cancelled()
+            },
+        };
+        // Already reported as cancelled otherwise
+        if ACTIVE_CONNECTION_TESTS.remove(&registered_id).is_some() {
+            let _ = app_handle.emit(
+                "connection-test-complete",
+                ConnectionTestComplete {
+                    handle_id: registered_id,
+                    result,
+                },
+            );
+        }
+    });
+
+    Ok(handle_id)
+}
+
+/// Cancels a running `start_test_connection`: its `connection-test-complete`
+/// event is sent now with a "cancelled" result, and the background test stops
+/// before its next phase. Returns false if the test had already finished.
+#[tauri::command]
+fn cancel_test_connection(app_handle: tauri::AppHandle, handle_id: String) -> bool {
+    match ACTIVE_CONNECTION_TESTS.remove(&handle_id) {
+        Some((handle_id, flag)) => {
+            flag.store(true, Ordering::SeqCst);
+            let _ = app_handle.emit(
+                "connection-test-complete",
+                ConnectionTestComplete {
+                    handle_id,
+                    result: connection_test::cancelled(),
+                },
+            );
+            true
         }
+        None => false,
+    }
+}
+
+/// Tests the jump host and the target behind it separately, to show which
+/// hop a failed connection stopped at.
+#[tauri::command]
+async fn test_jump_connection(config: ServerConfig) -> Result<connection_test::JumpConnectionTest, String> {
+    run_ssh(config.target(), connection_test::run_through_jump).await
+}
+
+/// Returns the comma-separated auth methods the server accepts for `username`,
+/// e.g. to tell the user a server needs a key rather than a password.
+#[tauri::command]
+async fn probe_auth_methods(
+    host: String,
+    port: u16,
+    username: String,
+    jump_host: Option<JumpConfig>,
+    connect_timeout_secs: Option<u64>,
+) -> Result<String, String> {
+    let permit = SSH_LIMITER.acquire().await?;
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let mut target = SshTarget::default();
+        target.host = host;
+        target.port = port;
+        target.username = username;
+        target.jump_host = jump_host;
+        target.connect_timeout_secs = connect_timeout_secs;
+        ssh_connect::auth_methods(&target)
     })
     .await
     .map_err(|e| format!("Task failed: {}", e))?
 }
 
+/// Probes every server's SSH port in parallel (at most `HEALTH_CHECK_PARALLEL`
+/// at a time) so the UI can show which ones are online. Results keep the input order.
+#[tauri::command]
+async fn check_servers_health(servers: Vec<ServerConfig>) -> Result<Vec<health::ServerHealth>, String> {
+    let semaphore = Arc::new(Semaphore::new(health::HEALTH_CHECK_PARALLEL));
+    let mut handles = Vec::with_capacity(servers.len());
+
+    for server in servers {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| format!("Semaphore closed: {}", e))?;
+        let ssh_permit = SSH_LIMITER.acquire().await?;
+
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permits = (permit, ssh_permit);
+            health::probe(server.id.clone(), server.target())
+        }));
+    }
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await.map_err(|e| format!("Task failed: {}", e))?);
+    }
+    Ok(results)
+}
+
+/// Persists a host key fingerprint the user accepted from a `host-key-unknown` prompt.
+#[tauri::command]
+fn trust_host_key(host: String, port: u16, key_type: String, fingerprint: String) -> Result<(), String> {
+    KNOWN_HOSTS.trust(KnownHost {
+        host,
+        port,
+        key_type,
+        fingerprint,
+    })
+}
+
+/// A problem with one field of a `ServerConfig`, as reported by `validate_server`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+// Checks `server` on its own and against the other stored servers
+fn server_field_errors(server: &ServerConfig, existing: &[ServerConfig]) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    let mut reject = |field: &str, message: &str| {
+        errors.push(FieldError {
+            field: field.to_string(),
+            message: message.to_string(),
+        })
+    };
+    if server.host.trim().is_empty() {
+        reject("host", "Host must not be empty");
+    }
+    if server.port == 0 {
+        reject("port", "Port must be between 1 and 65535");
+    }
+    if server.username.trim().is_empty() {
+        reject("username", "Username must not be empty");
+    }
+    let duplicate = existing.iter().any(|s| {
+        s.id != server.id
+            && s.host.eq_ignore_ascii_case(server.host.trim())
+            && s.port == server.port
+            && s.username == server.username.trim()
+    });
+    if duplicate {
+        reject("host", "A server with this host, port and username already exists");
+    }
+    errors
+}
+
+/// Field errors that would make `save_server` reject `server`; empty if it is valid.
+#[tauri::command]
+fn validate_server(app_handle: tauri::AppHandle, server: ServerConfig) -> Result<Vec<FieldError>, String> {
+    let store = load_servers(&app_handle)?;
+    Ok(server_field_errors(&server, &store.servers))
+}
+
 #[tauri::command]
 fn save_server(app_handle: tauri::AppHandle, server: ServerConfig) -> Result<ServerConfig, String> {
+    crypto::ensure_unlocked()?;
     let mut store = load_servers(&app_handle)?;
     
+    let errors = server_field_errors(&server, &store.servers);
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+        return Err(messages.join("; "));
+    }
+    
+    let mut server = server;
+    let existing = store.servers.iter().position(|s| s.id == server.id);
+    // New servers go to the end of the list
+    if existing.is_none() {
+        server.sort_index = store.servers.iter().map(|s| s.sort_index + 1).max().unwrap_or(0);
+    }
+    
     // Encrypt the password before storing
-    let encrypted_password = crypto::encrypt_password(&server.password)?;
-    let mut server_to_store = server.clone();
-    server_to_store.password = encrypted_password;
+    let server_to_store = server.encrypted()?;
     
     // Check if server with same ID exists (update) or add new
-    if let Some(pos) = store.servers.iter().position(|s| s.id == server_to_store.id) {
+    if let Some(pos) = existing {
         store.servers[pos] = server_to_store;
     } else {
         store.servers.push(server_to_store);
     }
     
-    save_servers(&app_handle, &store)?;
+    save_servers(&app_handle, &mut store)?;
     // Return the original server with plaintext password to frontend
     Ok(server)
 }
 
 #[tauri::command]
 fn list_servers(app_handle: tauri::AppHandle) -> Result<Vec<ServerConfig>, String> {
+    crypto::ensure_unlocked()?;
     let store = load_servers(&app_handle)?;
     // Decrypt passwords before returning to frontend
-    // If decryption fails (e.g., legacy plaintext password), use the original value
-    let decrypted_servers: Vec<ServerConfig> = store
+    let mut decrypted_servers: Vec<ServerConfig> = store
         .servers
         .into_iter()
-        .map(|mut s| {
-            s.password = crypto::decrypt_password(&s.password).unwrap_or_else(|_| s.password.clone());
-            s
-        })
+        .map(ServerConfig::decrypted)
         .collect();
+    sort_servers(&mut decrypted_servers);
     Ok(decrypted_servers)
 }
 
-/// List servers for export - keeps passwords encrypted
+/// Stores the order the user arranged servers in: each id gets its position in
+/// `ordered_ids` as `sort_index`, all written in a single save.
 #[tauri::command]
-fn list_servers_for_export(app_handle: tauri::AppHandle) -> Result<Vec<ServerConfig>, String> {
-    let store = load_servers(&app_handle)?;
-    // Return servers with encrypted passwords as stored
-    Ok(store.servers)
+fn reorder_servers(app_handle: tauri::AppHandle, ordered_ids: Vec<String>) -> Result<(), String> {
+    let mut store = load_servers(&app_handle)?;
+    apply_server_order(&mut store.servers, &ordered_ids)?;
+    save_servers(&app_handle, &mut store)
+}
+
+// One id that exists both locally and in an imported bundle
+#[derive(Serialize, Clone)]
+pub struct ImportConflict {
+    pub id: String,
+    pub existing_host: String,
+    pub existing_username: String,
+    pub incoming_host: String,
+    pub incoming_username: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportResult {
+    pub imported: usize,
+    pub conflicts: Vec<ImportConflict>, // Left untouched; pass their ids as overwrite_ids to replace them
 }
 
+/// Exports every server, secrets included, as a bundle encrypted under `password`
+/// so it can be imported on another machine.
 #[tauri::command]
-fn delete_server(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
-    let mut store = load_servers(&app_handle)?;
-    store.servers.retain(|s| s.id != id);
-    save_servers(&app_handle, &store)?;
-    Ok(())
+fn export_servers(app_handle: tauri::AppHandle, password: String) -> Result<String, String> {
+    crypto::ensure_unlocked()?;
+    let servers: Vec<ServerConfig> = load_servers(&app_handle)?
+        .servers
+        .into_iter()
+        .map(ServerConfig::decrypted)
+        .collect();
+    let json = Zeroizing::new(serde_json::to_string(&servers).map_err(|e| e.to_string())?);
+    crypto::seal_with_password(&json, &password)
 }
 
+/// Merges servers from an `export_servers` bundle into servers.json. Servers whose
+/// id already exists are only replaced when listed in `overwrite_ids`; the rest
+/// are returned as conflicts so the user can decide.
 #[tauri::command]
-fn get_system_info() -> SystemInfo {
-    let mut sys = System::new_all();
-    sys.refresh_all();
+fn import_servers(
+    app_handle: tauri::AppHandle,
+    data: String,
+    password: String,
+    overwrite_ids: Option<Vec<String>>,
+) -> Result<ImportResult, String> {
+    crypto::ensure_unlocked()?;
+    let json = crypto::open_with_password(&data, &password)?;
+    let incoming: Vec<ServerConfig> =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid server bundle: {}", e))?;
+    let overwrite_ids = overwrite_ids.unwrap_or_default();
+    
+    let mut store = load_servers(&app_handle)?;
+    let mut imported = 0;
+    let mut conflicts = Vec::new();
+    
+    for server in &incoming {
+        let server_to_store = server.encrypted()?;
+        match store.servers.iter().position(|s| s.id == server.id) {
+            Some(pos) if overwrite_ids.contains(&server.id) => store.servers[pos] = server_to_store,
+            Some(pos) => {
+                let existing = &store.servers[pos];
+                conflicts.push(ImportConflict {
+                    id: server.id.clone(),
+                    existing_host: existing.host.clone(),
+                    existing_username: existing.username.clone(),
+                    incoming_host: server.host.clone(),
+                    incoming_username: server.username.clone(),
+                });
+                continue;
+            }
+            None => store.servers.push(server_to_store),
+        }
+        imported += 1;
+    }
+    
+    if imported > 0 {
+        save_servers(&app_handle, &mut store)?;
+    }
+    Ok(ImportResult { imported, conflicts })
+}
+
+/// Derives the storage key from the master password; the first call sets it.
+#[tauri::command]
+fn unlock(app_handle: tauri::AppHandle, master_password: String) -> Result<(), String> {
+    let path = get_app_file_path(&app_handle, crypto::MASTER_KEY_FILE)?;
+    crypto::MASTER_KEY.unlock(&path, &master_password)?;
+    check_crypto(&app_handle);
+    Ok(())
+}
+
+/// Whether `unlock` is still needed before servers can be listed or saved.
+#[tauri::command]
+fn is_locked() -> bool {
+    !crypto::MASTER_KEY.is_unlocked()
+}
+
+/// The cipher used for newly encrypted passwords.
+#[tauri::command]
+fn get_encryption_algorithm() -> crypto::Algorithm {
+    crypto::MASTER_KEY.algorithm()
+}
+
+/// Picks the cipher for newly encrypted passwords. Passwords already stored
+/// keep their cipher until `rotate_encryption_key` re-encrypts them.
+#[tauri::command]
+fn set_encryption_algorithm(app_handle: tauri::AppHandle, algorithm: crypto::Algorithm) -> Result<(), String> {
+    let path = get_app_file_path(&app_handle, crypto::MASTER_KEY_FILE)?;
+    crypto::MASTER_KEY.set_algorithm(&path, algorithm)
+}
+
+/// Forgets the derived storage key until the next `unlock`.
+#[tauri::command]
+fn lock() {
+    crypto::MASTER_KEY.lock();
+}
+
+/// Re-encrypts every stored password and passphrase under a key derived from a
+/// fresh salt and `master_password`, which may differ from the current one.
+/// Returns the number of servers that were re-encrypted.
+#[tauri::command]
+fn rotate_encryption_key(app_handle: tauri::AppHandle, master_password: String) -> Result<usize, String> {
+    ensure_key_matches()?;
+    let path = get_app_file_path(&app_handle, crypto::MASTER_KEY_FILE)?;
+    let mut store = load_servers(&app_handle)?;
+    
+    crypto::MASTER_KEY.rotate(&path, &master_password, |new_key| {
+        for server in store.servers.iter_mut() {
+            // Values that fail to decrypt are legacy plaintext, as in list_servers,
+            // unless they look like ciphertext: those can't be carried over, so
+            // the rotation is abandoned rather than losing them
+            let id = server.id.clone();
+            let reencrypt = |value: &str| {
+                let plaintext = Zeroizing::new(match crypto::decrypt_password_with_aad(value, &id) {
+                    Ok(plaintext) => plaintext,
+                    Err(e) if crypto::looks_encrypted(value) => {
+                        return Err(format!("A secret of server {} does not decrypt with the current key: {}", id, e));
+                    }
+                    Err(_) => value.to_string(),
+                });
+                crypto::encrypt_with_aad(new_key, crypto::MASTER_KEY.algorithm(), &plaintext, &id)
+            };
+            server.password = reencrypt(&server.password)?;
+            server.passphrase = server.passphrase.as_deref().map(reencrypt).transpose()?;
+            server.jump_host = server.jump_host.as_ref().map(|jump| jump.with_secrets(reencrypt)).transpose()?;
+        }
+        // The new key only becomes active after this, so seal the file with it directly
+        write_server_store_with(&get_servers_file_path(&app_handle)?, &store, |json| {
+            crypto::encrypt_with_algorithm(new_key, crypto::MASTER_KEY.algorithm(), json)
+        })
+    })?;
+    Ok(store.servers.len())
+}
+
+/// Encrypts every password and passphrase still stored in plaintext by old
+/// builds, which `list_servers` otherwise keeps using as is. Returns the number
+/// of servers that had plaintext secrets.
+#[tauri::command]
+fn migrate_plaintext_passwords(app_handle: tauri::AppHandle) -> Result<usize, String> {
+    crypto::ensure_unlocked()?;
+    let mut store = load_servers(&app_handle)?;
+    let mut migrated = 0;
+    for server in store.servers.iter_mut() {
+        let id = server.id.clone();
+        let decrypt = |v: &str| crypto::decrypt_password_with_aad(v, &id);
+        let encrypt = |v: &str| crypto::encrypt_password_with_aad(v, &id);
+        if server.encrypt_plaintext_secrets(decrypt, encrypt)? {
+            tracing::info!(id = %server.id, host = %server.host, "encrypted plaintext secrets");
+            migrated += 1;
+        }
+    }
+    if migrated > 0 {
+        save_servers(&app_handle, &mut store)?;
+    }
+    tracing::info!(migrated, servers = store.servers.len(), "plaintext password migration finished");
+    Ok(migrated)
+}
+
+/// Turns whole-file encryption of servers.json on or off. Enabling it rewrites
+/// an existing plaintext file encrypted under the master key.
+#[tauri::command]
+fn set_server_store_encryption(app_handle: tauri::AppHandle, enabled: bool) -> Result<(), String> {
+    crypto::ensure_unlocked()?;
+    let mut store = load_servers(&app_handle)?;
+    store.encrypted = enabled;
+    save_servers(&app_handle, &mut store)
+}
+
+/// List servers for export - keeps passwords encrypted
+#[tauri::command]
+fn list_servers_for_export(app_handle: tauri::AppHandle) -> Result<Vec<ServerConfig>, String> {
+    let store = load_servers(&app_handle)?;
+    // Return servers with encrypted passwords as stored
+    Ok(store.servers)
+}
+
+// The file written by `export_servers_to_file`, in the same layout as the
+// frontend's JSON export so its importer reads either. Passwords stay
+// encrypted as stored; without `include_passwords` they're left out entirely,
+// the jump host's included.
+fn server_export_json(servers: &[ServerConfig], include_passwords: bool) -> Result<String, String> {
+    let mut entries = Vec::with_capacity(servers.len());
+    for server in servers {
+        let mut entry = serde_json::to_value(server).map_err(|e| e.to_string())?;
+        if let (false, Some(fields)) = (include_passwords, entry.as_object_mut()) {
+            fields.remove("password");
+            fields.remove("passphrase");
+            if let Some(jump) = fields.get_mut("jump_host").and_then(|jump| jump.as_object_mut()) {
+                jump.remove("password");
+                jump.remove("passphrase");
+            }
+        }
+        entries.push(entry);
+    }
+    let exported_at = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    serde_json::to_string_pretty(&serde_json::json!({
+        "version": "1.0",
+        "exportedAt": exported_at,
+        "servers": entries,
+    }))
+    .map_err(|e| e.to_string())
+}
+
+/// Writes every server to `path` as a JSON export in one step. Returns the
+/// number of servers written.
+#[tauri::command]
+fn export_servers_to_file(
+    app_handle: tauri::AppHandle,
+    path: String,
+    include_passwords: bool,
+) -> Result<usize, String> {
+    let store = load_servers(&app_handle)?;
+    let json = server_export_json(&store.servers, include_passwords)?;
+    write_file_at(Path::new(&path), &json, false, false)?;
+    Ok(store.servers.len())
+}
+
+// Parsed `find_servers` query: `env:<name>` terms filter on the environment,
+// every other word must appear in the host, description, username or environment
+struct ServerQuery {
+    environments: Vec<String>,
+    words: Vec<String>,
+}
+
+impl ServerQuery {
+    fn parse(query: &str) -> Self {
+        let mut parsed = ServerQuery {
+            environments: Vec::new(),
+            words: Vec::new(),
+        };
+        for term in query.split_whitespace().map(str::to_lowercase) {
+            match term.strip_prefix("env:") {
+                Some(env) if !env.is_empty() => parsed.environments.push(env.to_string()),
+                Some(_) => {}
+                None => parsed.words.push(term),
+            }
+        }
+        parsed
+    }
+
+    fn matches(&self, server: &ServerConfig) -> bool {
+        let environment = server.environment.to_lowercase();
+        if !self.environments.is_empty() && !self.environments.contains(&environment) {
+            return false;
+        }
+        let fields = [
+            server.host.to_lowercase(),
+            server.description.to_lowercase(),
+            server.username.to_lowercase(),
+            environment,
+        ];
+        self.words
+            .iter()
+            .all(|word| fields.iter().any(|field| field.contains(word.as_str())))
+    }
+}
+
+/// Servers matching `query` (case-insensitive), e.g. `env:prod gateway`.
+/// Like `list_servers_for_export`, passwords are left encrypted as stored.
+#[tauri::command]
+fn find_servers(app_handle: tauri::AppHandle, query: String) -> Result<Vec<ServerConfig>, String> {
+    let query = ServerQuery::parse(&query);
+    let store = load_servers(&app_handle)?;
+    let mut servers: Vec<ServerConfig> = store
+        .servers
+        .into_iter()
+        .filter(|s| query.matches(s))
+        .collect();
+    sort_servers(&mut servers);
+    Ok(servers)
+}
+
+#[tauri::command]
+fn delete_server(app_handle: tauri::AppHandle, id: String) -> Result<(), String> {
+    let mut store = load_servers(&app_handle)?;
+    store.servers.retain(|s| s.id != id);
+    save_servers(&app_handle, &mut store)?;
+    Ok(())
+}
+
+// Local machine sampler kept between calls. CPU usage is a difference between
+// two refreshes, so each call measures the interval since the previous one.
+// Network throughput is measured over the same interval.
+struct LocalSystem {
+    sys: System,
+    networks: Networks,
+    last_cpu_refresh: std::time::Instant,
+}
+
+lazy_static! {
+    static ref LOCAL_SYSTEM: Mutex<LocalSystem> = {
+        let mut sys = System::new();
+        sys.refresh_cpu_usage();
+        Mutex::new(LocalSystem {
+            sys,
+            networks: Networks::new_with_refreshed_list(),
+            last_cpu_refresh: std::time::Instant::now(),
+        })
+    };
+}
+
+fn local_disks() -> Option<Vec<DiskUsage>> {
+    let disks: Vec<DiskUsage> = Disks::new_with_refreshed_list()
+        .list()
+        .iter()
+        .map(|disk| DiskUsage {
+            mount: disk.mount_point().display().to_string(),
+            total_gb: disk.total_space() as f64 / 1_073_741_824.0,
+            available_gb: disk.available_space() as f64 / 1_073_741_824.0,
+        })
+        .collect();
+    (!disks.is_empty()).then_some(disks)
+}
+
+#[tauri::command]
+async fn get_system_info() -> Result<SystemInfo, String> {
+    // The first call may sleep while sampling CPU usage, so keep it off the main thread
+    tokio::task::spawn_blocking(sample_system_info)
+        .await
+        .map_err(|e| format!("Task failed: {}", e))?
+}
+
+fn sample_system_info() -> Result<SystemInfo, String> {
+    let mut local = LOCAL_SYSTEM.lock().map_err(|_| "Lock failed")?;
+    // Readings closer together than this are meaningless (often 0%)
+    let since_last = local.last_cpu_refresh.elapsed();
+    if since_last < sysinfo::MINIMUM_CPU_UPDATE_INTERVAL {
+        std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL - since_last);
+    }
+    local.sys.refresh_cpu_usage();
+    local.networks.refresh();
+    let interval = local.last_cpu_refresh.elapsed().as_secs_f64();
+    local.last_cpu_refresh = std::time::Instant::now();
+    local.sys.refresh_memory();
+    let sys = &local.sys;
+    
+    // Bytes moved since the previous call over all interfaces except loopback
+    let interfaces: Vec<_> = local
+        .networks
+        .list()
+        .iter()
+        .filter(|(name, _)| !name.starts_with("lo"))
+        .map(|(_, data)| data)
+        .collect();
+    let rate = |bytes: u64| (!interfaces.is_empty() && interval > 0.0).then(|| bytes as f64 / interval);
+    let net_rx_bytes_per_sec = rate(interfaces.iter().map(|d| d.received()).sum());
+    let net_tx_bytes_per_sec = rate(interfaces.iter().map(|d| d.transmitted()).sum());
     
-    // CPU usage (average across all cores)
+    // CPU usage (average across all cores, and each core on its own)
     let cpu_usage = sys.global_cpu_usage();
+    let per_core = sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect();
     
     // Memory info (in bytes, convert to GB)
     let memory_used = sys.used_memory() as f64 / 1_073_741_824.0; // bytes to GB
@@ -181,12 +1274,29 @@ fn get_system_info() -> SystemInfo {
         0.0
     };
     
-    SystemInfo {
+    Ok(SystemInfo {
         cpu_usage,
         memory_used_gb: memory_used,
         memory_total_gb: memory_total,
         memory_usage_percent: memory_percent,
-    }
+        per_core,
+        disks: local_disks(),
+        net_rx_bytes_per_sec,
+        net_tx_bytes_per_sec,
+    })
+}
+
+/// CPU, memory and disk usage of a remote Linux server. Disk usage is for the
+/// partition holding `disk_path` (default "/"), typically the log directory.
+#[tauri::command]
+async fn get_remote_system_info(
+    target: SshTarget,
+    disk_path: Option<String>,
+) -> Result<remote_info::RemoteSystemInfo, String> {
+    run_ssh(target, move |target| {
+        remote_info::fetch(target, disk_path.as_deref().unwrap_or("/"))
+    })
+    .await
 }
 
 #[tauri::command]
@@ -194,40 +1304,20 @@ fn greet(name: &str) -> String {
     format!("Hello, {}! You've been greeted from Rust!", name)
 }
 
-#[tauri::command]
-fn execute_ssh_command(
-    host: String,
-    port: u16,
-    username: String,
-    password: String,
-    command: String,
-) -> Result<String, String> {
-    let addr = format!("{}:{}", host, port);
-    
-    let tcp = TcpStream::connect(&addr)
-        .map_err(|e| format!("Connection failed: {}", e))?;
-    
-    tcp.set_read_timeout(Some(std::time::Duration::from_secs(30)))
-        .map_err(|e| format!("Failed to set timeout: {}", e))?;
-    
-    let mut sess = Session::new()
-        .map_err(|e| format!("Session failed: {}", e))?;
-    
-    sess.set_tcp_stream(tcp);
-    sess.handshake()
-        .map_err(|e| format!("Handshake failed: {}", e))?;
-    
-    sess.userauth_password(&username, &password)
-        .map_err(|e| format!("Auth failed: {}", e))?;
-    
-    if !sess.authenticated() {
-        return Err("Authentication failed".to_string());
-    }
-    
+/// Output of `execute_ssh_command`, filled in whether or not the command succeeded.
+#[derive(Serialize, Clone)]
+pub struct CommandResult {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_status: i32, // -1 if the server didn't report one
+}
+
+// Runs `command` on `sess` and collects its output and exit status
+fn run_command_in_session(sess: &Session, command: &str) -> Result<CommandResult, String> {
     let mut channel = sess.channel_session()
         .map_err(|e| format!("Channel failed: {}", e))?;
     
-    channel.exec(&command)
+    channel.exec(command)
         .map_err(|e| format!("Exec failed: {}", e))?;
     
     let mut stdout = String::new();
@@ -240,25 +1330,232 @@ fn execute_ssh_command(
     channel.wait_close().ok();
     let exit_status = channel.exit_status().unwrap_or(-1);
     
-    if !stderr.is_empty() && exit_status != 0 {
-        Ok(format!("{}\n[stderr] {}\n[exit: {}]", stdout, stderr, exit_status))
+    Ok(CommandResult {
+        stdout,
+        stderr,
+        exit_status,
+    })
+}
+
+#[tauri::command]
+#[tracing::instrument(skip_all, fields(host = %target.host, command = %command), err)]
+async fn execute_ssh_command(
+    target: SshTarget,
+    command: String,
+) -> Result<CommandResult, String> {
+    run_ssh(target, move |target| {
+        let sess = ssh_connect::connect(target, Duration::from_secs(30))?;
+        run_command_in_session(&sess, &command)
+    })
+    .await
+}
+
+/// One server's outcome in `run_command_on_servers`. On failure `error` is set
+/// and the output is empty with an exit status of -1.
+#[derive(Serialize, Clone)]
+pub struct ServerCommandResult {
+    pub server_id: String,
+    pub host: String,
+    #[serde(flatten)]
+    pub output: CommandResult,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+#[tracing::instrument(skip_all, fields(host = %server.host))]
+fn run_server_command(server: &ServerConfig, command: &str) -> ServerCommandResult {
+    let start_time = std::time::Instant::now();
+    let target = server.target();
+    let result = ssh_connect::connect(&target, Duration::from_secs(30))
+        .and_then(|sess| run_command_in_session(&sess, command));
+    let duration_ms = start_time.elapsed().as_millis() as u64;
+    let (output, error) = match result {
+        Ok(output) => (output, None),
+        Err(e) => {
+            let e = target.redact(&e);
+            tracing::warn!(error = %e, "server command failed");
+            let output = CommandResult {
+                stdout: String::new(),
+                stderr: String::new(),
+                exit_status: -1,
+            };
+            (output, Some(e))
+        }
+    };
+    ServerCommandResult {
+        server_id: server.id.clone(),
+        host: server.host.clone(),
+        output,
+        duration_ms,
+        error,
+    }
+}
+
+/// Runs `command` on every server, at most `max_parallel` at a time (0 means
+/// unbounded). A `server-command-done` event is emitted as each server
+/// finishes; the returned results keep the input order.
+#[tauri::command]
+async fn run_command_on_servers(
+    app_handle: tauri::AppHandle,
+    servers: Vec<ServerConfig>,
+    command: String,
+    max_parallel: usize,
+) -> Result<Vec<ServerCommandResult>, String> {
+    if command.trim().is_empty() {
+        return Err("Command is empty".to_string());
+    }
+    let permits = if max_parallel == 0 {
+        servers.len().max(1)
     } else {
-        Ok(stdout)
+        max_parallel
+    };
+    let semaphore = Arc::new(Semaphore::new(permits));
+    let command = Arc::new(command);
+    let mut handles = Vec::with_capacity(servers.len());
+    
+    for server in servers {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| format!("Semaphore closed: {}", e))?;
+        let ssh_permit = SSH_LIMITER.acquire().await?;
+        let app_handle = app_handle.clone();
+        let command = command.clone();
+        
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permits = (permit, ssh_permit);
+            let result = run_server_command(&server, &command);
+            let _ = app_handle.emit("server-command-done", result.clone());
+            result
+        }));
+    }
+    
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await.map_err(|e| format!("Task failed: {}", e))?);
     }
+    Ok(results)
+}
+
+/// Lists processes on a server, keeping only rows whose command line or user
+/// contains `filter` when one is given.
+#[tauri::command]
+async fn list_remote_processes(
+    target: SshTarget,
+    filter: Option<String>,
+) -> Result<Vec<remote_process::ProcessInfo>, String> {
+    run_ssh(target, move |target| {
+        remote_process::list(target, filter.as_deref())
+    })
+    .await
+}
+
+/// Sends `signal` (default TERM) to `pid` on a server; returns kill's exit status.
+#[tauri::command]
+async fn kill_remote_process(
+    target: SshTarget,
+    pid: u32,
+    signal: Option<String>,
+) -> Result<i32, String> {
+    run_ssh(target, move |target| {
+        remote_process::kill(target, pid, signal.as_deref().unwrap_or("TERM"))
+    })
+    .await
 }
 
-// PTY Session Commands
+/// Streaming variant of `execute_ssh_command` for commands with large or slow
+/// output: emits `command-output` events as data arrives and a final
+/// `command-exit`. Returns the exec id to pass to `cancel_command`.
+///
+/// Like the other long-lived streams (port forwards, PTY sessions and log
+/// follows), only connecting counts against the SSH limit, so open streams
+/// can't starve searches of slots.
 #[tauri::command]
-fn start_pty_session(
+async fn start_ssh_command(
     app_handle: tauri::AppHandle,
-    host: String,
-    port: u16,
-    username: String,
-    password: String,
+    target: SshTarget,
+    command: String,
+) -> Result<String, String> {
+    run_ssh(target, move |target| {
+        COMMAND_MANAGER.start_command(app_handle, target.clone(), command)
+    })
+    .await
+}
+
+#[tauri::command]
+fn cancel_command(exec_id: String) -> Result<(), String> {
+    COMMAND_MANAGER.cancel_command(&exec_id)
+}
+
+/// Forwards `127.0.0.1:<local_port>` (0 picks a free port) to
+/// `remote_host:remote_port` as seen from the server, e.g. a database bound to
+/// the server's localhost. Returns the forward id and the local port.
+/// The connections opened per forwarded client are not limited.
+#[tauri::command]
+async fn start_port_forward(
+    target: SshTarget,
+    remote_host: String,
+    remote_port: u16,
+    local_port: u16,
+) -> Result<port_forward::PortForwardInfo, String> {
+    run_ssh(target, move |target| {
+        PORT_FORWARD_MANAGER.start_forward(target.clone(), remote_host, remote_port, local_port)
+    })
+    .await
+}
+
+#[tauri::command]
+fn stop_port_forward(forward_id: String) -> Result<(), String> {
+    PORT_FORWARD_MANAGER.stop_forward(&forward_id)
+}
+
+// Optional terminal settings of `start_pty_session`; see `PtyOptions`
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct PtySessionArgs {
+    keepalive_interval: Option<u32>,
+    idle_timeout_secs: Option<u64>,
+    record_path: Option<String>,
+    record_input: Option<bool>,
+    auto_reconnect: Option<bool>,
+    env: Option<HashMap<String, String>>,
+    initial_command: Option<String>,
+    scrollback_bytes: Option<usize>,
+}
+
+impl PtySessionArgs {
+    fn into_options(self, cols: u32, rows: u32) -> PtyOptions {
+        PtyOptions {
+            cols,
+            rows,
+            keepalive_interval: self.keepalive_interval.unwrap_or(ssh_session::DEFAULT_KEEPALIVE_INTERVAL),
+            idle_timeout: Duration::from_secs(self.idle_timeout_secs.unwrap_or(0)),
+            record_path: self.record_path.filter(|p| !p.trim().is_empty()),
+            record_input: self.record_input.unwrap_or(false),
+            auto_reconnect: self.auto_reconnect.unwrap_or(false),
+            env: self.env.unwrap_or_default(),
+            initial_command: self.initial_command,
+            scrollback_bytes: self.scrollback_bytes.unwrap_or(ssh_session::DEFAULT_SCROLLBACK_BYTES),
+        }
+    }
+}
+
+// PTY Session Commands. The SSH limit covers connecting only; an open
+// terminal, and its automatic reconnects, don't hold a slot.
+#[tauri::command]
+async fn start_pty_session(
+    app_handle: tauri::AppHandle,
+    target: SshTarget,
     cols: u32,
     rows: u32,
+    args: Option<PtySessionArgs>,
 ) -> Result<String, String> {
-    SESSION_MANAGER.start_session(app_handle, host, port, username, password, cols, rows)
+    let options = args.unwrap_or_default().into_options(cols, rows);
+    run_ssh(target, move |target| {
+        SESSION_MANAGER.start_session(app_handle, target.clone(), options)
+    })
+    .await
 }
 
 #[tauri::command]
@@ -276,55 +1573,105 @@ fn close_pty_session(session_id: String) -> Result<(), String> {
     SESSION_MANAGER.close_session(&session_id)
 }
 
+// How long shutdown waits for PTY reader threads to exit
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(2);
+
+// Stops everything holding a remote connection: terminals, follows, streamed
+// commands, port forwards and running searches, then the pooled connections.
+// Returns how many sessions and connections were closed.
+fn close_everything() -> usize {
+    for flag in ACTIVE_SEARCHES.iter().chain(ACTIVE_CHAIN_TRACES.iter()) {
+        flag.value().store(true, Ordering::SeqCst);
+    }
+    let closed = SESSION_MANAGER.close_all(SHUTDOWN_TIMEOUT)
+        + FOLLOW_MANAGER.stop_all()
+        + COMMAND_MANAGER.cancel_all()
+        + PORT_FORWARD_MANAGER.stop_all()
+        + CONNECTION_POOL.close_all();
+    tracing::info!(closed, "closed all sessions");
+    closed
+}
+
+/// Closes every PTY session, log follow, streamed command, port forward and
+/// pooled connection. Also run when the app exits.
+#[tauri::command]
+fn close_all_sessions() -> usize {
+    close_everything()
+}
+
+/// Recent output of a PTY session, for repainting the terminal after a reload.
+#[tauri::command]
+fn get_pty_scrollback(session_id: String) -> Result<String, String> {
+    SESSION_MANAGER.scrollback(&session_id)
+}
+
+/// Lists live PTY sessions so a reloaded frontend can reattach to them.
+#[tauri::command]
+fn list_pty_sessions() -> Vec<ssh_session::PtySessionInfo> {
+    SESSION_MANAGER.list_sessions()
+}
+
+/// Number of live PTY sessions; should drop back to 0 once all terminals are closed.
+#[tauri::command]
+fn session_count() -> usize {
+    SESSION_MANAGER.session_count()
+}
+
 // Chain node for server-based transaction chain tracing
-#[derive(Serialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ChainNode {
     pub filename: String,      // Log file name (e.g., comm-InboundGatewayService-1022199.log)
     pub dus_id: String,        // DESTDUS value (e.g., B001Y)
     pub ip: String,            // Node IP address
     pub log_path: String,      // Log directory path
     pub children: Vec<ChainNode>, // Child nodes in the chain
+    #[serde(default)]
+    pub fallback: bool,        // Found via the fallback app-log command rather than a hop line
 }
 
 // Result of chain tracing operation
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ChainTraceResult {
     pub nodes: Vec<ChainNode>,     // Chain node tree
     pub trace_log: Vec<String>,    // Trace progress logs
     pub total_hops: u32,           // Total number of hops traced
     pub duration_ms: u64,          // Total time taken
     pub error: Option<String>,     // Error message if any
+    #[serde(default)]
+    pub loops: Vec<RoutingLoop>,   // Hops that route back to a server earlier on their own branch
+}
+
+// A hop from `from_ip` to `to_ip`, which already appears earlier on the same
+// branch of the chain, so following it would go round in circles
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoutingLoop {
+    pub from_ip: String,
+    pub to_ip: String,
+}
+
+// The closing trace_log warning listing every routing loop, if there were any
+fn routing_loop_warning(loops: &[RoutingLoop]) -> Option<String> {
+    if loops.is_empty() {
+        return None;
+    }
+    let pairs: Vec<String> = loops.iter().map(|l| format!("{} -> {}", l.from_ip, l.to_ip)).collect();
+    Some(format!(
+        "[WARN] {} routing loop(s) detected, check the routing configuration: {}",
+        loops.len(),
+        pairs.join(", ")
+    ))
 }
 
 // Helper function to execute SSH command and get output
+#[tracing::instrument(skip_all, fields(host = %target.host, command = %command), err)]
 fn execute_ssh_for_chain(
-    host: &str,
-    port: u16,
-    username: &str,
-    password: &str,
+    target: &SshTarget,
     command: &str,
+    retry: RetryPolicy,
+    on_retry: impl FnMut(u32, &str, Duration),
 ) -> Result<String, String> {
-    let addr = format!("{}:{}", host, port);
-    
-    let tcp = TcpStream::connect(&addr)
-        .map_err(|e| format!("Connection to {} failed: {}", host, e))?;
-    
-    tcp.set_read_timeout(Some(std::time::Duration::from_secs(60)))
-        .map_err(|e| format!("Failed to set timeout: {}", e))?;
-    
-    let mut sess = Session::new()
-        .map_err(|e| format!("Session failed: {}", e))?;
-    
-    sess.set_tcp_stream(tcp);
-    sess.handshake()
-        .map_err(|e| format!("Handshake failed: {}", e))?;
-    
-    sess.userauth_password(username, password)
-        .map_err(|e| format!("Auth failed on {}: {}", host, e))?;
-    
-    if !sess.authenticated() {
-        return Err(format!("Authentication failed on {}", host));
-    }
+    let sess = ssh_connect::connect_with_retry(target, Duration::from_secs(60), retry, on_retry)
+        .map_err(|e| format!("{} ({})", e, target.host))?;
     
     let mut channel = sess.channel_session()
         .map_err(|e| format!("Channel failed: {}", e))?;
@@ -354,200 +1701,663 @@ fn parse_chain_line(line: &str) -> Option<(String, String, String)> {
     }
 }
 
-// Check if DUS ID is a valid node (B or C prefix) vs router (G prefix)
-fn is_valid_chain_node(dus_id: &str) -> bool {
-    dus_id.starts_with('B') || dus_id.starts_with('C')
+const DEFAULT_CHAIN_FALLBACK_COMMAND: &str = "cd {log_path} && find . -maxdepth 1 -name \"*app*log*\" -print0 | xargs -0 -P $(nproc) grep -H -F -e {trace_id} 2>/dev/null | awk -F: '/dusCode/ { filename = $1; sub(/^\\.\\//, \"\", filename); text = $0; sub(/.*dusCode : /, \"\", text); split(text, codes, \" \"); print filename, \" \", codes[1] }'";
+
+// Describes how hops are recognised in a particular log format.
+// The defaults match the DESTDUS/PEER gateway logs.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ChainTraceConfig {
+    pub dest_field: String,              // Field holding the next node's DUS ID (e.g., DESTDUS)
+    pub peer_field: String,              // Field holding the next node's IP (e.g., PEER)
+    pub node_prefixes: Vec<String>,      // DUS ID prefixes of nodes worth following (e.g., B, C)
+    pub router_prefixes: Vec<String>,    // DUS ID prefixes of routers (e.g., G)
+    pub fallback_command: String,        // Run when only routers are found; {log_path} and {trace_id} are substituted shell-quoted
+    pub connect_attempts: u32,           // Tries per hop before giving up on network/handshake errors
+    pub retry_backoff_ms: u64,           // Wait before the first retry, doubled after each failure
 }
 
-// Recursive chain tracing function
-fn trace_chain_recursive(
-    host: &str,
-    port: u16,
-    username: &str,
-    password: &str,
-    trace_id: &str,
-    log_path: &str,
-    trace_log: &mut Vec<String>,
-    visited_ips: &mut std::collections::HashSet<String>,
-    known_servers: &Vec<ServerConfig>,
-    depth: u32,
-    max_depth: u32,
-) -> Result<Vec<ChainNode>, String> {
-    if depth >= max_depth {
-        trace_log.push(format!("[WARN] Max depth {} reached at {}", max_depth, host));
-        return Ok(Vec::new());
+impl Default for ChainTraceConfig {
+    fn default() -> Self {
+        Self {
+            dest_field: "DESTDUS".to_string(),
+            peer_field: "PEER".to_string(),
+            node_prefixes: vec!["B".to_string(), "C".to_string()],
+            router_prefixes: vec!["G".to_string()],
+            fallback_command: DEFAULT_CHAIN_FALLBACK_COMMAND.to_string(),
+            connect_attempts: 3,
+            retry_backoff_ms: 500,
+        }
     }
-    
-    if visited_ips.contains(host) {
-        trace_log.push(format!("[SKIP] Already visited: {}", host));
-        return Ok(Vec::new());
+}
+
+impl ChainTraceConfig {
+    // Field names end up inside a sed expression, so only allow plain identifiers
+    fn validate(&self) -> Result<(), String> {
+        for field in [&self.dest_field, &self.peer_field] {
+            if field.is_empty() || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(format!("Invalid chain trace field name: {:?}", field));
+            }
+        }
+        Ok(())
     }
-    visited_ips.insert(host.to_string());
-    
-    trace_log.push(format!("[{}] Searching on {} ...", depth + 1, host));
-    
-    // Build the search command
-    let command = format!(
-        "cd {} && find . -maxdepth 1 -name \"*log*\" -print0 | xargs -0 -P $(nproc) grep -H -F '{}' 2>/dev/null | grep -F 'PEER' | sed -n 's/^\\([^:]*\\):.*DESTDUS=\\([^|]*\\).*PEER=\\([0-9.]*\\).*/\\1 \\2 \\3/p' | grep -v 'N/A' | sort -u",
-        log_path, trace_id
-    );
-    
-    let output = execute_ssh_for_chain(host, port, username, password, &command)?;
-    
-    let lines: Vec<&str> = output.lines().filter(|l| !l.is_empty()).collect();
-    
-    // Check if we need fallback (no results or only G-codes)
-    let has_non_g = lines.iter().any(|l| parse_chain_line(l).map(|(_, id, _)| !id.starts_with('G')).unwrap_or(false));
-    let mut fallback_nodes = Vec::new();
 
-    if lines.is_empty() || !has_non_g {
-        trace_log.push(format!("[{}] Checking backup app logs on {}...", depth + 1, host));
-        // Use user-provided fallback command to find app logs containing the trace ID
-        let fb_cmd = format!(
-            "cd {} && find . -maxdepth 1 -name \"*app*log*\" -print0 | xargs -0 -P $(nproc) grep -H -F '{}' 2>/dev/null | awk -F: '/dusCode/ {{ filename = $1; sub(/^\\.\\//, \"\", filename); text = $0; sub(/.*dusCode : /, \"\", text); split(text, codes, \" \"); print filename, \" \", codes[1] }}'",
-            log_path, trace_id
+    // Check if DUS ID is a valid node (e.g., B or C prefix) vs router (e.g., G prefix)
+    fn is_node(&self, dus_id: &str) -> bool {
+        self.node_prefixes.iter().any(|p| dus_id.starts_with(p.as_str()))
+    }
+
+    fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            attempts: self.connect_attempts.max(1),
+            backoff: Duration::from_millis(self.retry_backoff_ms),
+        }
+    }
+
+    fn is_router(&self, dus_id: &str) -> bool {
+        self.router_prefixes.iter().any(|p| dus_id.starts_with(p.as_str()))
+    }
+
+    fn search_command(&self, log_path: &str, trace_id: &str) -> String {
+        format!(
+            "cd {} && find . -maxdepth 1 -name \"*log*\" -print0 | xargs -0 -P $(nproc) grep -H -F -e {} 2>/dev/null | grep -F '{}' | sed -n 's/^\\([^:]*\\):.*{}=\\([^|]*\\).*{}=\\([0-9.]*\\).*/\\1 \\2 \\3/p' | grep -v 'N/A' | sort -u",
+            shell_quote(log_path), shell_quote(trace_id), self.peer_field, self.dest_field, self.peer_field
+        )
+    }
+
+    fn fallback_command(&self, log_path: &str, trace_id: &str) -> String {
+        self.fallback_command
+            .replace("{log_path}", &shell_quote(log_path))
+            .replace("{trace_id}", &shell_quote(trace_id))
+    }
+}
+
+const DEFAULT_CHAIN_MAX_DEPTH: u32 = 10;
+const DEFAULT_CHAIN_CONCURRENCY: usize = 4;
+
+// Counting semaphore bounding how many hops run SSH commands at the same time
+struct ConcurrencyLimit {
+    available: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl ConcurrencyLimit {
+    fn new(permits: usize) -> Self {
+        Self {
+            available: Mutex::new(permits.max(1)),
+            freed: Condvar::new(),
+        }
+    }
+
+    // Waits for a free slot; it is handed back when the guard is dropped
+    fn acquire(&self) -> Result<ConcurrencySlot<'_>, String> {
+        let mut available = self.available.lock().map_err(|_| "Lock failed")?;
+        while *available == 0 {
+            available = self.freed.wait(available).map_err(|_| "Lock failed")?;
+        }
+        *available -= 1;
+        Ok(ConcurrencySlot { limit: self })
+    }
+}
+
+// A taken `ConcurrencyLimit` slot. Returned on drop, so a hop that fails or
+// panics can't leave the others waiting forever.
+struct ConcurrencySlot<'a> {
+    limit: &'a ConcurrencyLimit,
+}
+
+impl Drop for ConcurrencySlot<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut available) = self.limit.available.lock() {
+            *available += 1;
+        }
+        self.limit.freed.notify_one();
+    }
+}
+
+// State shared by all hops of one chain trace. Child hops are traced on
+// parallel threads, so everything mutable sits behind a lock.
+struct ChainTraceContext<'a> {
+    trace_id: &'a str,
+    log_path: &'a str,
+    known_servers: &'a [ServerConfig],
+    config: &'a ChainTraceConfig,
+    // Number of hops followed past the starting server; 0 searches only the start
+    max_depth: u32,
+    visited_ips: Mutex<HashSet<String>>,
+    loops: Mutex<Vec<RoutingLoop>>,
+    trace_log: Mutex<Vec<String>>,
+    ssh_limit: ConcurrencyLimit,
+    cancelled: &'a AtomicBool,
+}
+
+impl ChainTraceContext<'_> {
+    fn log(&self, line: String) {
+        if let Ok(mut trace_log) = self.trace_log.lock() {
+            trace_log.push(line);
+        }
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    // Records and logs a hop back to a server on its own branch, once per pair
+    fn record_loop(&self, from_ip: &str, to_ip: &str) {
+        let routing_loop = RoutingLoop {
+            from_ip: from_ip.to_string(),
+            to_ip: to_ip.to_string(),
+        };
+        if let Ok(mut loops) = self.loops.lock() {
+            if loops.contains(&routing_loop) {
+                return;
+            }
+            loops.push(routing_loop);
+        }
+        self.log(format!(
+            "[LOOP] Routing loop: {} routes back to {}, which is already on this chain",
+            from_ip, to_ip
+        ));
+    }
+
+    fn exec(&self, target: &SshTarget, command: &str) -> Result<String, String> {
+        let _slot = self.ssh_limit.acquire()?;
+        // Each connection takes its own slot of the process-wide limit.
+        // Hop threads aren't async, so the permit is waited for here.
+        let _permit = tauri::async_runtime::block_on(SSH_LIMITER.acquire())?;
+        // Checked after waiting for a slot, so queued hops stop promptly too
+        if self.is_cancelled() {
+            return Err("Chain trace cancelled".to_string());
+        }
+        execute_ssh_for_chain(target, command, self.config.retry_policy(), |attempt, error, wait| {
+            self.log(format!(
+                "[RETRY] {} attempt {} failed: {}; retrying in {}ms",
+                target.host,
+                attempt,
+                target.redact(error),
+                wait.as_millis()
+            ))
+        })
+        .map_err(|e| target.redact(&e))
+    }
+}
+
+// How long the greps of one hop are reused by later traces
+const TRACE_CACHE_TTL: Duration = Duration::from_secs(300);
+
+// What the greps on one host found, before any next hop is followed
+#[derive(Clone, Default)]
+struct HopScan {
+    entries: Vec<(String, String, String)>, // (filename, dus_id, next ip)
+    fallback: Vec<(String, String)>,        // (filename, dus_id) from the fallback command
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct TraceCacheKey {
+    host: String,
+    log_path: String,
+    trace_id: String,
+}
+
+struct CachedScan {
+    scan: HopScan,
+    // The search command used; a different chain config makes the entry a miss
+    command: String,
+    stored_at: std::time::Instant,
+}
+
+// Hop scans shared across traces, so re-running a trace or tracing ids that
+// share hops doesn't repeat the same SSH greps
+struct TraceCache {
+    entries: DashMap<TraceCacheKey, CachedScan>,
+}
+
+impl TraceCache {
+    fn get(&self, key: &TraceCacheKey, command: &str, ttl: Duration) -> Option<(HopScan, Duration)> {
+        let entry = self.entries.get(key)?;
+        let age = entry.stored_at.elapsed();
+        if age >= ttl || entry.command != command {
+            drop(entry);
+            self.entries.remove(key);
+            return None;
+        }
+        Some((entry.scan.clone(), age))
+    }
+
+    fn insert(&self, key: TraceCacheKey, command: String, scan: HopScan) {
+        self.entries.insert(
+            key,
+            CachedScan {
+                scan,
+                command,
+                stored_at: std::time::Instant::now(),
+            },
         );
+    }
+
+    fn clear(&self) -> usize {
+        let count = self.entries.len();
+        self.entries.clear();
+        count
+    }
+}
+
+lazy_static! {
+    static ref TRACE_CACHE: TraceCache = TraceCache {
+        entries: DashMap::new(),
+    };
+}
+
+// Runs the search (and, when it finds no real node, the fallback) command on one host
+fn scan_hop(ctx: &ChainTraceContext, target: &SshTarget, depth: u32, command: &str) -> Result<HopScan, String> {
+    let output = ctx.exec(target, command)?;
+    let lines: Vec<&str> = output.lines().filter(|l| !l.is_empty()).collect();
+    let mut scan = HopScan {
+        entries: lines.iter().filter_map(|l| parse_chain_line(l)).collect(),
+        fallback: Vec::new(),
+    };
+    
+    // Check if we need fallback (no results or only routers)
+    let has_non_router = scan.entries.iter().any(|(_, id, _)| !ctx.config.is_router(id));
+    if lines.is_empty() || !has_non_router {
+        ctx.log(format!("[{}] Checking backup app logs on {}...", depth + 1, target.host));
+        // Use the configured fallback command to find app logs containing the trace ID
+        let fb_cmd = ctx.config.fallback_command(ctx.log_path, ctx.trace_id);
         
-        if let Ok(fb_out) = execute_ssh_for_chain(host, port, username, password, &fb_cmd) {
+        if let Ok(fb_out) = ctx.exec(target, &fb_cmd) {
             for l in fb_out.lines().filter(|l| !l.is_empty()) {
-                 let parts: Vec<&str> = l.split_whitespace().collect();
-                 if parts.len() >= 2 {
-                      let filename = parts[0].to_string();
-                      let dus_id = parts[1].to_string();
-                      
-                      fallback_nodes.push(ChainNode {
-                          filename: filename.clone(),
-                          dus_id: dus_id.clone(),
-                          ip: host.to_string(), // Keep current IP
-                          log_path: log_path.to_string(),
-                          children: Vec::new(),
-                      });
-                      trace_log.push(format!("  -> [Fallback] found {} {} on {}", filename, dus_id, host));
-                 }
+                let parts: Vec<&str> = l.split_whitespace().collect();
+                if parts.len() >= 2 {
+                    scan.fallback.push((parts[0].to_string(), parts[1].to_string()));
+                }
             }
         }
     }
+    Ok(scan)
+}
 
-    if lines.is_empty() && fallback_nodes.is_empty() {
-        trace_log.push(format!("[{}] No results found on {}", depth + 1, host));
+// Recursive chain tracing function. `ancestors` are the hosts between the
+// start and `target`, to tell routing loops from branches that merge.
+fn trace_chain_recursive(
+    ctx: &ChainTraceContext,
+    target: &SshTarget,
+    depth: u32,
+    ancestors: &[String],
+) -> Result<Vec<ChainNode>, String> {
+    let host = target.host.as_str();
+    let trace_id = ctx.trace_id;
+    let log_path = ctx.log_path;
+    
+    if ctx.is_cancelled() {
         return Ok(Vec::new());
     }
     
-    trace_log.push(format!("[{}] Found {} entries on {}", depth + 1, lines.len(), host));
+    // Check and mark in one step so two parallel branches can't both claim a host
+    if !ctx.visited_ips.lock().map_err(|_| "Lock failed")?.insert(host.to_string()) {
+        ctx.log(format!("[SKIP] Already visited: {}", host));
+        return Ok(Vec::new());
+    }
     
-    let mut nodes: Vec<ChainNode> = Vec::new();
+    ctx.log(format!("[{}] Searching on {} ...", depth + 1, host));
     
-    for line in lines {
-        if let Some((filename, dus_id, ip)) = parse_chain_line(line) {
-            let is_valid = is_valid_chain_node(&dus_id);
-            let node_type = if is_valid { "有效节点" } else { "路由节点" };
-            trace_log.push(format!("  -> {} {} {} ({})", filename, dus_id, ip, node_type));
-            
-            // Recursively trace valid nodes (B/C prefix)
-            let children = if is_valid && !visited_ips.contains(&ip) {
-                // Validate next hop against known servers
-                if let Some(next_server) = known_servers.iter().find(|s| s.host == ip) {
-                    trace_chain_recursive(
-                        &next_server.host,
-                        next_server.port,
-                        &next_server.username,
-                        &next_server.password,
-                        trace_id,
-                        log_path,
-                        trace_log,
-                        visited_ips,
-                        known_servers,
-                        depth + 1,
-                        max_depth,
-                    ).unwrap_or_else(|e| {
-                        trace_log.push(format!("[ERROR] Failed to trace {}: {}", ip, e));
-                        Vec::new()
-                    })
-                } else {
-                    trace_log.push(format!("[ERROR] 发现下一节点 IP {} 不在配置列表中。请先在服务器配置中添加该节点才能继续追踪。", ip));
-                    Vec::new()
-                }
-            } else {
-                Vec::new()
-            };
-            
-            nodes.push(ChainNode {
+    // Build the search command
+    let command = ctx.config.search_command(log_path, trace_id);
+    let cache_key = TraceCacheKey {
+        host: host.to_string(),
+        log_path: log_path.to_string(),
+        trace_id: trace_id.to_string(),
+    };
+    
+    let scan = match TRACE_CACHE.get(&cache_key, &command, TRACE_CACHE_TTL) {
+        Some((scan, age)) => {
+            ctx.log(format!("[CACHE] Using results for {} cached {}s ago", host, age.as_secs()));
+            scan
+        }
+        None => {
+            let scan = scan_hop(ctx, target, depth, &command)?;
+            // A cancelled fallback looks like an empty one, so don't remember it
+            if !ctx.is_cancelled() {
+                TRACE_CACHE.insert(cache_key, command, scan.clone());
+            }
+            scan
+        }
+    };
+    let HopScan { entries, fallback } = scan;
+    
+    let fallback_nodes: Vec<ChainNode> = fallback
+        .into_iter()
+        .map(|(filename, dus_id)| {
+            ctx.log(format!("  -> [Fallback] found {} {} on {}", filename, dus_id, host));
+            ChainNode {
                 filename,
                 dus_id,
-                ip: host.to_string(),
+                ip: host.to_string(), // Keep current IP
                 log_path: log_path.to_string(),
-                children,
-            });
-        }
+                children: Vec::new(),
+                fallback: true,
+            }
+        })
+        .collect();
+
+    if entries.is_empty() && fallback_nodes.is_empty() {
+        ctx.log(format!("[{}] No results found on {}", depth + 1, host));
+        return Ok(Vec::new());
     }
     
+    ctx.log(format!("[{}] Found {} entries on {}", depth + 1, entries.len(), host));
+    
+    let mut branch = ancestors.to_vec();
+    branch.push(host.to_string());
+    let branch = &branch;
+    // Trace the next hop of every valid node (B/C prefix) in parallel
+    let children: Vec<Vec<ChainNode>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = entries
+            .iter()
+            .map(|(filename, dus_id, ip)| {
+                let is_valid = ctx.config.is_node(dus_id);
+                let node_type = if is_valid { "有效节点" } else { "路由节点" };
+                ctx.log(format!("  -> {} {} {} ({})", filename, dus_id, ip, node_type));
+                
+                if is_valid && branch.contains(ip) {
+                    ctx.record_loop(host, ip);
+                    return None;
+                }
+                let already_visited = ctx
+                    .visited_ips
+                    .lock()
+                    .map(|v| v.contains(ip))
+                    .unwrap_or(true);
+                if !is_valid || already_visited {
+                    return None;
+                }
+                if depth >= ctx.max_depth {
+                    ctx.log(format!("[WARN] Max depth {} reached at {}", ctx.max_depth, host));
+                    return None;
+                }
+                
+                // Validate next hop against known servers
+                match ctx.known_servers.iter().find(|s| &s.host == ip) {
+                    Some(next_server) => Some(scope.spawn(move || {
+                        trace_chain_recursive(ctx, &next_server.target(), depth + 1, branch).unwrap_or_else(|e| {
+                            if !ctx.is_cancelled() {
+                                ctx.log(format!("[ERROR] Failed to trace {}: {}", ip, e));
+                            }
+                            Vec::new()
+                        })
+                    })),
+                    None => {
+                        ctx.log(format!("[ERROR] 发现下一节点 IP {} 不在配置列表中。请先在服务器配置中添加该节点才能继续追踪。", ip));
+                        None
+                    }
+                }
+            })
+            .collect();
+        
+        handles
+            .into_iter()
+            .map(|h| h.map(|h| h.join().unwrap_or_default()).unwrap_or_default())
+            .collect()
+    });
+    
+    let mut nodes: Vec<ChainNode> = entries
+        .into_iter()
+        .zip(children)
+        .map(|((filename, dus_id, _), children)| ChainNode {
+            filename,
+            dus_id,
+            ip: host.to_string(),
+            log_path: log_path.to_string(),
+            children,
+            fallback: false,
+        })
+        .collect();
+    
     nodes.extend(fallback_nodes);
     
     Ok(nodes)
 }
 
-#[tauri::command]
-async fn trace_server_chain(
-    host: String,
-    port: u16,
-    username: String,
-    password: String,
+lazy_static! {
+    // Cancellation flags of chains started with `start_chain_trace`, keyed by handle id
+    static ref ACTIVE_CHAIN_TRACES: DashMap<String, Arc<AtomicBool>> = DashMap::new();
+}
+
+// Sent when a chain started with `start_chain_trace` finishes or is cancelled
+#[derive(Clone, Serialize)]
+pub struct ChainTraceComplete {
+    pub handle_id: String,
+    pub result: ChainTraceResult,
+}
+
+// Everything a chain trace needs, gathered from the command arguments
+struct ChainTraceRequest {
+    target: SshTarget,
     trace_id: String,
     log_path: String,
     known_servers: Vec<ServerConfig>,
-) -> Result<ChainTraceResult, String> {
+    max_depth: Option<u32>,
+    max_concurrency: Option<usize>,
+    config: ChainTraceConfig,
+    // Only log the commands that would run instead of connecting anywhere
+    dry_run: bool,
+}
+
+// Runs a whole chain trace on the current thread. When `cancelled` is set the
+// crawl unwinds and whatever was found so far is returned.
+#[tracing::instrument(skip_all, fields(host = %request.target.host, trace_id = %request.trace_id))]
+fn run_chain_trace(request: ChainTraceRequest, cancelled: &AtomicBool) -> ChainTraceResult {
     let start_time = std::time::Instant::now();
+    let ChainTraceRequest {
+        target,
+        trace_id,
+        log_path,
+        known_servers,
+        max_depth,
+        max_concurrency,
+        config,
+        dry_run,
+    } = request;
     
-    let result = tokio::task::spawn_blocking(move || {
-        let mut trace_log: Vec<String> = Vec::new();
-        let mut visited_ips: std::collections::HashSet<String> = std::collections::HashSet::new();
-        
-        trace_log.push(format!("=== 开始追踪交易链路 ==="));
-        trace_log.push(format!("流水号: {}", trace_id));
-        trace_log.push(format!("起始服务器: {}", host));
-        trace_log.push(format!("日志路径: {}", log_path));
-        trace_log.push(String::new());
+    let result = {
+        let ctx = ChainTraceContext {
+            trace_id: &trace_id,
+            log_path: &log_path,
+            known_servers: &known_servers,
+            config: &config,
+            max_depth: max_depth.unwrap_or(DEFAULT_CHAIN_MAX_DEPTH),
+            visited_ips: Mutex::new(HashSet::new()),
+            loops: Mutex::new(Vec::new()),
+            trace_log: Mutex::new(Vec::new()),
+            ssh_limit: ConcurrencyLimit::new(max_concurrency.unwrap_or(DEFAULT_CHAIN_CONCURRENCY)),
+            cancelled,
+        };
         
-        let nodes = trace_chain_recursive(
-            &host,
-            port,
-            &username,
-            &password,
-            &trace_id,
-            &log_path,
-            &mut trace_log,
-            &mut visited_ips,
-            &known_servers,
-            0,
-            10, // max depth
-        )?;
+        ctx.log("=== 开始追踪交易链路 ===".to_string());
+        ctx.log(format!("流水号: {}", trace_id));
+        ctx.log(format!("起始服务器: {}", target.host));
+        ctx.log(format!("日志路径: {}", log_path));
+        ctx.log(String::new());
         
-        let total_hops = visited_ips.len() as u32;
-        trace_log.push(String::new());
-        trace_log.push(format!("=== 追踪完成: 共访问 {} 个节点 ===", total_hops));
-        
-        Ok::<(Vec<ChainNode>, Vec<String>, u32), String>((nodes, trace_log, total_hops))
-    })
-    .await
-    .map_err(|e| format!("Task failed: {}", e))?;
+        if dry_run {
+            ctx.log("[DRY RUN] Commands are shown but not executed".to_string());
+            ctx.log(format!("[1] Search command on {}:", target.host));
+            ctx.log(config.search_command(&log_path, &trace_id));
+            ctx.log(format!("[1] Fallback command on {} (when only routers are found):", target.host));
+            ctx.log(config.fallback_command(&log_path, &trace_id));
+            ctx.log("[DRY RUN] Every further hop runs the same two commands on its own server".to_string());
+            Ok((Vec::new(), ctx.trace_log.into_inner().unwrap_or_default(), 0, Vec::new()))
+        } else {
+            trace_chain_recursive(&ctx, &target, 0, &[]).map(|nodes| {
+                let total_hops = ctx.visited_ips.lock().map(|v| v.len()).unwrap_or(0) as u32;
+                let loops = ctx.loops.lock().map(|l| l.clone()).unwrap_or_default();
+                ctx.log(String::new());
+                if let Some(warning) = routing_loop_warning(&loops) {
+                    ctx.log(warning);
+                }
+                if ctx.is_cancelled() {
+                    ctx.log(format!("=== 追踪已取消: 已访问 {} 个节点，结果不完整 ===", total_hops));
+                } else {
+                    ctx.log(format!("=== 追踪完成: 共访问 {} 个节点 ===", total_hops));
+                }
+            
+                let trace_log = ctx.trace_log.into_inner().unwrap_or_default();
+                (nodes, trace_log, total_hops, loops)
+            })
+        }
+    };
     
     let duration_ms = start_time.elapsed().as_millis() as u64;
     
     match result {
-        Ok((nodes, trace_log, total_hops)) => Ok(ChainTraceResult {
+        Ok((nodes, trace_log, total_hops, loops)) => ChainTraceResult {
             nodes,
             trace_log,
             total_hops,
             duration_ms,
             error: None,
-        }),
-        Err(e) => Ok(ChainTraceResult {
+            loops,
+        },
+        Err(e) => ChainTraceResult {
             nodes: Vec::new(),
             trace_log: vec![format!("Error: {}", e)],
             total_hops: 0,
             duration_ms,
             error: Some(e),
-        }),
+            loops: Vec::new(),
+        },
+    }
+}
+
+/// Optional settings of `trace_server_chain` and `start_chain_trace`.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct ChainTraceOptions {
+    pub max_depth: Option<u32>,       // Hops followed past the start (default `DEFAULT_CHAIN_MAX_DEPTH`)
+    pub max_concurrency: Option<usize>, // Hops searched at once (default `DEFAULT_CHAIN_CONCURRENCY`)
+    pub config: ChainTraceConfig,
+    pub dry_run: bool,                // Only log the commands that would run
+}
+
+fn chain_trace_request(
+    target: SshTarget,
+    trace_id: String,
+    log_path: String,
+    known_servers: Vec<ServerConfig>,
+    options: ChainTraceOptions,
+) -> Result<ChainTraceRequest, String> {
+    let ChainTraceOptions {
+        max_depth,
+        max_concurrency,
+        config,
+        dry_run,
+    } = options;
+    config.validate()?;
+    Ok(ChainTraceRequest {
+        target,
+        trace_id,
+        log_path,
+        known_servers,
+        max_depth,
+        max_concurrency,
+        config,
+        dry_run,
+    })
+}
+
+#[tauri::command]
+async fn trace_server_chain(
+    app_handle: tauri::AppHandle,
+    target: SshTarget,
+    trace_id: String,
+    log_path: String,
+    known_servers: Vec<ServerConfig>,
+    options: Option<ChainTraceOptions>,
+) -> Result<ChainTraceResult, String> {
+    let start = known_servers.iter().find(|s| s.host == target.host && s.port == target.port);
+    let log_path = log_path_or_default(&app_handle, log_path, start)?;
+    if let Some(start) = start {
+        record_recent_query(&app_handle, &start.id, &log_path, &trace_id);
+    }
+    let request = chain_trace_request(target, trace_id, log_path, known_servers, options.unwrap_or_default())?;
+    
+    // No permit here: every hop takes its own (see `ChainTraceContext::exec`)
+    tokio::task::spawn_blocking(move || run_chain_trace(request, &AtomicBool::new(false)))
+    .await
+    .map_err(|e| format!("Task failed: {}", e))
+}
+
+/// Starts a chain trace in the background and returns its handle id right away.
+/// The result arrives as a `chain-trace-complete` event; `cancel_chain_trace`
+/// stops it early with partial results.
+#[tauri::command]
+fn start_chain_trace(
+    app_handle: tauri::AppHandle,
+    target: SshTarget,
+    trace_id: String,
+    log_path: String,
+    known_servers: Vec<ServerConfig>,
+    options: Option<ChainTraceOptions>,
+) -> Result<String, String> {
+    let start = known_servers.iter().find(|s| s.host == target.host && s.port == target.port);
+    let log_path = log_path_or_default(&app_handle, log_path, start)?;
+    if let Some(start) = start {
+        record_recent_query(&app_handle, &start.id, &log_path, &trace_id);
+    }
+    let request = chain_trace_request(target, trace_id, log_path, known_servers, options.unwrap_or_default())?;
+    
+    let handle_id = uuid::Uuid::new_v4().to_string();
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_CHAIN_TRACES.insert(handle_id.clone(), cancelled.clone());
+    
+    let registered_id = handle_id.clone();
+    std::thread::spawn(move || {
+        let result = run_chain_trace(request, &cancelled);
+        ACTIVE_CHAIN_TRACES.remove(&registered_id);
+        let _ = app_handle.emit(
+            "chain-trace-complete",
+            ChainTraceComplete {
+                handle_id: registered_id,
+                result,
+            },
+        );
+    });
+    
+    Ok(handle_id)
+}
+
+/// Forgets all cached hop results so the next trace searches every server again.
+/// Returns the number of entries dropped.
+#[tauri::command]
+fn clear_trace_cache() -> usize {
+    TRACE_CACHE.clear()
+}
+
+/// Asks a running `start_chain_trace` to stop before its next SSH call.
+#[tauri::command]
+fn cancel_chain_trace(handle_id: String) -> bool {
+    match ACTIVE_CHAIN_TRACES.get(&handle_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// The nodes and parent-to-child edges of a finished chain trace, for drawing
+/// it as a graph without walking the tree in the frontend.
+#[tauri::command]
+fn flatten_chain(result: ChainTraceResult) -> chain_export::FlatChain {
+    chain_export::to_flat(&result)
+}
+
+// Renders a finished chain trace as "dot" (Graphviz) or "json" (adjacency list)
+#[tauri::command]
+fn export_chain_trace(result: ChainTraceResult, format: String) -> Result<String, String> {
+    match format.to_lowercase().as_str() {
+        "dot" => Ok(chain_export::to_dot(&result)),
+        "json" => chain_export::to_json(&result),
+        other => Err(format!("Unsupported export format: {}", other)),
     }
 }
 
@@ -557,221 +2367,2081 @@ pub struct LogFileInfo {
     pub path: String,
     pub name: String,
     pub match_count: u32,
+    pub compressed: bool,
+    // The first few matching lines, when the search asked for previews
+    pub preview: Option<Vec<String>>,
+    // Counting stopped at the search's `max_matches`
+    pub matches_truncated: bool,
 }
 
 // Search result for a single server
-#[derive(Serialize)]
+#[derive(Serialize, Clone)]
 pub struct LogSearchResult {
     pub server_id: String,
     pub host: String,
     pub files: Vec<LogFileInfo>,
     pub total_matches: u32,
+    pub files_truncated: bool,       // More files matched than `max_files`
     pub duration_ms: u64,
     pub error: Option<String>,
+    // Usage of the partition holding `log_path` and the directory's own size,
+    // `None` when `df`/`du` are unavailable
+    pub disk_used_percent: Option<f32>,
+    pub log_dir_size_bytes: Option<u64>,
+    // Cancelled part way: `files` and `total_matches` only cover the files
+    // grepped before that
+    pub aborted: bool,
+    // Some file hit `max_matches`, so `total_matches` is a lower bound
+    pub matches_truncated: bool,
 }
 
-#[tauri::command]
-async fn search_log_files(
-    host: String,
-    port: u16,
-    username: String,
-    password: String,
-    server_id: String,
-    log_path: String,
-    trace_id: String,
-) -> Result<LogSearchResult, String> {
-    let start_time = std::time::Instant::now();
-    let host_clone = host.clone();
-    let server_id_clone = server_id.clone();
-    
-    let result = tokio::task::spawn_blocking(move || {
-        let addr = format!("{}:{}", host, port);
-        
-        // Connect TCP
-        let tcp = TcpStream::connect(&addr)
-            .map_err(|e| format!("TCP connection failed: {}", e))?;
-        
-        tcp.set_read_timeout(Some(std::time::Duration::from_secs(30)))
-            .map_err(|e| format!("Failed to set timeout: {}", e))?;
-        
-        // Create SSH session
-        let mut sess = Session::new()
-            .map_err(|e| format!("Failed to create SSH session: {}", e))?;
-        
-        sess.set_tcp_stream(tcp);
-        sess.handshake()
-            .map_err(|e| format!("SSH handshake failed: {}", e))?;
-        
-        sess.userauth_password(&username, &password)
-            .map_err(|e| format!("Authentication failed: {}", e))?;
-        
-        if !sess.authenticated() {
-            return Err("Authentication failed".to_string());
+// How `trace_id` is matched when grepping log files
+#[derive(Clone, Copy, Default)]
+pub struct GrepOptions {
+    pub use_regex: bool,
+    pub case_insensitive: bool,
+}
+
+impl GrepOptions {
+    fn from_flags(use_regex: Option<bool>, case_insensitive: Option<bool>) -> Self {
+        Self {
+            use_regex: use_regex.unwrap_or(false),
+            case_insensitive: case_insensitive.unwrap_or(false),
         }
-        
-        // Find all files containing "log" in the filename (non-recursive, only current directory)
-        let find_cmd = format!(
-            "find {} -maxdepth 1 -type f -name '*log*' 2>/dev/null | head -100",
-            log_path
-        );
-        
-        let mut channel = sess.channel_session()
-            .map_err(|e| format!("Failed to open channel: {}", e))?;
-        channel.exec(&find_cmd)
-            .map_err(|e| format!("Failed to execute find command: {}", e))?;
-        
-        let mut find_output = String::new();
-        channel.read_to_string(&mut find_output)
-            .map_err(|e| format!("Failed to read find output: {}", e))?;
-        channel.wait_close().ok();
-        
-        let files: Vec<String> = find_output
-            .lines()
-            .filter(|s| !s.is_empty())
-            .map(|s| s.to_string())
-            .collect();
-        
-        if files.is_empty() {
-            return Ok((Vec::new(), 0u32));
+    }
+
+    // Builds `grep <flags> -e <pattern>`. The pattern is always passed through
+    // `shell_quote`, so quotes, `$` or backticks in a trace id reach grep verbatim
+    // instead of being interpreted by the remote shell. Without `use_regex` grep
+    // also treats it as a fixed string (-F) rather than a regular expression (-E).
+    fn grep_command(&self, program: &str, extra_flags: &str, pattern: &str) -> String {
+        let mut cmd = String::from(program);
+        cmd.push_str(if self.use_regex { " -E" } else { " -F" });
+        if self.case_insensitive {
+            cmd.push_str(" -i");
         }
-        
-        // If trace_id is provided, grep for it in each file
-        let mut file_infos: Vec<LogFileInfo> = Vec::new();
-        let mut total_matches: u32 = 0;
-        
-        for file_path in files {
-            let file_name = file_path
-                .split('/')
-                .last()
-                .unwrap_or(&file_path)
-                .to_string();
-            
-            let match_count = if !trace_id.is_empty() {
-                // Count matches for trace_id
-                let grep_cmd = format!(
-                    "grep -c '{}' '{}' 2>/dev/null || echo 0",
-                    trace_id, file_path
-                );
-                
-                let mut grep_channel = sess.channel_session()
-                    .map_err(|e| format!("Failed to open grep channel: {}", e))?;
-                grep_channel.exec(&grep_cmd)
-                    .map_err(|e| format!("Failed to execute grep: {}", e))?;
-                
-                let mut grep_output = String::new();
-                grep_channel.read_to_string(&mut grep_output).ok();
-                grep_channel.wait_close().ok();
-                
-                grep_output.trim().parse::<u32>().unwrap_or(0)
-            } else {
-                0
-            };
-            
-            total_matches += match_count;
-            
-            file_infos.push(LogFileInfo {
-                path: file_path,
-                name: file_name,
-                match_count,
-            });
+        if !extra_flags.is_empty() {
+            cmd.push(' ');
+            cmd.push_str(extra_flags);
         }
-        
-        // Sort by match count (descending) if trace_id was provided
-        if !trace_id.is_empty() {
-            // Filter out files with 0 matches when trace_id is provided
-            file_infos.retain(|f| f.match_count > 0);
-            file_infos.sort_by(|a, b| b.match_count.cmp(&a.match_count));
+        cmd.push_str(" -e ");
+        cmd.push_str(&shell_quote(pattern));
+        cmd
+    }
+}
+
+// Compression of a rotated log file, detected from its extension
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum LogCompression {
+    None,
+    Gzip,
+    Bzip2,
+}
+
+impl LogCompression {
+    fn detect(path: &str) -> Self {
+        if path.ends_with(".gz") {
+            LogCompression::Gzip
+        } else if path.ends_with(".bz2") {
+            LogCompression::Bzip2
+        } else {
+            LogCompression::None
         }
-        
-        Ok((file_infos, total_matches))
-    })
-    .await
-    .map_err(|e| format!("Task failed: {}", e))?;
-    
-    let duration_ms = start_time.elapsed().as_millis() as u64;
-    
-    match result {
-        Ok((files, total_matches)) => Ok(LogSearchResult {
-            server_id: server_id_clone,
-            host: host_clone,
-            files,
-            total_matches,
-            duration_ms,
-            error: None,
-        }),
-        Err(e) => Ok(LogSearchResult {
-            server_id: server_id_clone,
-            host: host_clone,
+    }
+
+    fn grep_program(self) -> &'static str {
+        match self {
+            LogCompression::None => "grep",
+            LogCompression::Gzip => "zgrep",
+            LogCompression::Bzip2 => "bzgrep",
+        }
+    }
+
+    // Command that writes the decompressed content to stdout
+    fn cat_program(self) -> Option<&'static str> {
+        match self {
+            LogCompression::None => None,
+            LogCompression::Gzip => Some("zcat"),
+            LogCompression::Bzip2 => Some("bzcat"),
+        }
+    }
+}
+
+const DEFAULT_SEARCH_MAX_DEPTH: u32 = 1;
+const DEFAULT_SEARCH_MAX_FILES: u32 = 1000;
+const DEFAULT_SEARCH_NAME_PATTERN: &str = "*log*";
+
+// How the patterns of a multi-pattern search combine on one line
+#[derive(Deserialize, Clone, Copy, Default, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum PatternMode {
+    // Every pattern (`grep a | grep b`)
+    #[default]
+    And,
+    // Any pattern (`grep -E 'a|b'`)
+    Or,
+}
+
+// Escapes ERE metacharacters so `pattern` matches literally inside `grep -E`
+fn escape_ere(pattern: &str) -> String {
+    let mut escaped = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        if "\\.[]()*+?{}|^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// Everything that shapes which files are searched and how they are grepped
+#[derive(Clone)]
+struct LogSearchOptions {
+    grep: GrepOptions,
+    // Searched for alongside `trace_id`, combined by `pattern_mode`
+    patterns: Vec<String>,
+    pattern_mode: PatternMode,
+    // `find -maxdepth`; 1 searches only the log directory itself
+    max_depth: u32,
+    // Upper bound on files grepped per server
+    max_files: u32,
+    // `find -name` globs; a file matching any of them is searched
+    name_patterns: Vec<String>,
+    // `find -newermt` dates bounding the files' modification time
+    modified_after: Option<String>,
+    modified_before: Option<String>,
+    // Fetch the first `PREVIEW_LINES` matching lines of each file with matches
+    preview: bool,
+    // Stop counting a file's matches here (`grep -m`)
+    max_matches: Option<u32>,
+    // Only search this many of the most recently modified files
+    newest_n: Option<u32>,
+}
+
+// The search settings the frontend sends; unset ones take their defaults
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LogSearchArgs {
+    use_regex: Option<bool>,
+    case_insensitive: Option<bool>,
+    max_depth: Option<u32>,
+    max_files: Option<u32>,
+    name_pattern: Option<String>,
+    modified_after: Option<String>,
+    modified_before: Option<String>,
+    patterns: Option<Vec<String>>,
+    pattern_mode: Option<PatternMode>,
+    with_preview: Option<bool>,
+    max_matches: Option<u32>,
+    newest_n: Option<u32>,
+}
+
+impl LogSearchArgs {
+    fn into_options(self) -> Result<LogSearchOptions, String> {
+        Ok(LogSearchOptions::from_args(
+            self.use_regex,
+            self.case_insensitive,
+            self.max_depth,
+            self.max_files,
+            self.name_pattern,
+        )
+        .with_modified_window(self.modified_after, self.modified_before)?
+        .with_patterns(self.patterns, self.pattern_mode)
+        .with_preview(self.with_preview)
+        .with_max_matches(self.max_matches)
+        .with_newest_n(self.newest_n))
+    }
+}
+
+// Matching lines shown per file in a search preview, and the characters kept
+// of each, so a huge or binary line doesn't balloon the result
+const PREVIEW_LINES: u32 = 3;
+const PREVIEW_LINE_CHARS: usize = 300;
+
+// At most `PREVIEW_LINE_CHARS` characters of `line`, marked when cut
+fn truncate_preview_line(line: &str) -> String {
+    match line.char_indices().nth(PREVIEW_LINE_CHARS) {
+        Some((cut, _)) => format!("{}…", &line[..cut]),
+        None => line.to_string(),
+    }
+}
+
+// Turns a `modified_after`/`modified_before` value into a date `find -newermt`
+// understands: ISO timestamps pass through, relative values like `-7d` or
+// `-12h` become "7 days ago". Empty values mean no bound.
+fn parse_modified_time(value: Option<&str>) -> Result<Option<String>, String> {
+    let value = match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+    if let Some(relative) = value.strip_prefix('-') {
+        let invalid = || format!("Invalid relative time {:?}: use e.g. -30m, -12h, -7d or -2w", value);
+        let (amount, unit) = [("m", "minutes"), ("h", "hours"), ("d", "days"), ("w", "weeks")]
+            .into_iter()
+            .find_map(|(suffix, unit)| relative.strip_suffix(suffix).map(|amount| (amount, unit)))
+            .ok_or_else(invalid)?;
+        let amount: u32 = amount.parse().map_err(|_| invalid())?;
+        return Ok(Some(format!("{} {} ago", amount, unit)));
+    }
+    let looks_like_iso = value.starts_with(|c: char| c.is_ascii_digit())
+        && value
+            .chars()
+            .all(|c| c.is_ascii_digit() || "-:T .+Z".contains(c));
+    if !looks_like_iso {
+        return Err(format!("Invalid timestamp {:?}: expected e.g. 2024-05-01T08:00:00", value));
+    }
+    Ok(Some(value.to_string()))
+}
+
+impl Default for LogSearchOptions {
+    fn default() -> Self {
+        Self {
+            grep: GrepOptions::default(),
+            max_depth: DEFAULT_SEARCH_MAX_DEPTH,
+            max_files: DEFAULT_SEARCH_MAX_FILES,
+            patterns: Vec::new(),
+            pattern_mode: PatternMode::default(),
+            name_patterns: vec![DEFAULT_SEARCH_NAME_PATTERN.to_string()],
+            modified_after: None,
+            modified_before: None,
+            preview: false,
+            max_matches: None,
+            newest_n: None,
+        }
+    }
+}
+
+impl LogSearchOptions {
+    // `name_pattern` may hold several comma-separated globs, e.g. "*log*,*.out"
+    fn from_args(
+        use_regex: Option<bool>,
+        case_insensitive: Option<bool>,
+        max_depth: Option<u32>,
+        max_files: Option<u32>,
+        name_pattern: Option<String>,
+    ) -> Self {
+        let mut name_patterns: Vec<String> = name_pattern
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+            .map(str::to_string)
+            .collect();
+        if name_patterns.is_empty() {
+            name_patterns.push(DEFAULT_SEARCH_NAME_PATTERN.to_string());
+        }
+        Self {
+            grep: GrepOptions::from_flags(use_regex, case_insensitive),
+            patterns: Vec::new(),
+            pattern_mode: PatternMode::default(),
+            max_depth: max_depth.unwrap_or(DEFAULT_SEARCH_MAX_DEPTH).max(1),
+            max_files: max_files.unwrap_or(DEFAULT_SEARCH_MAX_FILES).max(1),
+            name_patterns,
+            modified_after: None,
+            modified_before: None,
+            preview: false,
+            max_matches: None,
+            newest_n: None,
+        }
+    }
+
+    // Also return the first matching lines of each file
+    fn with_preview(mut self, preview: Option<bool>) -> Self {
+        self.preview = preview.unwrap_or(false);
+        self
+    }
+
+    // Count at most `max_matches` matching lines per file; 0 means no cap
+    fn with_max_matches(mut self, max_matches: Option<u32>) -> Self {
+        self.max_matches = max_matches.filter(|max| *max > 0);
+        self
+    }
+
+    // Keep only the `newest_n` most recently modified files; 0 means all of them
+    fn with_newest_n(mut self, newest_n: Option<u32>) -> Self {
+        self.newest_n = newest_n.filter(|n| *n > 0);
+        self
+    }
+
+    // Only search files modified inside the given window (either end optional)
+    fn with_modified_window(
+        mut self,
+        modified_after: Option<String>,
+        modified_before: Option<String>,
+    ) -> Result<Self, String> {
+        self.modified_after = parse_modified_time(modified_after.as_deref())?;
+        self.modified_before = parse_modified_time(modified_before.as_deref())?;
+        Ok(self)
+    }
+
+    // Extra patterns each line is matched against besides `trace_id`
+    fn with_patterns(mut self, patterns: Option<Vec<String>>, pattern_mode: Option<PatternMode>) -> Self {
+        self.patterns = patterns
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| !p.is_empty())
+            .collect();
+        self.pattern_mode = pattern_mode.unwrap_or_default();
+        self
+    }
+
+    // `trace_id` (when set) followed by the extra patterns
+    fn search_patterns<'a>(&'a self, trace_id: &'a str) -> Vec<&'a str> {
+        let mut patterns: Vec<&str> = self.patterns.iter().map(String::as_str).collect();
+        if !trace_id.is_empty() {
+            patterns.insert(0, trace_id);
+        }
+        patterns
+    }
+
+    // Prints how many lines of `file` (already shell-quoted) match the search.
+    // None when there is nothing to match.
+    fn count_command(&self, compression: LogCompression, trace_id: &str, file: &str) -> Option<String> {
+        let flags = match self.max_matches {
+            Some(max) => format!("-c -m {}", max),
+            None => "-c".to_string(),
+        };
+        self.match_command(compression, trace_id, file, &flags)
+    }
+
+    // Prints the first `PREVIEW_LINES` matching lines of `file`, cut to a
+    // bounded width before they leave the server
+    fn preview_command(&self, compression: LogCompression, trace_id: &str, file: &str) -> Option<String> {
+        self.match_command(compression, trace_id, file, &format!("-m {}", PREVIEW_LINES))
+            .map(|cmd| format!("{} | cut -c 1-{}", cmd, PREVIEW_LINE_CHARS * 4))
+    }
+
+    // Greps `file` for the search patterns, with `flags` on the grep deciding
+    // the output: one grep, a `grep | grep` chain for "and", or a single
+    // `grep -E 'a|b'` for "or". Only the first grep reads the file, so only
+    // it needs the zgrep/bzgrep variant.
+    fn match_command(&self, compression: LogCompression, trace_id: &str, file: &str, flags: &str) -> Option<String> {
+        let patterns = self.search_patterns(trace_id);
+        let program = compression.grep_program();
+        match (self.pattern_mode, patterns.as_slice()) {
+            (_, []) => None,
+            (_, [pattern]) => Some(format!("{} {}", self.grep.grep_command(program, flags, pattern), file)),
+            (PatternMode::Or, _) => {
+                let alternation: Vec<String> = patterns
+                    .iter()
+                    .map(|p| if self.grep.use_regex { format!("({})", p) } else { escape_ere(p) })
+                    .collect();
+                let grep = GrepOptions {
+                    use_regex: true,
+                    ..self.grep
+                };
+                Some(format!("{} {}", grep.grep_command(program, flags, &alternation.join("|")), file))
+            }
+            (PatternMode::And, [first, rest @ ..]) => {
+                let mut cmd = format!("{} {}", self.grep.grep_command(program, "", first), file);
+                for (i, pattern) in rest.iter().enumerate() {
+                    let flags = if i + 1 == rest.len() { flags } else { "" };
+                    cmd.push_str(" | ");
+                    cmd.push_str(&self.grep.grep_command("grep", flags, pattern));
+                }
+                Some(cmd)
+            }
+        }
+    }
+
+    // Lists the files to search under `log_path`, one per line. One more than
+    // `max_files` is listed so the caller can tell when the cap cut the list
+    // short. With `newest_n`, files are ranked by mtime first (GNU find's
+    // `-printf`, like `-newermt` above).
+    fn list_files_command(&self, log_path: &str) -> String {
+        let find = format!(
+            "find {} -maxdepth {} -type f {}",
+            shell_quote(log_path),
+            self.max_depth,
+            self.find_predicates()
+        );
+        let newest = match self.newest_n {
+            Some(n) => format!(
+                " -printf '%T@ %p\\n' 2>/dev/null | sort -rn | head -n {} | cut -d ' ' -f 2-",
+                n
+            ),
+            None => " 2>/dev/null".to_string(),
+        };
+        format!("{}{} | head -{}", find, newest, self.max_files as u64 + 1)
+    }
+
+    // Everything after `find <dir> -maxdepth N -type f`: the name filter, then
+    // the modification time bounds
+    fn find_predicates(&self) -> String {
+        let mut predicates = self.find_name_filter();
+        if let Some(after) = &self.modified_after {
+            predicates.push_str(&format!(" -newermt {}", shell_quote(after)));
+        }
+        if let Some(before) = &self.modified_before {
+            predicates.push_str(&format!(" ! -newermt {}", shell_quote(before)));
+        }
+        predicates
+    }
+
+    // The `find` test selecting log files: `-name 'a'`, or with several patterns
+    // `\( -name 'a' -o -name 'b' \)`. Patterns are quoted, so only find expands them.
+    fn find_name_filter(&self) -> String {
+        let tests: Vec<String> = self
+            .name_patterns
+            .iter()
+            .map(|p| format!("-name {}", shell_quote(p)))
+            .collect();
+        if tests.len() == 1 {
+            tests.into_iter().next().unwrap_or_default()
+        } else {
+            format!("\\( {} \\)", tests.join(" -o "))
+        }
+    }
+}
+
+// Files found on one server, before being wrapped into a `LogSearchResult`
+struct SearchOutcome {
+    files: Vec<LogFileInfo>,
+    total_matches: u32,
+    files_truncated: bool,
+    usage: LogDirUsage,
+    aborted: bool,
+}
+
+// Disk context reported alongside a search, so an empty result can be told
+// apart from a full disk or rotated-away logs
+#[derive(Default, Debug, PartialEq)]
+struct LogDirUsage {
+    disk_used_percent: Option<f32>,
+    log_dir_size_bytes: Option<u64>,
+}
+
+impl LogDirUsage {
+    fn command(log_path: &str) -> String {
+        let path = shell_quote(log_path);
+        format!(
+            "df -P {} 2>/dev/null | awk 'NR==2 {{print \"df\", $5}}'; du -sk {} 2>/dev/null | awk '{{print \"du\", $1}}'",
+            path, path
+        )
+    }
+
+    fn parse(output: &str) -> Self {
+        let mut usage = LogDirUsage::default();
+        for line in output.lines() {
+            match line.split_once(' ') {
+                Some(("df", percent)) => {
+                    usage.disk_used_percent = percent.trim().trim_end_matches('%').parse().ok();
+                }
+                Some(("du", kb)) => {
+                    usage.log_dir_size_bytes = kb.trim().parse::<u64>().ok().map(|kb| kb * 1024);
+                }
+                _ => {}
+            }
+        }
+        usage
+    }
+
+    // Any failure just leaves the fields empty; it never fails the search
+    fn fetch(sess: &Session, log_path: &str) -> Self {
+        let output = (|| {
+            let mut channel = sess.channel_session().ok()?;
+            channel.exec(&Self::command(log_path)).ok()?;
+            let mut output = String::new();
+            channel.read_to_string(&mut output).ok()?;
+            channel.wait_close().ok();
+            Some(output)
+        })();
+        output.map(|o| Self::parse(&o)).unwrap_or_default()
+    }
+}
+
+// Per-file search callback receiving (file, files_done, files_total, running_total)
+type FileProgressFn<'a> = dyn Fn(&LogFileInfo, u32, u32, u32) + Sync + 'a;
+
+// Optional observers for a running search
+#[derive(Default)]
+struct SearchHooks<'a> {
+    on_file: Option<&'a FileProgressFn<'a>>,
+    cancelled: Option<&'a AtomicBool>,
+}
+
+impl SearchHooks<'_> {
+    fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .map(|flag| flag.load(Ordering::SeqCst))
+            .unwrap_or(false)
+    }
+}
+
+// Emitted by `search_log_files_stream` after each file has been grepped
+#[derive(Serialize, Clone)]
+pub struct LogSearchProgress {
+    pub search_id: String,
+    pub server_id: String,
+    pub file: LogFileInfo,
+    pub files_done: u32,
+    pub files_total: u32,
+    pub total_matches: u32,
+}
+
+lazy_static! {
+    // Cancellation flags of streaming searches, keyed by the caller-provided search id
+    static ref ACTIVE_SEARCHES: DashMap<String, Arc<AtomicBool>> = DashMap::new();
+    // Cancellation flag of the latest search running on each server, keyed by server id
+    static ref SERVER_SEARCHES: DashMap<String, Arc<AtomicBool>> = DashMap::new();
+}
+
+// Makes `flag` cancellable through `cancel_search(server_id)` until dropped.
+// A newer search on the same server takes over the entry, so dropping an
+// older guard leaves the newer registration alone.
+struct ServerSearchGuard {
+    server_id: String,
+    flag: Arc<AtomicBool>,
+}
+
+impl ServerSearchGuard {
+    fn register(server_id: &str, flag: Arc<AtomicBool>) -> Self {
+        SERVER_SEARCHES.insert(server_id.to_string(), flag.clone());
+        Self {
+            server_id: server_id.to_string(),
+            flag,
+        }
+    }
+}
+
+impl Drop for ServerSearchGuard {
+    fn drop(&mut self) {
+        SERVER_SEARCHES.remove_if(&self.server_id, |_, flag| Arc::ptr_eq(flag, &self.flag));
+    }
+}
+
+// Runs a `preview_command` and returns its lines. Read as bytes, since
+// `cut` may split a multi-byte character.
+fn fetch_preview(sess: &Session, cmd: &str) -> Result<Vec<String>, String> {
+    let mut channel = sess.channel_session()
+        .map_err(|e| format!("Failed to open preview channel: {}", e))?;
+    channel.exec(cmd)
+        .map_err(|e| format!("Failed to execute preview: {}", e))?;
+    let mut output = Vec::new();
+    channel.read_to_end(&mut output).ok();
+    channel.wait_close().ok();
+    Ok(String::from_utf8_lossy(&output)
+        .lines()
+        .take(PREVIEW_LINES as usize)
+        .map(truncate_preview_line)
+        .collect())
+}
+
+// Lists log files under `log_path` and counts `trace_id` matches in each of them
+fn search_log_files_in_session(
+    sess: &Session,
+    log_path: &str,
+    trace_id: &str,
+    options: &LogSearchOptions,
+    hooks: &SearchHooks,
+) -> Result<SearchOutcome, String> {
+    // Find all files containing "log" in the filename, up to `max_depth` levels deep
+    let find_cmd = options.list_files_command(log_path);
+    tracing::debug!(command = %find_cmd, "listing log files");
+    
+    let mut channel = sess.channel_session()
+        .map_err(|e| format!("Failed to open channel: {}", e))?;
+    channel.exec(&find_cmd)
+        .map_err(|e| format!("Failed to execute find command: {}", e))?;
+    
+    let mut find_output = String::new();
+    channel.read_to_string(&mut find_output)
+        .map_err(|e| format!("Failed to read find output: {}", e))?;
+    channel.wait_close().ok();
+    
+    let mut files: Vec<String> = find_output
+        .lines()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect();
+    
+    let files_truncated = files.len() > options.max_files as usize;
+    files.truncate(options.max_files as usize);
+    let usage = LogDirUsage::fetch(sess, log_path);
+    
+    if files.is_empty() {
+        return Ok(SearchOutcome {
             files: Vec::new(),
             total_matches: 0,
+            files_truncated,
+            usage,
+            aborted: false,
+        });
+    }
+    
+    // If trace_id is provided, grep for it in each file
+    let mut file_infos: Vec<LogFileInfo> = Vec::new();
+    let mut total_matches: u32 = 0;
+    let files_total = files.len() as u32;
+    let mut aborted = false;
+    
+    for file_path in files {
+        if hooks.is_cancelled() {
+            aborted = true;
+            break;
+        }
+        
+        let file_name = file_path
+            .split('/')
+            .next_back()
+            .unwrap_or(&file_path)
+            .to_string();
+        
+        let compression = LogCompression::detect(&file_path);
+        let count_cmd = options.count_command(compression, trace_id, &shell_quote(&file_path));
+        let match_count = if let Some(count_cmd) = count_cmd {
+            // Count matching lines (zgrep/bzgrep for rotated archives)
+            let grep_cmd = format!("{} 2>/dev/null || echo 0", count_cmd);
+            
+            let mut grep_channel = sess.channel_session()
+                .map_err(|e| format!("Failed to open grep channel: {}", e))?;
+            grep_channel.exec(&grep_cmd)
+                .map_err(|e| format!("Failed to execute grep: {}", e))?;
+            
+            let mut grep_output = String::new();
+            grep_channel.read_to_string(&mut grep_output).ok();
+            grep_channel.wait_close().ok();
+            
+            grep_output.trim().parse::<u32>().unwrap_or(0)
+        } else {
+            0
+        };
+        
+        total_matches += match_count;
+        
+        let preview = match options.preview_command(compression, trace_id, &shell_quote(&file_path)) {
+            Some(preview_cmd) if options.preview && match_count > 0 => {
+                Some(fetch_preview(sess, &format!("{} 2>/dev/null", preview_cmd))?)
+            }
+            _ => None,
+        };
+        
+        let info = LogFileInfo {
+            path: file_path,
+            name: file_name,
+            match_count,
+            compressed: compression != LogCompression::None,
+            preview,
+            matches_truncated: options.max_matches.is_some_and(|max| match_count >= max),
+        };
+        if let Some(on_file) = hooks.on_file {
+            on_file(&info, file_infos.len() as u32 + 1, files_total, total_matches);
+        }
+        file_infos.push(info);
+    }
+    
+    // Sort by match count (descending) if there was anything to match
+    if !options.search_patterns(trace_id).is_empty() {
+        // Filter out files with 0 matches
+        file_infos.retain(|f| f.match_count > 0);
+        file_infos.sort_by_key(|f| std::cmp::Reverse(f.match_count));
+    }
+    
+    Ok(SearchOutcome {
+        files: file_infos,
+        total_matches,
+        files_truncated,
+        usage,
+        aborted,
+    })
+}
+
+// Searches kept for `get_search_history`, newest first
+const SEARCH_HISTORY_LEN: usize = 20;
+
+/// A finished search as kept in the history: what was searched for and the
+/// result's files, counts and timings.
+#[derive(Serialize, Clone)]
+struct SearchHistoryEntry {
+    searched_at: u64, // Unix time in milliseconds
+    log_path: String,
+    trace_id: String,
+    result: LogSearchResult,
+}
+
+lazy_static! {
+    static ref SEARCH_HISTORY: Mutex<VecDeque<SearchHistoryEntry>> = Mutex::new(VecDeque::new());
+}
+
+// Adds a search to the front of the history, dropping the oldest beyond
+// `SEARCH_HISTORY_LEN`. Previews are matched log content, so they aren't kept.
+fn record_search_history(history: &mut VecDeque<SearchHistoryEntry>, mut entry: SearchHistoryEntry) {
+    for file in &mut entry.result.files {
+        file.preview = None;
+    }
+    history.push_front(entry);
+    history.truncate(SEARCH_HISTORY_LEN);
+}
+
+/// Recent search results across all servers, newest first.
+#[tauri::command]
+fn get_search_history() -> Result<Vec<SearchHistoryEntry>, String> {
+    let history = SEARCH_HISTORY.lock().map_err(|_| "Lock failed")?;
+    Ok(history.iter().cloned().collect())
+}
+
+/// Forgets every search in the history.
+#[tauri::command]
+fn clear_search_history() -> Result<(), String> {
+    SEARCH_HISTORY.lock().map_err(|_| "Lock failed")?.clear();
+    Ok(())
+}
+
+// Runs a full search against one server, folding any failure into the result
+#[tracing::instrument(skip_all, fields(host = %target.host, log_path = %log_path, trace_id = %trace_id))]
+fn run_log_search(
+    target: &SshTarget,
+    server_id: String,
+    log_path: &str,
+    trace_id: &str,
+    options: &LogSearchOptions,
+    hooks: &SearchHooks,
+) -> LogSearchResult {
+    let start_time = std::time::Instant::now();
+    
+    let result = CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+        search_log_files_in_session(sess, log_path, trace_id, options, hooks)
+    });
+    
+    let duration_ms = start_time.elapsed().as_millis() as u64;
+    
+    let result = match result {
+        Ok(outcome) => LogSearchResult {
+            matches_truncated: outcome.files.iter().any(|f| f.matches_truncated),
+            server_id,
+            host: target.host.clone(),
+            files: outcome.files,
+            total_matches: outcome.total_matches,
+            files_truncated: outcome.files_truncated,
             duration_ms,
-            error: Some(e),
-        }),
+            error: None,
+            disk_used_percent: outcome.usage.disk_used_percent,
+            log_dir_size_bytes: outcome.usage.log_dir_size_bytes,
+            aborted: outcome.aborted,
+        },
+        Err(e) => {
+            let e = target.redact(&e);
+            tracing::warn!(error = %e, "log search failed");
+            LogSearchResult {
+                server_id,
+                host: target.host.clone(),
+                files: Vec::new(),
+                total_matches: 0,
+                files_truncated: false,
+                duration_ms,
+                error: Some(e),
+                disk_used_percent: None,
+                log_dir_size_bytes: None,
+                aborted: false,
+                matches_truncated: false,
+            }
+        }
+    };
+    
+    if let Ok(mut history) = SEARCH_HISTORY.lock() {
+        let searched_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        record_search_history(
+            &mut history,
+            SearchHistoryEntry {
+                searched_at,
+                log_path: log_path.to_string(),
+                trace_id: trace_id.to_string(),
+                result: result.clone(),
+            },
+        );
+    }
+    result
+}
+
+#[tauri::command]
+async fn search_log_files(
+    app_handle: tauri::AppHandle,
+    target: SshTarget,
+    server_id: String,
+    log_path: String,
+    trace_id: String,
+    args: Option<LogSearchArgs>,
+) -> Result<LogSearchResult, String> {
+    let options = args.unwrap_or_default().into_options()?;
+    let log_path = if log_path.trim().is_empty() {
+        let store = load_servers(&app_handle)?;
+        log_path_or_default(&app_handle, log_path, store.servers.iter().find(|s| s.id == server_id))?
+    } else {
+        log_path
+    };
+    record_recent_query(&app_handle, &server_id, &log_path, &trace_id);
+    run_ssh(target, move |target| {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let _registration = ServerSearchGuard::register(&server_id, cancelled.clone());
+        let hooks = SearchHooks {
+            on_file: None,
+            cancelled: Some(&cancelled),
+        };
+        Ok(run_log_search(target, server_id, &log_path, &trace_id, &options, &hooks))
+    })
+    .await
+}
+
+/// Same as `search_log_files`, but emits a `log-search-progress` event per file
+/// as its match count comes in. The search can be stopped with
+/// `cancel_log_search(search_id)`; the partial result is still returned.
+#[tauri::command]
+async fn search_log_files_stream(
+    app_handle: tauri::AppHandle,
+    search_id: String,
+    target: SshTarget,
+    server_id: String,
+    log_path: String,
+    trace_id: String,
+    args: Option<LogSearchArgs>,
+) -> Result<LogSearchResult, String> {
+    let options = args.unwrap_or_default().into_options()?;
+    let permit = SSH_LIMITER.acquire().await?;
+    // Registered only once nothing can fail before the removal below
+    let cancelled = Arc::new(AtomicBool::new(false));
+    ACTIVE_SEARCHES.insert(search_id.clone(), cancelled.clone());
+    let registered_id = search_id.clone();
+    
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let on_file = |file: &LogFileInfo, files_done: u32, files_total: u32, total_matches: u32| {
+            let _ = app_handle.emit(
+                "log-search-progress",
+                LogSearchProgress {
+                    search_id: search_id.clone(),
+                    server_id: server_id.clone(),
+                    file: file.clone(),
+                    files_done,
+                    files_total,
+                    total_matches,
+                },
+            );
+        };
+        let _registration = ServerSearchGuard::register(&server_id, cancelled.clone());
+        let hooks = SearchHooks {
+            on_file: Some(&on_file),
+            cancelled: Some(&cancelled),
+        };
+        run_log_search(&target, server_id.clone(), &log_path, &trace_id, &options, &hooks)
+    })
+    .await
+    .map_err(|e| format!("Task failed: {}", e));
+    
+    ACTIVE_SEARCHES.remove(&registered_id);
+    result
+}
+
+/// Stops a running `search_log_files_stream` before its next file is grepped.
+#[tauri::command]
+fn cancel_log_search(search_id: String) -> bool {
+    match ACTIVE_SEARCHES.get(&search_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Stops the search running on `server_id` before its next file is grepped;
+/// it returns what it found so far with `aborted` set.
+#[tauri::command]
+fn cancel_search(server_id: String) -> bool {
+    match SERVER_SEARCHES.get(&server_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::SeqCst);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Result of `validate_log_path`.
+#[derive(Serialize, Debug, PartialEq)]
+struct LogPathCheck {
+    exists: bool,
+    is_dir: bool,
+    readable: bool,
+    // Files directly inside matching the default `*log*` pattern
+    log_file_count: u32,
+}
+
+// Prints `e`, `d` and `r` for each of `test -e/-d/-r` that holds, then the
+// number of log files if the path is a readable directory
+fn build_log_path_check_command(log_path: &str) -> String {
+    let path = shell_quote(log_path);
+    format!(
+        "for t in e d r; do test -$t {p} && echo $t; done; \
+test -d {p} && test -r {p} && find {p} -maxdepth 1 -type f -name {} 2>/dev/null | wc -l",
+        shell_quote(DEFAULT_SEARCH_NAME_PATTERN),
+        p = path
+    )
+}
+
+fn parse_log_path_check(output: &str) -> LogPathCheck {
+    let mut check = LogPathCheck {
+        exists: false,
+        is_dir: false,
+        readable: false,
+        log_file_count: 0,
+    };
+    for line in output.lines().map(str::trim) {
+        match line {
+            "e" => check.exists = true,
+            "d" => check.is_dir = true,
+            "r" => check.readable = true,
+            count => check.log_file_count = count.parse().unwrap_or(check.log_file_count),
+        }
+    }
+    check
+}
+
+/// Checks that `log_path` exists on the server and is a readable directory,
+/// and counts the log files in it, so typos show up before a search.
+#[tauri::command]
+async fn validate_log_path(
+    target: SshTarget,
+    log_path: String,
+) -> Result<LogPathCheck, String> {
+    run_ssh(target, move |target| {
+        let command = build_log_path_check_command(&log_path);
+        let output = CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+            run_remote(sess, &command)
+        })?;
+        Ok(parse_log_path_check(&output))
+    })
+    .await
+}
+
+/// Reads the leading timestamp of each line (strptime-style `format`) as Unix
+/// milliseconds, so lines merged from several servers can be sorted. Formats
+/// without a zone are read at `utc_offset_minutes` (UTC by default).
+#[tauri::command]
+fn parse_log_timestamps(
+    lines: Vec<String>,
+    format: String,
+    utc_offset_minutes: Option<i32>,
+) -> Result<Vec<log_time::TimestampedLine>, String> {
+    log_time::parse_lines(lines, &format, utc_offset_minutes.unwrap_or(0))
+}
+
+/// A server `merge_trace_timeline` couldn't read.
+#[derive(Serialize)]
+struct TimelineError {
+    host: String,
+    error: String,
+}
+
+/// The merged timeline of a trace across servers.
+#[derive(Serialize)]
+struct TraceTimeline {
+    entries: Vec<log_time::TimelineEntry>,
+    errors: Vec<TimelineError>,
+}
+
+/// Reads the lines matching `trace_id` in `file_paths` on every server and
+/// merges them into one list ordered by their leading timestamp (`ts_format`),
+/// so a transaction can be followed across hops. Servers are read in parallel;
+/// one that fails is reported in `errors` without failing the rest.
+#[tauri::command]
+async fn merge_trace_timeline(
+    servers: Vec<ServerConfig>,
+    file_paths: Vec<String>,
+    trace_id: String,
+    ts_format: String,
+    utc_offset_minutes: Option<i32>,
+    use_regex: Option<bool>,
+    case_insensitive: Option<bool>,
+) -> Result<TraceTimeline, String> {
+    if trace_id.is_empty() {
+        return Err("A trace id is required to build a timeline".to_string());
+    }
+    let grep = GrepOptions::from_flags(use_regex, case_insensitive);
+    let file_paths = Arc::new(file_paths);
+    let trace_id = Arc::new(trace_id);
+    let hosts: Vec<String> = servers.iter().map(|s| s.host.clone()).collect();
+    let mut handles = Vec::new();
+    for server in servers {
+        let file_paths = file_paths.clone();
+        let trace_id = trace_id.clone();
+        handles.push(tokio::spawn(async move {
+            let host = server.host.clone();
+            let output = run_ssh(server.target(), move |target| {
+                let mut output = String::new();
+                for file_path in file_paths.iter() {
+                    output.push_str(&log_diff::fetch_matching_lines(target, file_path, &trace_id, &grep)?);
+                }
+                Ok(output)
+            })
+            .await?;
+            Ok::<_, String>((host, output))
+        }));
+    }
+
+    let mut outputs = Vec::new();
+    let mut errors = Vec::new();
+    for (handle, host) in handles.into_iter().zip(hosts) {
+        match handle.await.map_err(|e| format!("Task failed: {}", e)).and_then(|r| r) {
+            Ok(output) => outputs.push(output),
+            Err(error) => errors.push(TimelineError { host, error }),
+        }
+    }
+    let entries = log_time::merge_timeline(outputs, &ts_format, utc_offset_minutes.unwrap_or(0))?;
+    Ok(TraceTimeline { entries, errors })
+}
+
+/// Compares the lines matching `trace_id` in the same file on two servers, e.g.
+/// two nodes behind a load balancer. Both servers are read in parallel.
+#[tauri::command]
+async fn diff_remote_logs(
+    server_a: ServerConfig,
+    server_b: ServerConfig,
+    file_path: String,
+    trace_id: String,
+    use_regex: Option<bool>,
+    case_insensitive: Option<bool>,
+) -> Result<log_diff::LogDiff, String> {
+    if trace_id.is_empty() {
+        return Err("A trace id is required to diff logs".to_string());
+    }
+    let grep = GrepOptions::from_flags(use_regex, case_insensitive);
+    let fetch = |server: ServerConfig| {
+        let file_path = file_path.clone();
+        let trace_id = trace_id.clone();
+        async move {
+            run_ssh(server.target(), move |target| {
+                log_diff::fetch_matching_lines(target, &file_path, &trace_id, &grep)
+            })
+            .await
+            .map_err(|e| format!("{}: {}", server.host, e))
+        }
+    };
+    let a = tokio::spawn(fetch(server_a));
+    let b = tokio::spawn(fetch(server_b));
+    let a = a.await.map_err(|e| format!("Task failed: {}", e))??;
+    let b = b.await.map_err(|e| format!("Task failed: {}", e))??;
+    Ok(log_diff::diff_lines(&a, &b))
+}
+
+/// Searches several servers at once, running at most `max_parallel` searches
+/// concurrently (0 means unbounded). A `log-search-server-done` event is emitted
+/// as each server finishes; the returned results keep the input order.
+#[tauri::command]
+async fn search_log_files_multi(
+    app_handle: tauri::AppHandle,
+    servers: Vec<ServerConfig>,
+    log_path: String,
+    trace_id: String,
+    max_parallel: usize,
+    args: Option<LogSearchArgs>,
+) -> Result<Vec<LogSearchResult>, String> {
+    let options = args.unwrap_or_default().into_options()?;
+    let permits = if max_parallel == 0 {
+        servers.len().max(1)
+    } else {
+        max_parallel
+    };
+    let semaphore = Arc::new(Semaphore::new(permits));
+    let mut handles = Vec::with_capacity(servers.len());
+    
+    for server in servers {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| format!("Semaphore closed: {}", e))?;
+        let ssh_permit = SSH_LIMITER.acquire().await?;
+        let app_handle = app_handle.clone();
+        let log_path = log_path.clone();
+        let trace_id = trace_id.clone();
+        let options = options.clone();
+        
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permits = (permit, ssh_permit);
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let _registration = ServerSearchGuard::register(&server.id, cancelled.clone());
+            let hooks = SearchHooks {
+                on_file: None,
+                cancelled: Some(&cancelled),
+            };
+            let result = run_log_search(
+                &server.target(),
+                server.id.clone(),
+                &log_path,
+                &trace_id,
+                &options,
+                &hooks,
+            );
+            let _ = app_handle.emit("log-search-server-done", result.clone());
+            result
+        }));
+    }
+    
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await.map_err(|e| format!("Task failed: {}", e))?);
+    }
+    Ok(results)
+}
+
+/// Per-server total of `count_trace_occurrences`.
+#[derive(Clone, Serialize)]
+pub struct TraceCount {
+    pub server_id: String,
+    pub host: String,
+    pub total_matches: u64,
+    pub duration_ms: u64,
+    pub error: Option<String>,
+}
+
+// One remote pipeline that greps every log file under `log_path` and prints the
+// summed match count, instead of a find plus one grep round trip per file
+fn build_count_command(log_path: &str, trace_id: &str, options: &LogSearchOptions) -> String {
+    let grep = |compression: LogCompression| {
+        options
+            .count_command(compression, trace_id, "\"$f\"")
+            .unwrap_or_else(|| "echo 0".to_string())
+    };
+    format!(
+        "find {} -maxdepth {} -type f {} 2>/dev/null | while IFS= read -r f; do \
+case \"$f\" in *.gz) {};; *.bz2) {};; *) {};; esac; done 2>/dev/null | awk '{{s+=$1}} END {{print s+0}}'",
+        shell_quote(log_path),
+        options.max_depth,
+        options.find_predicates(),
+        grep(LogCompression::Gzip),
+        grep(LogCompression::Bzip2),
+        grep(LogCompression::None)
+    )
+}
+
+#[tracing::instrument(skip_all, fields(host = %target.host))]
+fn run_trace_count(target: &SshTarget, server_id: String, command: &str) -> TraceCount {
+    let start_time = std::time::Instant::now();
+    let result = CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+        let mut channel = sess.channel_session()
+            .map_err(|e| format!("Failed to open channel: {}", e))?;
+        channel.exec(command)
+            .map_err(|e| format!("Failed to execute count: {}", e))?;
+        let mut output = String::new();
+        channel.read_to_string(&mut output)
+            .map_err(|e| format!("Failed to read count: {}", e))?;
+        channel.wait_close().ok();
+        output
+            .trim()
+            .parse::<u64>()
+            .map_err(|_| format!("Unexpected count output: {}", output.trim()))
+    });
+
+    let (total_matches, error) = match result {
+        Ok(count) => (count, None),
+        Err(e) => (0, Some(target.redact(&e))),
+    };
+    TraceCount {
+        server_id,
+        host: target.host.clone(),
+        total_matches,
+        duration_ms: start_time.elapsed().as_millis() as u64,
+        error,
+    }
+}
+
+/// Counts `trace_id` occurrences on each server with a single remote command,
+/// without listing individual files. Runs at most `max_parallel` servers at a
+/// time (0 or unset means unbounded); results keep the input order.
+#[tauri::command]
+async fn count_trace_occurrences(
+    servers: Vec<ServerConfig>,
+    log_path: String,
+    trace_id: String,
+    max_parallel: Option<usize>,
+    args: Option<LogSearchArgs>,
+) -> Result<Vec<TraceCount>, String> {
+    // Only the grep flags, depth and name patterns apply to a count
+    let args = args.unwrap_or_default();
+    let options = LogSearchOptions::from_args(args.use_regex, args.case_insensitive, args.max_depth, None, args.name_pattern);
+    let command = Arc::new(build_count_command(&log_path, &trace_id, &options));
+    let permits = match max_parallel {
+        Some(n) if n > 0 => n,
+        _ => servers.len().max(1),
+    };
+    let semaphore = Arc::new(Semaphore::new(permits));
+    let mut handles = Vec::with_capacity(servers.len());
+
+    for server in servers {
+        let permit = semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| format!("Semaphore closed: {}", e))?;
+        let ssh_permit = SSH_LIMITER.acquire().await?;
+        let command = command.clone();
+
+        handles.push(tokio::task::spawn_blocking(move || {
+            let _permits = (permit, ssh_permit);
+            run_trace_count(&server.target(), server.id.clone(), &command)
+        }));
+    }
+
+    let mut results = Vec::with_capacity(handles.len());
+    for handle in handles {
+        results.push(handle.await.map_err(|e| format!("Task failed: {}", e))?);
+    }
+    Ok(results)
+}
+
+// Trace ids per `search_multiple_traces` call, and the matching lines fetched
+// to count them (one more is requested to detect the cut)
+const MAX_MULTI_TRACE_IDS: usize = 500;
+const MAX_MULTI_TRACE_LINES: usize = 100_000;
+
+/// How many matching lines `search_multiple_traces` found for one trace id.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TraceIdCount {
+    pub trace_id: String,
+    pub matches: u64,
+}
+
+/// Result of `search_multiple_traces` on one server.
+#[derive(Serialize, Debug)]
+pub struct MultiTraceCounts {
+    pub server_id: String,
+    pub host: String,
+    // In the order the ids were given, duplicates dropped
+    pub counts: Vec<TraceIdCount>,
+    // Lines mentioning any of the ids, each counted once
+    pub total_lines: u64,
+    // More than `MAX_MULTI_TRACE_LINES` lines matched, so the counts are lower bounds
+    pub truncated: bool,
+    pub duration_ms: u64,
+}
+
+// Trimmed, non-empty and without duplicates, keeping the first occurrence
+fn normalize_trace_ids(trace_ids: Vec<String>) -> Result<Vec<String>, String> {
+    let mut unique: Vec<String> = Vec::new();
+    for id in trace_ids {
+        let id = id.trim();
+        if !id.is_empty() && !unique.iter().any(|u| u == id) {
+            unique.push(id.to_string());
+        }
+    }
+    if unique.is_empty() {
+        return Err("No trace ids to search for".to_string());
+    }
+    if unique.len() > MAX_MULTI_TRACE_IDS {
+        return Err(format!("Too many trace ids: {} (at most {})", unique.len(), MAX_MULTI_TRACE_IDS));
+    }
+    Ok(unique)
+}
+
+// One remote pipeline printing the lines of every log file under `log_path`
+// that contain any of `trace_ids`, as a single escaped `grep -E 'a|b|...'`
+fn build_multi_trace_command(log_path: &str, trace_ids: &[String]) -> String {
+    let options = LogSearchOptions {
+        patterns: trace_ids.to_vec(),
+        pattern_mode: PatternMode::Or,
+        ..LogSearchOptions::default()
+    };
+    let grep = |compression: LogCompression| {
+        options
+            .match_command(compression, "", "\"$f\"", "")
+            .unwrap_or_else(|| "true".to_string())
+    };
+    format!(
+        "find {} -maxdepth {} -type f {} 2>/dev/null | while IFS= read -r f; do \
+case \"$f\" in *.gz) {};; *.bz2) {};; *) {};; esac; done 2>/dev/null | head -n {}",
+        shell_quote(log_path),
+        options.max_depth,
+        options.find_predicates(),
+        grep(LogCompression::Gzip),
+        grep(LogCompression::Bzip2),
+        grep(LogCompression::None),
+        MAX_MULTI_TRACE_LINES + 1
+    )
+}
+
+// Whether `id` appears in `line` as a whole token, so "TX1" isn't found in "TX10"
+fn contains_trace_id(line: &str, id: &str) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    line.match_indices(id).any(|(start, _)| {
+        !line[..start].chars().next_back().is_some_and(is_word)
+            && !line[start + id.len()..].chars().next().is_some_and(is_word)
+    })
+}
+
+// Counts the lines of `output` mentioning each id. Done here rather than with
+// `grep -o`, so an id that is a prefix of another isn't credited with its matches.
+fn count_trace_ids(output: &str, trace_ids: &[String]) -> (Vec<TraceIdCount>, u64, bool) {
+    let mut counts: Vec<TraceIdCount> = trace_ids
+        .iter()
+        .map(|id| TraceIdCount {
+            trace_id: id.clone(),
+            matches: 0,
+        })
+        .collect();
+    let mut total_lines = 0;
+    let mut truncated = false;
+    for (i, line) in output.lines().enumerate() {
+        if i == MAX_MULTI_TRACE_LINES {
+            truncated = true;
+            break;
+        }
+        let mut matched = false;
+        for count in counts.iter_mut() {
+            if contains_trace_id(line, &count.trace_id) {
+                count.matches += 1;
+                matched = true;
+            }
+        }
+        if matched {
+            total_lines += 1;
+        }
+    }
+    (counts, total_lines, truncated)
+}
+
+/// Searches the logs under `log_path` on `server` for all of `trace_ids` in one
+/// grep and returns how many lines mention each id, instead of one search per id.
+#[tauri::command]
+async fn search_multiple_traces(
+    server: ServerConfig,
+    log_path: String,
+    trace_ids: Vec<String>,
+) -> Result<MultiTraceCounts, String> {
+    let trace_ids = normalize_trace_ids(trace_ids)?;
+    let command = build_multi_trace_command(&log_path, &trace_ids);
+    run_ssh(server.target(), move |target| {
+        let start_time = std::time::Instant::now();
+        let output = CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+            run_remote(sess, &command)
+        })?;
+        let (counts, total_lines, truncated) = count_trace_ids(&output, &trace_ids);
+        Ok(MultiTraceCounts {
+            server_id: server.id.clone(),
+            host: server.host.clone(),
+            counts,
+            total_lines,
+            truncated,
+            duration_ms: start_time.elapsed().as_millis() as u64,
+        })
+    })
+    .await
+}
+
+// Offsets beyond this are certainly a client bug rather than a real file position
+const MAX_READ_BYTE_OFFSET: i64 = 1 << 40;
+
+// What part of a remote log file `read_log_file` returns
+#[derive(Default)]
+struct LogReadOptions {
+    max_lines: u32,
+    trace_id: String,
+    // When set (and `trace_id` is not empty), only matching lines are returned,
+    // numbered and surrounded by this many lines of context (`grep -n -C`)
+    context_lines: Option<u32>,
+    // Read the last `max_lines` lines instead of the first ones
+    from_end: bool,
+    // Absolute byte position to page from: reading continues forward from it,
+    // or with `from_end` returns the lines just before it
+    byte_offset: Option<i64>,
+    // Also locate every `trace_id` match in the returned text (`grep -bon`)
+    match_offsets: bool,
+    // Stop after this many matching lines (`grep -m`)
+    max_matches: Option<u32>,
+}
+
+// The optional `read_log_file` settings the frontend sends
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct LogReadArgs {
+    context_lines: Option<u32>,
+    from_end: Option<bool>,
+    byte_offset: Option<i64>,
+    return_match_offsets: Option<bool>,
+    max_matches: Option<u32>,
+}
+
+impl LogReadArgs {
+    fn into_options(self, max_lines: u32, trace_id: String) -> LogReadOptions {
+        LogReadOptions {
+            max_lines,
+            trace_id,
+            context_lines: self.context_lines,
+            from_end: self.from_end.unwrap_or(false),
+            byte_offset: self.byte_offset,
+            match_offsets: self.return_match_offsets.unwrap_or(false),
+            max_matches: self.max_matches.filter(|max| *max > 0),
+        }
+    }
+}
+
+// Printed between the `grep -bon` lines and the content they refer to. grep
+// output lines start with a line number, so none of them can equal it.
+const MATCH_OFFSETS_SEPARATOR: &str = "--logtoolpro-content--";
+
+/// `read_log_file` output when match offsets were asked for.
+#[derive(Serialize, Debug, PartialEq)]
+struct LogContentWithMatches {
+    content: String,
+    // (1-based line, start byte, end byte) of each `trace_id` match, as
+    // positions in `content`
+    match_offsets: Vec<(u32, u64, u64)>,
+    // Offsets stopped at `max_matches` matching lines
+    truncated: bool,
+}
+
+// Plain text unless match offsets were asked for, so existing callers still
+// receive a string
+#[derive(Serialize)]
+#[serde(untagged)]
+enum LogReadOutput {
+    Text(String),
+    WithMatches(LogContentWithMatches),
+}
+
+// Builds the shell command that prints the requested part of `file_path`
+fn build_read_command(file_path: &str, options: &LogReadOptions) -> Result<String, String> {
+    let compression = LogCompression::detect(file_path);
+    
+    if let Some(context) = options.context_lines.filter(|_| !options.trace_id.is_empty()) {
+        let mut flags = format!("-n -C {}", context);
+        if let Some(max) = options.max_matches {
+            flags.push_str(&format!(" -m {}", max));
+        }
+        let grep = GrepOptions::default().grep_command(compression.grep_program(), &flags, &options.trace_id);
+        return Ok(format!("{} {} 2>/dev/null | head -{}", grep, shell_quote(file_path), options.max_lines));
+    }
+    
+    // Otherwise read a window of the file (trace_id highlighting is done on the frontend)
+    let mut stages: Vec<String> = Vec::new();
+    match options.byte_offset {
+        Some(offset) if !(0..=MAX_READ_BYTE_OFFSET).contains(&offset) => {
+            return Err(format!(
+                "Invalid byte offset {}: must be between 0 and {}",
+                offset, MAX_READ_BYTE_OFFSET
+            ));
+        }
+        Some(offset) if options.from_end => stages.push(format!("head -c {}", offset)),
+        Some(offset) => stages.push(format!("tail -c +{}", offset + 1)),
+        None => {}
+    }
+    stages.push(if options.from_end {
+        format!("tail -n {}", options.max_lines)
+    } else {
+        format!("head -{}", options.max_lines)
+    });
+    
+    let quoted_path = shell_quote(file_path);
+    let cmd = match compression.cat_program() {
+        Some(cat) => format!("{} {} 2>/dev/null | {}", cat, quoted_path, stages.join(" | ")),
+        None => {
+            let first = stages.remove(0);
+            if stages.is_empty() {
+                format!("{} {} 2>/dev/null", first, quoted_path)
+            } else {
+                format!("{} {} 2>/dev/null | {}", first, quoted_path, stages.join(" | "))
+            }
+        }
+    };
+    Ok(cmd)
+}
+
+// Saves the output of `read_cmd` to a temp file, greps that for `trace_id`
+// with byte offsets, then prints the separator and the saved output. Reading
+// once keeps the offsets in step with the content of a growing file.
+fn build_match_offsets_command(read_cmd: &str, trace_id: &str, max_matches: Option<u32>) -> String {
+    let flags = match max_matches {
+        Some(max) => format!("-bon -m {}", max),
+        None => "-bon".to_string(),
+    };
+    let grep = GrepOptions::default().grep_command("grep", &flags, trace_id);
+    format!(
+        "t=$(mktemp) || exit 1; ({}) > \"$t\"; {} \"$t\"; echo '{}'; cat \"$t\"; rm -f \"$t\"",
+        read_cmd, grep, MATCH_OFFSETS_SEPARATOR
+    )
+}
+
+// Splits the output of `build_match_offsets_command` into the content and
+// its `line:byte:match` positions
+fn parse_match_offsets_output(output: &str, max_matches: Option<u32>) -> LogContentWithMatches {
+    let separator = format!("{}\n", MATCH_OFFSETS_SEPARATOR);
+    let (offsets, content) = if let Some(content) = output.strip_prefix(&separator) {
+        ("", content)
+    } else if let Some(at) = output.find(&format!("\n{}", separator)) {
+        (&output[..at], &output[at + 1 + separator.len()..])
+    } else {
+        ("", output)
+    };
+    let match_offsets: Vec<(u32, u64, u64)> = offsets
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            let line_number = fields.next()?.parse().ok()?;
+            let start: u64 = fields.next()?.parse().ok()?;
+            let matched = fields.next()?;
+            Some((line_number, start, start + matched.len() as u64))
+        })
+        .collect();
+    // `grep -m` counts lines, and a line may hold several matches
+    let mut lines: Vec<u32> = match_offsets.iter().map(|(line, _, _)| *line).collect();
+    lines.dedup();
+    LogContentWithMatches {
+        content: content.to_string(),
+        truncated: max_matches.is_some_and(|max| lines.len() >= max as usize),
+        match_offsets,
+    }
+}
+
+// Runs the read command for `file_path` and returns its output
+fn read_log_file_in_session(sess: &Session, file_path: &str, options: &LogReadOptions) -> Result<LogReadOutput, String> {
+    let cmd = build_read_command(file_path, options)?;
+    if !options.match_offsets {
+        return run_remote(sess, &cmd).map(LogReadOutput::Text);
+    }
+    if options.trace_id.is_empty() {
+        return Ok(LogReadOutput::WithMatches(LogContentWithMatches {
+            content: run_remote(sess, &cmd)?,
+            match_offsets: Vec::new(),
+            truncated: false,
+        }));
+    }
+    let output = run_remote(sess, &build_match_offsets_command(&cmd, &options.trace_id, options.max_matches))?;
+    Ok(LogReadOutput::WithMatches(parse_match_offsets_output(&output, options.max_matches)))
+}
+
+#[tauri::command]
+async fn read_log_file(
+    target: SshTarget,
+    file_path: String,
+    trace_id: String,
+    max_lines: u32,
+    args: Option<LogReadArgs>,
+) -> Result<LogReadOutput, String> {
+    let options = args.unwrap_or_default().into_options(max_lines, trace_id);
+    run_ssh(target, move |target| {
+        CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+            read_log_file_in_session(sess, &file_path, &options)
+        })
+    })
+    .await
+}
+
+// Most lines `peek_log_tail` returns; more is a job for `read_log_file`
+const MAX_PEEK_LINES: u32 = 5000;
+
+// The last `lines` lines of `file_path`, decompressed first if needed
+fn build_tail_command(file_path: &str, lines: u32) -> Result<String, String> {
+    if lines == 0 || lines > MAX_PEEK_LINES {
+        return Err(format!("Lines must be between 1 and {}, got {}", MAX_PEEK_LINES, lines));
+    }
+    let quoted_path = shell_quote(file_path);
+    Ok(match LogCompression::detect(file_path).cat_program() {
+        Some(cat) => format!("{} {} 2>/dev/null | tail -n {}", cat, quoted_path, lines),
+        None => format!("tail -n {} {} 2>/dev/null", lines, quoted_path),
+    })
+}
+
+/// The last `lines` lines of a log file as plain text, for a quick look
+/// without a trace id or any of `read_log_file`'s options.
+#[tauri::command]
+async fn peek_log_tail(
+    target: SshTarget,
+    file_path: String,
+    lines: u32,
+) -> Result<String, String> {
+    let command = build_tail_command(&file_path, lines)?;
+    run_ssh(target, move |target| {
+        CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| run_remote(sess, &command))
+    })
+    .await
+}
+
+/// One window of a log file returned by `read_log_page`.
+#[derive(Serialize)]
+struct LogPage {
+    content: String,
+    start_line: u32, // 1-based line number of the first line of `content`
+    total_lines: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct LineCountKey {
+    host: String,
+    port: u16,
+    file_path: String,
+}
+
+// Most lines one `read_log_page` call returns
+const MAX_PAGE_LINES: u32 = 5000;
+// Files whose line count is remembered; the least recently counted go first
+const MAX_LINE_COUNT_ENTRIES: usize = 256;
+
+lazy_static! {
+    // `wc -l` of files paged with `read_log_page`, with the "size mtime" stamp
+    // they were counted at; a file that changed since is counted again
+    static ref LINE_COUNT_CACHE: DashMap<LineCountKey, (String, u64, std::time::Instant)> = DashMap::new();
+}
+
+fn cache_line_count(cache: &DashMap<LineCountKey, (String, u64, std::time::Instant)>, key: LineCountKey, stamp: String, total: u64) {
+    if cache.len() >= MAX_LINE_COUNT_ENTRIES && !cache.contains_key(&key) {
+        let oldest = cache
+            .iter()
+            .min_by_key(|entry| entry.value().2)
+            .map(|entry| entry.key().clone());
+        if let Some(oldest) = oldest {
+            cache.remove(&oldest);
+        }
+    }
+    cache.insert(key, (stamp, total, std::time::Instant::now()));
+}
+
+// Prints "stat <size> <mtime>" of `file_path` (GNU stat, then BSD), followed by
+// lines `start_line..start_line + line_count` of it. `sed` quits after the last
+// line, so pages near the top of a huge file stay cheap.
+fn build_page_command(file_path: &str, start_line: u32, line_count: u32) -> Result<String, String> {
+    if line_count == 0 || line_count > MAX_PAGE_LINES {
+        return Err(format!("Line count must be between 1 and {}, got {}", MAX_PAGE_LINES, line_count));
+    }
+    let quoted_path = shell_quote(file_path);
+    let start = start_line.max(1);
+    let end = start.saturating_add(line_count - 1);
+    let sed = format!("sed -n '{},{}p;{}q'", start, end, end);
+    let page = match LogCompression::detect(file_path).cat_program() {
+        Some(cat) => format!("{} {} 2>/dev/null | {}", cat, quoted_path, sed),
+        None => format!("{} {} 2>/dev/null", sed, quoted_path),
+    };
+    Ok(format!("{}; {}", stat_command(&quoted_path), page))
+}
+
+// Prints "stat <size> <mtime>" of an already quoted path; just "stat" if it's missing
+fn stat_command(quoted_path: &str) -> String {
+    format!(
+        "echo \"stat $(stat -c '%s %Y' {p} 2>/dev/null || stat -f '%z %m' {p} 2>/dev/null)\"",
+        p = quoted_path
+    )
+}
+
+fn build_line_count_command(file_path: &str) -> String {
+    let quoted_path = shell_quote(file_path);
+    match LogCompression::detect(file_path).cat_program() {
+        Some(cat) => format!("{} {} 2>/dev/null | wc -l", cat, quoted_path),
+        None => format!("wc -l < {}", quoted_path),
+    }
+}
+
+// Splits the output of `build_page_command` into the stat stamp and the page
+fn split_page_output(output: &str) -> (String, &str) {
+    let (first, rest) = output.split_once('\n').unwrap_or((output, ""));
+    let stamp = first.strip_prefix("stat").unwrap_or("").trim().to_string();
+    (stamp, rest)
+}
+
+fn run_remote(sess: &Session, cmd: &str) -> Result<String, String> {
+    let mut channel = sess.channel_session()
+        .map_err(|e| format!("Channel failed: {}", e))?;
+    channel.exec(cmd)
+        .map_err(|e| format!("Exec failed: {}", e))?;
+    let mut output = String::new();
+    channel.read_to_string(&mut output)
+        .map_err(|e| format!("Read failed: {}", e))?;
+    channel.wait_close().ok();
+    Ok(output)
+}
+
+fn read_log_page_in_session(
+    sess: &Session,
+    key: LineCountKey,
+    command: &str,
+    start_line: u32,
+) -> Result<LogPage, String> {
+    let output = run_remote(sess, command)?;
+    let (stamp, content) = split_page_output(&output);
+    if stamp.is_empty() {
+        return Err(format!("File not found: {}", key.file_path));
+    }
+
+    let cached = LINE_COUNT_CACHE
+        .get(&key)
+        .filter(|entry| entry.0 == stamp)
+        .map(|entry| entry.1);
+    let total_lines = match cached {
+        Some(total) => total,
+        None => {
+            let counted = run_remote(sess, &build_line_count_command(&key.file_path))?;
+            let total = counted
+                .trim()
+                .parse::<u64>()
+                .map_err(|_| format!("Failed to count lines of {}", key.file_path))?;
+            cache_line_count(&LINE_COUNT_CACHE, key, stamp, total);
+            total
+        }
+    };
+
+    Ok(LogPage {
+        content: content.to_string(),
+        start_line: start_line.max(1),
+        total_lines,
+    })
+}
+
+/// Returns `line_count` lines of a log starting at line `start_line` (1-based),
+/// plus the file's total line count so the viewer can scroll anywhere in it.
+/// At most `MAX_PAGE_LINES` lines are returned at once.
+#[tauri::command]
+async fn read_log_page(
+    target: SshTarget,
+    file_path: String,
+    start_line: u32,
+    line_count: u32,
+) -> Result<LogPage, String> {
+    let command = build_page_command(&file_path, start_line, line_count)?;
+    run_ssh(target, move |target| {
+        let key = LineCountKey {
+            host: target.host.clone(),
+            port: target.port,
+            file_path,
+        };
+        CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+            read_log_page_in_session(sess, key.clone(), &command, start_line)
+        })
+    })
+    .await
+}
+
+// Upper bound of one `read_log_bytes` window
+const MAX_READ_BYTES: u64 = 4 * 1024 * 1024;
+
+/// One byte window of a log file returned by `read_log_bytes`.
+#[derive(Serialize)]
+struct LogBytes {
+    content: String,
+    offset: u64,
+    // Bytes of the file covered by `content`; the next window starts at
+    // `offset + length`. May be a few bytes short of what was asked for, so
+    // a UTF-8 character is never split across two windows.
+    length: u64,
+    total_size: u64,
+}
+
+// Prints the stat line, then `length` bytes of the file starting at `offset`.
+// `tail -c +N` seeks on regular files, so the cost doesn't depend on `offset`.
+fn build_byte_range_command(file_path: &str, offset: u64, length: u64) -> Result<String, String> {
+    if LogCompression::detect(file_path) != LogCompression::None {
+        return Err(format!("Byte ranges can't be read from compressed file {}", file_path));
+    }
+    if offset > MAX_READ_BYTE_OFFSET as u64 {
+        return Err(format!(
+            "Invalid byte offset {}: must be between 0 and {}",
+            offset, MAX_READ_BYTE_OFFSET
+        ));
     }
+    if length == 0 || length > MAX_READ_BYTES {
+        return Err(format!("Invalid length {}: must be between 1 and {}", length, MAX_READ_BYTES));
+    }
+    let quoted_path = shell_quote(file_path);
+    Ok(format!(
+        "{}; tail -c +{} {} 2>/dev/null | head -c {}",
+        stat_command(&quoted_path),
+        offset + 1,
+        quoted_path,
+        length
+    ))
+}
+
+// Splits the output of `build_byte_range_command` into the file size and the
+// longest prefix of the window that doesn't end inside a UTF-8 character
+fn parse_byte_range_output(output: &[u8], file_path: &str) -> Result<(u64, String, u64), String> {
+    let newline = output.iter().position(|&b| b == b'\n').unwrap_or(output.len());
+    let stat = String::from_utf8_lossy(&output[..newline]);
+    let total_size = stat
+        .strip_prefix("stat")
+        .and_then(|rest| rest.split_whitespace().next())
+        .and_then(|size| size.parse::<u64>().ok())
+        .ok_or_else(|| format!("File not found: {}", file_path))?;
+
+    let window = output.get(newline + 1..).unwrap_or(&[]);
+    let complete = match std::str::from_utf8(window) {
+        Ok(_) => window.len(),
+        // Only a truncated trailing character is cut; invalid bytes elsewhere are replaced
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => window.len(),
+    };
+    let content = String::from_utf8_lossy(&window[..complete]).into_owned();
+    Ok((total_size, content, complete as u64))
+}
+
+/// Returns up to `length` bytes of a log starting at byte `offset`, plus the
+/// file's size, for scrolling through huge files without re-reading them.
+#[tauri::command]
+async fn read_log_bytes(
+    target: SshTarget,
+    file_path: String,
+    offset: u64,
+    length: u64,
+) -> Result<LogBytes, String> {
+    let cmd = build_byte_range_command(&file_path, offset, length)?;
+    run_ssh(target, move |target| {
+        let output = CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+            let mut channel = sess.channel_session()
+                .map_err(|e| format!("Channel failed: {}", e))?;
+            channel.exec(&cmd)
+                .map_err(|e| format!("Exec failed: {}", e))?;
+            let mut output = Vec::new();
+            channel.read_to_end(&mut output)
+                .map_err(|e| format!("Read failed: {}", e))?;
+            channel.wait_close().ok();
+            Ok(output)
+        })?;
+        let (total_size, content, length) = parse_byte_range_output(&output, &file_path)?;
+        Ok(LogBytes {
+            content,
+            offset,
+            length,
+            total_size,
+        })
+    })
+    .await
+}
+
+/// Streams new lines of a remote log (`tail -f`) as `log-follow-line` events.
+/// Returns the follow id to pass to `stop_log_follow`. Only connecting counts
+/// against the SSH limit, as with `start_ssh_command`.
+#[tauri::command]
+async fn start_log_follow(
+    app_handle: tauri::AppHandle,
+    target: SshTarget,
+    file_path: String,
+) -> Result<String, String> {
+    run_ssh(target, move |target| {
+        FOLLOW_MANAGER.start_follow(app_handle, target.clone(), file_path)
+    })
+    .await
+}
+
+#[tauri::command]
+fn stop_log_follow(follow_id: String) -> Result<(), String> {
+    FOLLOW_MANAGER.stop_follow(&follow_id)
+}
+
+/// Downloads a whole remote file over SFTP, emitting `download-progress` events.
+/// Returns the number of bytes written to `local_path`.
+#[tauri::command]
+async fn download_log_file(
+    app_handle: tauri::AppHandle,
+    mut target: SshTarget,
+    remote_path: String,
+    local_path: String,
+    verify_hash: Option<file_hash::HashAlgo>,
+) -> Result<u64, String> {
+    // Already-compressed files gain nothing from a compressed stream
+    target.use_compression &= matches!(LogCompression::detect(&remote_path), LogCompression::None);
+    run_ssh(target, move |target| {
+        let bytes = sftp::download(&app_handle, target, &remote_path, &local_path)?;
+        if let Some(algo) = verify_hash {
+            file_hash::verify_download(target, &remote_path, &local_path, algo)?;
+        }
+        Ok(bytes)
+    })
+    .await
 }
 
+/// Downloads the given files into one zip at `local_zip_path`, keeping their
+/// remote directory structure. Files that fail are listed in the result.
 #[tauri::command]
-async fn read_log_file(
-    host: String,
-    port: u16,
-    username: String,
-    password: String,
+async fn download_matched_files(
+    app_handle: tauri::AppHandle,
+    mut target: SshTarget,
+    file_paths: Vec<String>,
+    local_zip_path: String,
+) -> Result<sftp::BulkDownloadResult, String> {
+    // Off when every file is already compressed
+    target.use_compression &= file_paths
+        .iter()
+        .any(|path| matches!(LogCompression::detect(path), LogCompression::None));
+    run_ssh(target, move |target| {
+        sftp::download_zip(&app_handle, target, &file_paths, &local_zip_path)
+    })
+    .await
+}
+
+/// Lists a remote directory over SFTP for the file browser: directories first,
+/// then files, each by name.
+#[tauri::command]
+async fn list_remote_dir(
+    target: SshTarget,
+    path: String,
+) -> Result<Vec<sftp::RemoteDirEntry>, String> {
+    run_ssh(target, move |target| {
+        sftp::list_dir(target, &path)
+    })
+    .await
+}
+
+/// Size, modification time and mode of a remote file, without reading it, so
+/// the UI can warn before opening a huge file.
+#[tauri::command]
+async fn stat_remote_file(
+    target: SshTarget,
     file_path: String,
-    _trace_id: String,
-    max_lines: u32,
+) -> Result<sftp::RemoteFileStat, String> {
+    run_ssh(target, move |target| {
+        sftp::stat_file(target, &file_path)
+    })
+    .await
+}
+
+/// The hex digest of a remote file from `sha256sum`/`md5sum` (or `openssl
+/// dgst`), or "unavailable" when the server has neither.
+#[tauri::command]
+async fn remote_file_hash(
+    target: SshTarget,
+    file_path: String,
+    algo: file_hash::HashAlgo,
 ) -> Result<String, String> {
-    tokio::task::spawn_blocking(move || {
-        let addr = format!("{}:{}", host, port);
-        
-        let tcp = TcpStream::connect(&addr)
-            .map_err(|e| format!("TCP connection failed: {}", e))?;
-        
-        tcp.set_read_timeout(Some(std::time::Duration::from_secs(30)))
-            .map_err(|e| format!("Failed to set timeout: {}", e))?;
-        
-        let mut sess = Session::new()
-            .map_err(|e| format!("Session failed: {}", e))?;
-        
-        sess.set_tcp_stream(tcp);
-        sess.handshake()
-            .map_err(|e| format!("Handshake failed: {}", e))?;
-        
-        sess.userauth_password(&username, &password)
-            .map_err(|e| format!("Auth failed: {}", e))?;
-        
-        if !sess.authenticated() {
-            return Err("Authentication failed".to_string());
-        }
-        
-        // Always read the full file content (trace_id filtering is done on frontend for highlighting)
-        // Use cat to read the file, limiting output to max_lines
-        let cmd = format!(
-            "head -{} '{}' 2>/dev/null",
-            max_lines, file_path
-        );
-        
-        let mut channel = sess.channel_session()
-            .map_err(|e| format!("Channel failed: {}", e))?;
-        
-        channel.exec(&cmd)
-            .map_err(|e| format!("Exec failed: {}", e))?;
-        
-        let mut output = String::new();
-        channel.read_to_string(&mut output)
-            .map_err(|e| format!("Read failed: {}", e))?;
-        
-        channel.wait_close().ok();
-        
-        Ok(output)
+    run_ssh(target, move |target| {
+        file_hash::remote_hash(target, &file_path, algo)
     })
     .await
-    .map_err(|e| format!("Task failed: {}", e))?
 }
 
+/// Uploads a local file over SFTP, emitting `upload-progress` events.
+/// Returns the number of bytes written to `remote_path`.
+#[tauri::command]
+async fn upload_file(
+    app_handle: tauri::AppHandle,
+    target: SshTarget,
+    local_path: String,
+    remote_path: String,
+) -> Result<u64, String> {
+    run_ssh(target, move |target| {
+        sftp::upload(&app_handle, target, &local_path, &remote_path)
+    })
+    .await
+}
+
+/// Closes the cached search/read connection for a server so the next call reconnects.
+#[tauri::command]
+fn disconnect_server(host: String, port: u16, username: String) -> bool {
+    CONNECTION_POOL.disconnect(&host, port, &username)
+}
+
+/// The cap on SSH tasks running at once, and how many are running now.
+#[tauri::command]
+fn get_ssh_concurrency() -> ssh_limit::SshLimitStatus {
+    SSH_LIMITER.status()
+}
+
+/// Changes the cap on SSH tasks running at once.
+#[tauri::command]
+async fn set_ssh_concurrency(limit: usize) -> Result<(), String> {
+    SSH_LIMITER.set_limit(limit)
+}
+
+/// Minimum milliseconds between connection attempts to the same host.
+#[tauri::command]
+fn get_connect_interval() -> u64 {
+    ssh_limit::CONNECT_THROTTLE.interval().as_millis() as u64
+}
+
+/// Sets the minimum milliseconds between connection attempts to the same host
+/// (0 turns the limit off). Faster attempts fail with a "Rate limited" error
+/// saying when to retry, to stay under fail2ban thresholds on hardened hosts.
+#[tauri::command]
+fn set_connect_interval(interval_ms: u64) {
+    ssh_limit::CONNECT_THROTTLE.set_interval(Duration::from_millis(interval_ms));
+}
+
+/// Directory of the app's own daily-rotated log files.
 #[tauri::command]
-async fn write_file(path: String, content: String) -> Result<(), String> {
-    fs::write(&path, &content)
-        .map_err(|e| format!("Failed to write file: {}", e))
+fn get_app_log_path() -> Result<String, String> {
+    app_log::log_dir().map(|dir| dir.display().to_string())
+}
+
+/// Sets the app log level: off, error, warn, info, debug or trace.
+#[tauri::command]
+fn set_app_log_level(level: String) -> Result<(), String> {
+    app_log::set_level(&level)
+}
+
+/// Sets how long an unused cached connection stays open.
+#[tauri::command]
+fn set_connection_idle_ttl(seconds: u64) {
+    CONNECTION_POOL.set_idle_ttl(Duration::from_secs(seconds));
+}
+
+// Turns the io errors users can do something about into a hint on what to fix
+fn write_error(path: &Path, e: &std::io::Error) -> String {
+    let path = path.display();
+    match e.kind() {
+        std::io::ErrorKind::PermissionDenied => format!(
+            "Permission denied writing {}: choose a folder you can write to or check the file isn't read-only",
+            path
+        ),
+        std::io::ErrorKind::NotFound => format!(
+            "Cannot write {}: the folder doesn't exist (enable creating missing folders or pick another one)",
+            path
+        ),
+        std::io::ErrorKind::StorageFull => format!(
+            "Cannot write {}: the disk is full, free up some space and try again",
+            path
+        ),
+        _ => format!("Failed to write {}: {}", path, e),
+    }
+}
+
+// Writes (or with `append`, appends) `content` to `path`, creating missing
+// parent directories first when `create_dirs` is set. Returns the bytes written.
+// A plain write goes to a sibling temp file renamed over `path`, so an
+// interrupted save never leaves a half-written file behind.
+fn write_file_at(path: &Path, content: &str, append: bool, create_dirs: bool) -> Result<u64, String> {
+    if create_dirs {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create directory {}: {}", parent.display(), e))?;
+        }
+    }
+    if append {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| write_error(path, &e))?;
+        file.write_all(content.as_bytes())
+            .map_err(|e| write_error(path, &e))?;
+        return Ok(content.len() as u64);
+    }
+
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| format!("Invalid file path: {}", path.display()))?;
+    let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+    let written = fs::File::create(&tmp_path).and_then(|mut file| {
+        file.write_all(content.as_bytes())?;
+        file.sync_all()
+    });
+    if let Err(e) = written.and_then(|_| fs::rename(&tmp_path, path)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(write_error(path, &e));
+    }
+    Ok(content.len() as u64)
+}
+
+#[tauri::command]
+async fn write_file(
+    path: String,
+    content: String,
+    append: Option<bool>,
+    create_dirs: Option<bool>,
+) -> Result<u64, String> {
+    write_file_at(
+        Path::new(&path),
+        &content,
+        append.unwrap_or(false),
+        create_dirs.unwrap_or(false),
+    )
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -779,24 +4449,999 @@ pub fn run() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
+        .setup(|app| {
+            let log_dir = get_app_file_path(app.handle(), app_log::APP_LOG_DIR)?;
+            if let Err(e) = app_log::init(&log_dir) {
+                eprintln!("WARNING: {}", e);
+            }
+            let known_hosts_path = get_app_file_path(app.handle(), "known_hosts.json")?;
+            KNOWN_HOSTS.init(app.handle().clone(), known_hosts_path);
+            // Only reports "locked" until the master password is entered; `unlock` runs it again
+            check_crypto(app.handle());
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             greet,
             get_system_info,
             test_ssh_connection,
+            test_jump_connection,
+            probe_auth_methods,
+            trust_host_key,
             save_server,
             list_servers,
             list_servers_for_export,
+            export_servers_to_file,
+            get_log_path_templates,
+            get_recent,
+            get_search_history,
+            clear_search_history,
+            diff_remote_logs,
+            parse_log_timestamps,
+            merge_trace_timeline,
+            set_log_path_template,
             delete_server,
+            export_servers,
+            import_servers,
+            unlock,
+            lock,
+            is_locked,
+            crypto_self_test,
+            get_encryption_algorithm,
+            set_encryption_algorithm,
+            rotate_encryption_key,
+            set_server_store_encryption,
+            migrate_plaintext_passwords,
             execute_ssh_command,
+            run_command_on_servers,
             start_pty_session,
             send_pty_input,
             resize_pty,
             close_pty_session,
+            session_count,
+            list_pty_sessions,
+            get_pty_scrollback,
+            close_all_sessions,
             search_log_files,
+            search_log_files_multi,
+            search_log_files_stream,
+            cancel_log_search,
+            cancel_search,
+            validate_log_path,
             read_log_file,
+            peek_log_tail,
+            read_log_page,
+            read_log_bytes,
+            start_log_follow,
+            stop_log_follow,
+            download_log_file,
+            download_matched_files,
+            list_remote_dir,
+            stat_remote_file,
+            remote_file_hash,
+            upload_file,
+            disconnect_server,
+            set_connection_idle_ttl,
+            get_ssh_concurrency,
+            set_ssh_concurrency,
+            get_connect_interval,
+            set_connect_interval,
+            get_app_log_path,
+            set_app_log_level,
             write_file,
-            trace_server_chain
+            trace_server_chain,
+            start_chain_trace,
+            cancel_chain_trace,
+            start_test_connection,
+            cancel_test_connection,
+            export_chain_trace,
+            flatten_chain,
+            check_servers_health,
+            start_ssh_command,
+            cancel_command,
+            start_port_forward,
+            stop_port_forward,
+            count_trace_occurrences,
+            search_multiple_traces,
+            get_remote_system_info,
+            reorder_servers,
+            find_servers,
+            validate_server,
+            clear_trace_cache,
+            list_remote_processes,
+            kill_remote_process
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|_app_handle, event| {
+            // Leave no shells or reader threads behind on the servers
+            if let tauri::RunEvent::Exit = event {
+                close_everything();
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grep_command_fixed_string_is_quoted() {
+        let grep = GrepOptions::default();
+        assert_eq!(grep.grep_command("grep", "-c", "it's"), "grep -F -c -e 'it'\\''s'");
+        assert_eq!(grep.grep_command("grep", "-c", "$HOME"), "grep -F -c -e '$HOME'");
+        assert_eq!(grep.grep_command("grep", "-c", "`id`"), "grep -F -c -e '`id`'");
+    }
+
+    #[test]
+    fn test_grep_command_regex_and_case_insensitive() {
+        let grep = GrepOptions::from_flags(Some(true), Some(true));
+        assert_eq!(grep.grep_command("grep", "-c", "TX[0-9]+"), "grep -E -i -c -e 'TX[0-9]+'");
+    }
+
+    #[test]
+    fn test_count_command_is_one_pipeline() {
+        let cmd = build_count_command("/var/log/my app", "TX'1", &LogSearchOptions::default());
+        assert!(cmd.starts_with("find '/var/log/my app' -maxdepth "));
+        assert!(cmd.contains("*.gz) zgrep -F -c -e 'TX'\\''1' \"$f\";;"));
+        assert!(cmd.contains("*.bz2) bzgrep -F -c -e 'TX'\\''1' \"$f\";;"));
+        assert!(cmd.contains("*) grep -F -c -e 'TX'\\''1' \"$f\";;"));
+        assert!(cmd.ends_with("| awk '{s+=$1} END {print s+0}'"));
+    }
+
+    #[test]
+    fn test_preview_command_and_truncation() {
+        let options = LogSearchOptions::default()
+            .with_patterns(Some(vec!["acct".to_string()]), None)
+            .with_preview(Some(true));
+        assert_eq!(
+            options.preview_command(LogCompression::None, "TX1", "'/logs/a.log'").unwrap(),
+            "grep -F -e 'TX1' '/logs/a.log' | grep -F -m 3 -e 'acct' | cut -c 1-1200"
+        );
+        assert!(options.preview_command(LogCompression::None, "", "f").is_some());
+        assert!(LogSearchOptions::default().preview_command(LogCompression::None, "", "f").is_none());
+
+        let capped = LogSearchOptions::default().with_max_matches(Some(100));
+        assert_eq!(
+            capped.count_command(LogCompression::None, "TX1", "f").unwrap(),
+            "grep -F -c -m 100 -e 'TX1' f"
+        );
+        assert!(LogSearchOptions::default().with_max_matches(Some(0)).max_matches.is_none());
+
+        assert_eq!(truncate_preview_line("short"), "short");
+        let long = "é".repeat(PREVIEW_LINE_CHARS + 5);
+        let cut = truncate_preview_line(&long);
+        assert_eq!(cut.chars().count(), PREVIEW_LINE_CHARS + 1);
+        assert!(cut.ends_with('…'));
+    }
+
+    #[test]
+    fn test_multi_pattern_count_command() {
+        let file = "'/logs/a.log'";
+        let single = LogSearchOptions::default();
+        assert_eq!(
+            single.count_command(LogCompression::None, "TX1", file).unwrap(),
+            "grep -F -c -e 'TX1' '/logs/a.log'"
+        );
+        assert!(single.count_command(LogCompression::None, "", file).is_none());
+
+        let and = LogSearchOptions::default().with_patterns(Some(vec!["acct 7".to_string(), "".to_string()]), None);
+        assert_eq!(
+            and.count_command(LogCompression::Gzip, "TX1", file).unwrap(),
+            "zgrep -F -e 'TX1' '/logs/a.log' | grep -F -c -e 'acct 7'"
+        );
+        let and = and.with_patterns(Some(vec!["b".to_string(), "c".to_string()]), Some(PatternMode::And));
+        assert_eq!(
+            and.count_command(LogCompression::None, "", file).unwrap(),
+            "grep -F -e 'b' '/logs/a.log' | grep -F -c -e 'c'"
+        );
+
+        let or = LogSearchOptions::default().with_patterns(Some(vec!["a.b|c".to_string()]), Some(PatternMode::Or));
+        assert_eq!(
+            or.count_command(LogCompression::None, "it's", file).unwrap(),
+            "grep -E -c -e 'it'\\''s|a\\.b\\|c' '/logs/a.log'"
+        );
+        let mut or_regex = or.clone();
+        or_regex.grep.use_regex = true;
+        assert_eq!(
+            or_regex.count_command(LogCompression::None, "TX[0-9]+", file).unwrap(),
+            "grep -E -c -e '(TX[0-9]+)|(a.b|c)' '/logs/a.log'"
+        );
+    }
+
+    #[test]
+    fn test_log_dir_usage_parsing() {
+        assert_eq!(
+            LogDirUsage::parse("df 87%\ndu 2048\n"),
+            LogDirUsage {
+                disk_used_percent: Some(87.0),
+                log_dir_size_bytes: Some(2 * 1024 * 1024),
+            }
+        );
+        // Missing commands simply print nothing
+        assert_eq!(LogDirUsage::parse(""), LogDirUsage::default());
+        assert!(LogDirUsage::command("/var/log/my app").starts_with("df -P '/var/log/my app' "));
+    }
+
+    fn server(id: &str, group: Option<&str>, sort_index: i32) -> ServerConfig {
+        ServerConfig {
+            id: id.to_string(),
+            host: String::new(),
+            port: 22,
+            username: String::new(),
+            password: String::new(),
+            description: String::new(),
+            environment: String::new(),
+            status: String::new(),
+            private_key_path: None,
+            passphrase: None,
+            auth_method: None,
+            jump_host: None,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            group: group.map(str::to_string),
+            sort_index,
+            default_log_path: None,
+            use_compression: false,
+        }
+    }
+
+    #[test]
+    fn test_servers_sorted_by_group_then_index() {
+        let mut servers = vec![
+            server("b2", Some("prod"), 1),
+            server("a", None, 5),
+            server("b1", Some("prod"), 0),
+            server("c", Some("dev"), 0),
+        ];
+        sort_servers(&mut servers);
+        let ids: Vec<&str> = servers.iter().map(|s| s.id.as_str()).collect();
+        assert_eq!(ids, ["a", "c", "b1", "b2"]);
+    }
+
+    #[test]
+    fn test_reorder_servers() {
+        let mut servers = vec![server("x", None, 0), server("y", None, 1), server("z", None, 2)];
+        apply_server_order(&mut servers, &["z".to_string(), "x".to_string()]).unwrap();
+        sort_servers(&mut servers);
+        let order: Vec<(&str, i32)> = servers.iter().map(|s| (s.id.as_str(), s.sort_index)).collect();
+        assert_eq!(order, [("z", 0), ("x", 1), ("y", 2)]);
+
+        assert_eq!(
+            apply_server_order(&mut servers, &["nope".to_string()]).unwrap_err(),
+            "Unknown server id: nope"
+        );
+    }
+
+    #[test]
+    fn test_server_export_json() {
+        let mut with_secret = server("a", None, 0);
+        with_secret.password = "ENC:secret".to_string();
+        let mut jump = JumpConfig::default();
+        jump.host = "bastion".to_string();
+        jump.password = "ENC:jump-secret".to_string();
+        jump.passphrase = Some("ENC:jump-phrase".to_string());
+        with_secret.jump_host = Some(jump);
+        let servers = vec![with_secret];
+
+        let json: serde_json::Value =
+            serde_json::from_str(&server_export_json(&servers, true).unwrap()).unwrap();
+        assert_eq!(json["servers"][0]["password"], "ENC:secret");
+        assert_eq!(json["servers"][0]["host"], servers[0].host.as_str());
+
+        let json: serde_json::Value =
+            serde_json::from_str(&server_export_json(&servers, false).unwrap()).unwrap();
+        assert!(json["servers"][0].get("password").is_none());
+        assert!(json["servers"][0].get("passphrase").is_none());
+        assert_eq!(json["servers"][0]["jump_host"]["host"], "bastion");
+        let text = json.to_string();
+        assert!(!text.contains("password") && !text.contains("passphrase") && !text.contains("ENC:"), "{}", text);
+    }
+
+    #[test]
+    fn test_default_log_path_resolution() {
+        let mut templates = LogPathTemplates::new();
+        templates.insert("prod".to_string(), "/var/log/app".to_string());
+        let mut prod = server("a", None, 0);
+        prod.environment = "prod".to_string();
+
+        assert_eq!(resolve_default_log_path(Some(&prod), &templates).unwrap(), "/var/log/app");
+        prod.default_log_path = Some("/opt/svc/logs".to_string());
+        assert_eq!(resolve_default_log_path(Some(&prod), &templates).unwrap(), "/opt/svc/logs");
+
+        // A blank default counts as unset, and with no template nothing resolves
+        let mut dev = server("b", None, 0);
+        dev.environment = "dev".to_string();
+        dev.default_log_path = Some("  ".to_string());
+        assert!(resolve_default_log_path(Some(&dev), &templates).is_err());
+        assert!(resolve_default_log_path(None, &templates).is_err());
+    }
+
+    #[test]
+    fn test_search_history_is_bounded_and_drops_previews() {
+        let entry = |trace_id: &str| SearchHistoryEntry {
+            searched_at: 0,
+            log_path: "/var/log".to_string(),
+            trace_id: trace_id.to_string(),
+            result: LogSearchResult {
+                server_id: "s1".to_string(),
+                host: "h".to_string(),
+                files: vec![LogFileInfo {
+                    path: "/var/log/a.log".to_string(),
+                    name: "a.log".to_string(),
+                    match_count: 1,
+                    compressed: false,
+                    preview: Some(vec!["TX secret line".to_string()]),
+                    matches_truncated: false,
+                }],
+                total_matches: 1,
+                files_truncated: false,
+                duration_ms: 5,
+                error: None,
+                disk_used_percent: None,
+                log_dir_size_bytes: None,
+                aborted: false,
+                matches_truncated: false,
+            },
+        };
+        let mut history = VecDeque::new();
+        for i in 0..SEARCH_HISTORY_LEN + 3 {
+            record_search_history(&mut history, entry(&format!("TX{}", i)));
+        }
+        assert_eq!(history.len(), SEARCH_HISTORY_LEN);
+        assert_eq!(history[0].trace_id, format!("TX{}", SEARCH_HISTORY_LEN + 2));
+        assert_eq!(history[0].result.files[0].match_count, 1);
+        assert!(history.iter().all(|e| e.result.files[0].preview.is_none()));
+    }
+
+    #[test]
+    fn test_recent_query_ring_buffer() {
+        let mut recent = RecentQuery::default();
+        for i in 0..12 {
+            recent.record("/var/log/app", &format!("TX{}", i));
+        }
+        recent.record("", "TX5");
+        recent.record("/var/log/other", "");
+        assert_eq!(recent.log_path.as_deref(), Some("/var/log/other"));
+        assert_eq!(recent.trace_ids.len(), MAX_RECENT_TRACE_IDS);
+        assert_eq!(recent.trace_ids[..3], ["TX5", "TX11", "TX10"]);
+        assert_eq!(recent.trace_ids.iter().filter(|id| *id == "TX5").count(), 1);
+    }
+
+    #[test]
+    fn test_server_query() {
+        let mut gateway = server("1", None, 0);
+        gateway.host = "10.0.0.5".to_string();
+        gateway.description = "Payment Gateway".to_string();
+        gateway.environment = "PROD".to_string();
+
+        assert!(ServerQuery::parse("gateway").matches(&gateway));
+        assert!(ServerQuery::parse("env:prod  10.0.0").matches(&gateway));
+        assert!(ServerQuery::parse("").matches(&gateway));
+        assert!(!ServerQuery::parse("env:dev gateway").matches(&gateway));
+        assert!(!ServerQuery::parse("gateway billing").matches(&gateway));
+    }
+
+    #[test]
+    fn test_server_target_carries_compression() {
+        let mut config = server("1", None, 0);
+        assert!(!config.target().use_compression);
+        config.use_compression = true;
+        let mut jump = JumpConfig::default();
+        jump.host = "bastion".to_string();
+        config.jump_host = Some(jump);
+        let target = config.target();
+        assert!(target.use_compression);
+        // The bastion leg only relays the inner session
+        assert!(!target.jump_host.as_ref().unwrap().target().use_compression);
+    }
+
+    #[test]
+    fn test_corrupt_store_falls_back_to_backup() {
+        let dir = std::env::temp_dir().join(format!("servers_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("servers.json");
+
+        let first = ServerStore { servers: vec![server("a", None, 0)], ..Default::default() };
+        let second = ServerStore {
+            servers: vec![server("a", None, 0), server("b", None, 1)],
+            ..Default::default()
+        };
+        write_server_store(&path, &first).unwrap();
+        write_server_store(&path, &second).unwrap();
+        assert_eq!(read_server_store(&path).unwrap().0.servers.len(), 2);
+
+        // A write cut short leaves a truncated file: the backup holds the previous version
+        fs::write(&path, "{\"servers\": [").unwrap();
+        let (store, error) = read_server_store(&path).unwrap();
+        assert_eq!(store.servers.len(), 1);
+        assert!(error.is_some());
+
+        // Saving over the corrupt file keeps the good backup
+        write_server_store(&path, &second).unwrap();
+        assert_eq!(parse_server_store(&backup_path(&path)).unwrap().servers.len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_sealed_server_store() {
+        let key = [7u8; 32];
+        let mut store = ServerStore { servers: vec![server("a", None, 0)], ..Default::default() };
+        store.servers[0].host = "db.internal.example".to_string();
+
+        // Plaintext stores are still written (and read) as before
+        let plain = encode_server_store(&store, |_| Err("unused".to_string())).unwrap();
+        assert!(plain.contains("db.internal.example"));
+        assert!(!decode_server_store(&plain, |_| Err("unused".to_string())).unwrap().encrypted);
+
+        store.encrypted = true;
+        let sealed = encode_server_store(&store, |json| crypto::encrypt_with_key(&key, json)).unwrap();
+        assert!(!sealed.contains("db.internal.example"));
+        let opened = decode_server_store(&sealed, |blob| crypto::decrypt_with_key(&key, blob)).unwrap();
+        assert!(opened.encrypted);
+        assert_eq!(opened.servers[0].host, "db.internal.example");
+        assert!(decode_server_store(&sealed, |blob| crypto::decrypt_with_key(&[8u8; 32], blob)).is_err());
+    }
+
+    #[test]
+    fn test_check_stored_secret() {
+        let key = [7u8; 32];
+        let mut store = ServerStore { servers: vec![server("legacy", None, 0), server("b", None, 1)], ..Default::default() };
+        // Plaintext from old builds is skipped; the first real ciphertext is checked
+        store.servers[0].password = "hunter2".to_string();
+        store.servers[1].password = crypto::encrypt_with_key(&key, "pw").unwrap();
+        assert_eq!(
+            check_stored_secret(&store, |v, _| crypto::decrypt_with_key(&key, v)),
+            Ok(Some("b".to_string()))
+        );
+        let mismatch = check_stored_secret(&store, |v, _| crypto::decrypt_with_key(&[8u8; 32], v)).unwrap_err();
+        assert!(mismatch.starts_with("Password of server b does not decrypt"));
+
+        store.servers.truncate(1);
+        assert_eq!(check_stored_secret(&store, |_, _| Err("unused".to_string())), Ok(None));
+    }
+
+    #[test]
+    fn test_encrypt_plaintext_secrets() {
+        let key = [7u8; 32];
+        let decrypt = |v: &str| crypto::decrypt_with_key(&key, v);
+        let encrypt = |v: &str| crypto::encrypt_with_key(&key, v);
+
+        let mut legacy = server("a", None, 0);
+        legacy.password = "hunter2".to_string();
+        legacy.passphrase = Some(String::new());
+        assert!(legacy.encrypt_plaintext_secrets(decrypt, encrypt).unwrap());
+        assert_ne!(legacy.password, "hunter2");
+        assert_eq!(decrypt(&legacy.password).unwrap(), "hunter2");
+        assert_eq!(legacy.passphrase.as_deref(), Some(""));
+
+        // Running again finds nothing left to migrate
+        let ciphertext = legacy.password.clone();
+        assert!(!legacy.encrypt_plaintext_secrets(decrypt, encrypt).unwrap());
+        assert_eq!(legacy.password, ciphertext);
+
+        // Ciphertext under another key is kept, not wrapped again
+        let mut foreign = server("b", None, 0);
+        foreign.password = crypto::encrypt_with_key(&[9u8; 32], "hunter2").unwrap();
+        let ciphertext = foreign.password.clone();
+        assert!(!foreign.encrypt_plaintext_secrets(decrypt, encrypt).unwrap());
+        assert_eq!(foreign.password, ciphertext);
+    }
+
+    #[test]
+    fn test_bind_legacy_secrets() {
+        let key = [7u8; 32];
+        let decrypt = |v: &str| crypto::decrypt_with_key(&key, v);
+        let encrypt = |v: &str, id: &str| crypto::encrypt_with_aad(&key, crypto::Algorithm::Aes256Gcm, v, id);
+
+        let mut stored = server("a", None, 0);
+        stored.password = crypto::encrypt_with_key(&key, "pw").unwrap();
+        stored.passphrase = Some("plain".to_string());
+        stored.bind_legacy_secrets(decrypt, encrypt).unwrap();
+        assert!(crypto::is_bound(&stored.password));
+        assert_eq!(crypto::decrypt_with_aad(&key, &stored.password, "a").unwrap(), "pw");
+        // Swapped onto another server it no longer decrypts
+        assert!(crypto::decrypt_with_aad(&key, &stored.password, "b").is_err());
+        assert_eq!(stored.passphrase.as_deref(), Some("plain"));
+
+        let bound = stored.password.clone();
+        stored.bind_legacy_secrets(decrypt, encrypt).unwrap();
+        assert_eq!(stored.password, bound);
+    }
+
+    #[test]
+    fn test_server_validation() {
+        let mut existing = server("1", None, 0);
+        existing.host = "10.0.0.1".to_string();
+        existing.username = "root".to_string();
+
+        let mut blank = server("2", None, 0);
+        blank.port = 0;
+        let fields: Vec<String> = server_field_errors(&blank, &[]).into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["host", "port", "username"]);
+
+        let mut copy = server("2", None, 0);
+        copy.host = "10.0.0.1".to_string();
+        copy.username = "root".to_string();
+        assert_eq!(server_field_errors(&copy, std::slice::from_ref(&existing)).len(), 1);
+        // Updating the server itself is not a duplicate
+        copy.id = "1".to_string();
+        assert!(server_field_errors(&copy, std::slice::from_ref(&existing)).is_empty());
+    }
+
+    #[test]
+    fn test_trace_cache_expiry_and_config_change() {
+        let cache = TraceCache {
+            entries: DashMap::new(),
+        };
+        let key = TraceCacheKey {
+            host: "10.0.0.1".to_string(),
+            log_path: "/logs".to_string(),
+            trace_id: "TX1".to_string(),
+        };
+        let scan = HopScan {
+            entries: vec![("gw.log".to_string(), "B001".to_string(), "10.0.0.2".to_string())],
+            fallback: Vec::new(),
+        };
+        cache.insert(key.clone(), "search".to_string(), scan);
+
+        let (hit, _) = cache.get(&key, "search", TRACE_CACHE_TTL).unwrap();
+        assert_eq!(hit.entries.len(), 1);
+        assert!(cache.get(&key, "other search", TRACE_CACHE_TTL).is_none());
+        // The mismatch evicted the entry
+        assert_eq!(cache.clear(), 0);
+
+        cache.insert(key.clone(), "search".to_string(), HopScan::default());
+        assert!(cache.get(&key, "search", Duration::ZERO).is_none());
+    }
+
+    #[test]
+    fn test_find_name_filter() {
+        assert_eq!(LogSearchOptions::default().find_name_filter(), "-name '*log*'");
+        let options = LogSearchOptions::from_args(None, None, None, None, Some(" *.out, trace*.txt ,".to_string()));
+        assert_eq!(options.find_name_filter(), "\\( -name '*.out' -o -name 'trace*.txt' \\)");
+        let options = LogSearchOptions::from_args(None, None, None, None, Some("*;reboot $(id)".to_string()));
+        assert_eq!(options.find_name_filter(), "-name '*;reboot $(id)'");
+        let options = LogSearchOptions::from_args(None, None, None, None, Some(" ".to_string()));
+        assert_eq!(options.name_patterns, ["*log*"]);
+    }
+
+    #[test]
+    fn test_modified_time_window() {
+        let options = LogSearchOptions::default()
+            .with_modified_window(Some("-7d".to_string()), Some("2024-05-01T08:00:00".to_string()))
+            .unwrap();
+        assert_eq!(
+            options.find_predicates(),
+            "-name '*log*' -newermt '7 days ago' ! -newermt '2024-05-01T08:00:00'"
+        );
+        // Empty values are no bound at all
+        let options = LogSearchOptions::default()
+            .with_modified_window(Some(String::new()), Some("  ".to_string()))
+            .unwrap();
+        assert_eq!(options.find_predicates(), "-name '*log*'");
+
+        assert_eq!(
+            LogSearchOptions::default().list_files_command("/var/log"),
+            "find '/var/log' -maxdepth 1 -type f -name '*log*' 2>/dev/null | head -1001"
+        );
+        let newest = LogSearchOptions::default().with_newest_n(Some(2));
+        assert_eq!(
+            newest.list_files_command("/var/log"),
+            "find '/var/log' -maxdepth 1 -type f -name '*log*' -printf '%T@ %p\\n' 2>/dev/null \
+| sort -rn | head -n 2 | cut -d ' ' -f 2- | head -1001"
+        );
+        assert!(LogSearchOptions::default().with_newest_n(Some(0)).newest_n.is_none());
+
+        assert_eq!(parse_modified_time(Some("-30m")).unwrap().unwrap(), "30 minutes ago");
+        assert!(parse_modified_time(Some("-7x")).is_err());
+        // Multi-byte units are rejected, not split mid-character
+        assert!(parse_modified_time(Some("-7天")).is_err());
+        assert!(parse_modified_time(Some("-7д")).is_err());
+        assert!(parse_modified_time(Some("-")).is_err());
+        assert!(parse_modified_time(Some("yesterday; reboot")).is_err());
+    }
+
+    #[test]
+    fn test_log_page_command() {
+        let dir = std::env::temp_dir().join(format!("logtool-page-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("app.log");
+        let lines: Vec<String> = (1..=100).map(|i| format!("line {}", i)).collect();
+        std::fs::write(&file, lines.join("\n") + "\n").unwrap();
+        let path = file.to_str().unwrap();
+
+        let run = |cmd: String| {
+            let output = std::process::Command::new("sh").arg("-c").arg(cmd).output().unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let output = run(build_page_command(path, 41, 3).unwrap());
+        let (stamp, content) = split_page_output(&output);
+        assert!(stamp.starts_with(&format!("{} ", std::fs::metadata(&file).unwrap().len())));
+        assert_eq!(content, "line 41\nline 42\nline 43\n");
+        assert_eq!(run(build_line_count_command(path)).trim(), "100");
+
+        // A missing file has no stamp
+        let output = run(build_page_command(dir.join("gone.log").to_str().unwrap(), 1, 10).unwrap());
+        assert_eq!(split_page_output(&output), (String::new(), ""));
+        std::fs::remove_dir_all(&dir).ok();
+
+        assert!(build_page_command(path, 1, 0).is_err());
+        assert!(build_page_command(path, 1, MAX_PAGE_LINES).is_ok());
+        assert!(build_page_command(path, 1, MAX_PAGE_LINES + 1).is_err());
+    }
+
+    #[test]
+    fn test_line_count_cache_is_bounded() {
+        let cache = DashMap::new();
+        let key = |i: usize| LineCountKey {
+            host: "h".to_string(),
+            port: 22,
+            file_path: format!("/logs/{}.log", i),
+        };
+        for i in 0..MAX_LINE_COUNT_ENTRIES + 10 {
+            cache_line_count(&cache, key(i), "1 1".to_string(), i as u64);
+        }
+        assert_eq!(cache.len(), MAX_LINE_COUNT_ENTRIES);
+        // The newest survive, and updating a cached file doesn't evict another
+        assert!(cache.contains_key(&key(MAX_LINE_COUNT_ENTRIES + 9)));
+        cache_line_count(&cache, key(MAX_LINE_COUNT_ENTRIES + 9), "2 2".to_string(), 7);
+        assert_eq!(cache.len(), MAX_LINE_COUNT_ENTRIES);
+    }
+
+    #[test]
+    fn test_byte_range_command() {
+        let dir = std::env::temp_dir().join(format!("logtool-bytes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("app.log");
+        std::fs::write(&file, "0123456789héllo\n").unwrap();
+        let path = file.to_str().unwrap();
+
+        let run = |offset, length| {
+            let cmd = build_byte_range_command(path, offset, length).unwrap();
+            let output = std::process::Command::new("sh").arg("-c").arg(cmd).output().unwrap();
+            parse_byte_range_output(&output.stdout, path).unwrap()
+        };
+        assert_eq!(run(3, 4), (17, "3456".to_string(), 4));
+        // "é" is two bytes; a window ending between them stops before it
+        assert_eq!(run(10, 2), (17, "h".to_string(), 1));
+        assert_eq!(run(11, 100), (17, "éllo\n".to_string(), 6));
+        assert_eq!(run(40, 10), (17, String::new(), 0));
+
+        assert!(build_byte_range_command("/logs/app.log.gz", 0, 10).is_err());
+        assert!(build_byte_range_command(path, 0, 0).is_err());
+        assert!(parse_byte_range_output(b"stat \n", path).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_write_file_append_and_create_dirs() {
+        let dir = std::env::temp_dir().join(format!("logtool-write-{}", std::process::id()));
+        let file = dir.join("exports").join("result.txt");
+
+        // Without create_dirs a missing parent is still an error
+        assert!(write_file_at(&file, "a", false, false).is_err());
+        assert_eq!(write_file_at(&file, "first\n", false, true).unwrap(), 6);
+        assert_eq!(write_file_at(&file, "second\n", true, false).unwrap(), 7);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "first\nsecond\n");
+        write_file_at(&file, "over", false, false).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "over");
+        // The temp file used for the atomic write is renamed away
+        assert_eq!(fs::read_dir(file.parent().unwrap()).unwrap().count(), 1);
+
+        let missing = dir.join("nowhere").join("result.txt");
+        assert!(write_file_at(&missing, "a", false, false)
+            .unwrap_err()
+            .contains("the folder doesn't exist"));
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        assert!(write_error(&file, &denied).starts_with("Permission denied writing"));
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_server_search_registration() {
+        let first = Arc::new(AtomicBool::new(false));
+        let second = Arc::new(AtomicBool::new(false));
+        let first_guard = ServerSearchGuard::register("srv-cancel", first.clone());
+        let second_guard = ServerSearchGuard::register("srv-cancel", second.clone());
+
+        // The older search finishing doesn't unregister the newer one
+        drop(first_guard);
+        assert!(cancel_search("srv-cancel".to_string()));
+        assert!(second.load(Ordering::SeqCst));
+        assert!(!first.load(Ordering::SeqCst));
+
+        drop(second_guard);
+        assert!(!cancel_search("srv-cancel".to_string()));
+    }
+
+    #[test]
+    fn test_log_path_check() {
+        let dir = std::env::temp_dir().join(format!("logtool-check-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app.log"), "").unwrap();
+        std::fs::write(dir.join("gc.log.1"), "").unwrap();
+        std::fs::write(dir.join("notes.txt"), "").unwrap();
+
+        let check = |path: &Path| {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(build_log_path_check_command(path.to_str().unwrap()))
+                .output()
+                .unwrap();
+            parse_log_path_check(&String::from_utf8_lossy(&output.stdout))
+        };
+        assert_eq!(
+            check(&dir),
+            LogPathCheck { exists: true, is_dir: true, readable: true, log_file_count: 2 }
+        );
+        let file = check(&dir.join("app.log"));
+        assert!(file.exists && !file.is_dir && file.log_file_count == 0);
+        assert!(!check(&dir.join("missing")).exists);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_compression_detection() {
+        assert_eq!(LogCompression::detect("/var/log/app-2024-01-01.log.gz"), LogCompression::Gzip);
+        assert_eq!(LogCompression::detect("/var/log/app.log.bz2"), LogCompression::Bzip2);
+        assert_eq!(LogCompression::detect("/var/log/app.log"), LogCompression::None);
+        assert_eq!(LogCompression::Gzip.grep_program(), "zgrep");
+        assert_eq!(LogCompression::Bzip2.cat_program(), Some("bzcat"));
+    }
+
+    #[test]
+    fn test_read_command_context_mode() {
+        let mut options = LogReadOptions {
+            max_lines: 500,
+            trace_id: "TX1".to_string(),
+            context_lines: Some(3),
+            ..Default::default()
+        };
+        assert_eq!(
+            build_read_command("/logs/a.log", &options).unwrap(),
+            "grep -F -n -C 3 -e 'TX1' '/logs/a.log' 2>/dev/null | head -500"
+        );
+
+        options.trace_id.clear();
+        assert_eq!(
+            build_read_command("/logs/a.log", &options).unwrap(),
+            "head -500 '/logs/a.log' 2>/dev/null"
+        );
+    }
+
+    #[test]
+    fn test_read_command_tail_and_offsets() {
+        let mut options = LogReadOptions {
+            max_lines: 100,
+            from_end: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            build_read_command("/logs/a.log", &options).unwrap(),
+            "tail -n 100 '/logs/a.log' 2>/dev/null"
+        );
+
+        options.byte_offset = Some(4096);
+        assert_eq!(
+            build_read_command("/logs/a.log.gz", &options).unwrap(),
+            "zcat '/logs/a.log.gz' 2>/dev/null | head -c 4096 | tail -n 100"
+        );
+
+        options.from_end = false;
+        assert_eq!(
+            build_read_command("/logs/a.log", &options).unwrap(),
+            "tail -c +4097 '/logs/a.log' 2>/dev/null | head -100"
+        );
+
+        options.byte_offset = Some(-1);
+        assert!(build_read_command("/logs/a.log", &options).is_err());
+        options.byte_offset = Some(i64::MAX);
+        assert!(build_read_command("/logs/a.log", &options).is_err());
+    }
+
+    #[test]
+    fn test_match_offsets() {
+        let cmd = build_match_offsets_command("tail -n 5 '/logs/a.log'", "it's", None);
+        assert!(cmd.contains("(tail -n 5 '/logs/a.log') > \"$t\""));
+        assert!(cmd.contains("grep -F -bon -e 'it'\\''s' \"$t\""));
+
+        let output = "1:6:abc\n3:20:abc\n3:26:abc\n--logtoolpro-content--\n\
+                      start abc\nnothing\nx abc y abc\n";
+        let parsed = parse_match_offsets_output(output, None);
+        assert_eq!(parsed.content, "start abc\nnothing\nx abc y abc\n");
+        assert_eq!(parsed.match_offsets, [(1, 6, 9), (3, 20, 23), (3, 26, 29)]);
+        assert_eq!(&parsed.content[20..23], "abc");
+
+        assert!(!parsed.truncated);
+        // Two matching lines reach a cap of 2, however many matches they hold
+        assert!(parse_match_offsets_output(output, Some(2)).truncated);
+        assert!(!parse_match_offsets_output(output, Some(3)).truncated);
+        assert!(build_match_offsets_command("cat f", "x", Some(2)).contains("grep -F -bon -m 2 -e 'x'"));
+
+        let parsed = parse_match_offsets_output("--logtoolpro-content--\nno hits\n", None);
+        assert_eq!(parsed.content, "no hits\n");
+        assert!(parsed.match_offsets.is_empty());
+    }
+
+    #[test]
+    fn test_default_chain_config_matches_gateway_format() {
+        let config = ChainTraceConfig::default();
+        assert_eq!(
+            config.search_command("/logs", "TX1"),
+            "cd '/logs' && find . -maxdepth 1 -name \"*log*\" -print0 | xargs -0 -P $(nproc) grep -H -F -e 'TX1' 2>/dev/null | grep -F 'PEER' | sed -n 's/^\\([^:]*\\):.*DESTDUS=\\([^|]*\\).*PEER=\\([0-9.]*\\).*/\\1 \\2 \\3/p' | grep -v 'N/A' | sort -u"
+        );
+        assert!(config.fallback_command("/logs", "TX1").starts_with("cd '/logs' && "));
+        assert!(config.fallback_command("/logs", "TX1").contains("grep -H -F -e 'TX1'"));
+        assert!(config.is_node("B001Y") && config.is_node("C002"));
+        assert!(!config.is_node("G100") && config.is_router("G100"));
+    }
+
+    #[test]
+    fn test_chain_commands_quote_hostile_values() {
+        let config = ChainTraceConfig::default();
+        let search = config.search_command("/logs/a b;c", "x'; $(reboot)");
+        assert!(search.starts_with("cd '/logs/a b;c' && "));
+        assert!(search.contains("grep -H -F -e 'x'\\''; $(reboot)' "));
+        let fallback = config.fallback_command("/logs/a b;c", "x'; $(reboot)");
+        assert!(fallback.starts_with("cd '/logs/a b;c' && "));
+        assert!(fallback.contains("-e 'x'\\''; $(reboot)' "));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_read_command_with_hostile_file_name() {
+        let dir = std::env::temp_dir().join(format!("quote_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let canary = dir.join("pwned");
+        let path = dir.join("it's a; $(touch pwned) `id`.log");
+        fs::write(&path, "line one\nline two\n").unwrap();
+
+        let options = LogReadOptions {
+            max_lines: 10,
+            ..Default::default()
+        };
+        let cmd = build_read_command(path.to_str().unwrap(), &options).unwrap();
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(&cmd)
+            .current_dir(&dir)
+            .output()
+            .unwrap();
+        let canary_exists = canary.exists();
+        let _ = fs::remove_dir_all(&dir);
+
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "line one\nline two\n");
+        assert!(!canary_exists);
+    }
+
+    #[test]
+    fn test_custom_chain_config() {
+        let config: ChainTraceConfig = serde_json::from_str(
+            r#"{"dest_field": "TARGETDUS", "peer_field": "NEXTHOP", "node_prefixes": ["N"], "router_prefixes": ["R", "G"]}"#,
+        )
+        .unwrap();
+        assert!(config.validate().is_ok());
+        assert!(config.search_command("/logs", "TX1").contains(".*TARGETDUS=\\([^|]*\\).*NEXTHOP="));
+        assert!(config.is_node("N01") && !config.is_node("B01"));
+        assert!(config.is_router("R01"));
+        assert_eq!(config.fallback_command, DEFAULT_CHAIN_FALLBACK_COMMAND);
+
+        let bad = ChainTraceConfig { dest_field: "X/;rm".to_string(), ..Default::default() };
+        assert!(bad.validate().is_err());
+
+        // Unset options fall back to the defaults
+        let options: ChainTraceOptions = serde_json::from_str(r#"{"max_depth": 2}"#).unwrap();
+        assert_eq!(options.max_depth, Some(2));
+        assert_eq!(options.config.dest_field, "DESTDUS");
+        assert!(!options.dry_run);
+    }
+
+    // Where the chain tests start; nothing is ever connected to
+    fn chain_start() -> SshTarget {
+        let mut target = SshTarget::default();
+        target.host = "10.0.0.1".to_string();
+        target.port = 22;
+        target.username = "app".to_string();
+        target
+    }
+
+    #[test]
+    fn test_concurrency_slot_released_on_panic() {
+        let limit = ConcurrencyLimit::new(1);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let _slot = limit.acquire().unwrap();
+            panic!("hop failed");
+        }));
+        assert!(result.is_err());
+        assert_eq!(*limit.available.lock().unwrap(), 1);
+        drop(limit.acquire().unwrap());
+        assert_eq!(*limit.available.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_cancelled_chain_trace_returns_partial_result() {
+        let request = chain_trace_request(
+            chain_start(),
+            "TX1".to_string(),
+            "/logs".to_string(),
+            Vec::new(),
+            ChainTraceOptions::default(),
+        )
+        .unwrap();
+        let result = run_chain_trace(request, &AtomicBool::new(true));
+        assert!(result.error.is_none());
+        assert!(result.nodes.is_empty());
+        assert!(result.trace_log.last().unwrap().contains("追踪已取消"));
+    }
+
+    #[test]
+    fn test_dry_run_only_logs_commands() {
+        let options = ChainTraceOptions {
+            dry_run: true,
+            ..ChainTraceOptions::default()
+        };
+        let request = chain_trace_request(chain_start(), "TX1".to_string(), "/logs".to_string(), Vec::new(), options)
+            .unwrap();
+        let config = request.config.clone();
+        let result = run_chain_trace(request, &AtomicBool::new(false));
+        assert!(result.error.is_none());
+        assert!(result.nodes.is_empty());
+        assert_eq!(result.total_hops, 0);
+        assert!(result.trace_log.contains(&config.search_command("/logs", "TX1")));
+        assert!(result.trace_log.contains(&config.fallback_command("/logs", "TX1")));
+        assert!(result.loops.is_empty());
+    }
+
+    #[test]
+    fn test_multi_trace_search() {
+        let ids = normalize_trace_ids(vec![" TX1 ".to_string(), "TX1".to_string(), String::new(), "a.b".to_string()])
+            .unwrap();
+        assert_eq!(ids, ["TX1", "a.b"]);
+        assert!(normalize_trace_ids(vec![" ".to_string()]).is_err());
+        assert!(normalize_trace_ids((0..=MAX_MULTI_TRACE_IDS).map(|i| i.to_string()).collect()).is_err());
+
+        let command = build_multi_trace_command("/logs", &ids);
+        assert!(command.contains("*.gz) zgrep -E -e 'TX1|a\\.b' \"$f\";;"));
+        assert!(command.ends_with(&format!("| head -n {}", MAX_MULTI_TRACE_LINES + 1)));
+
+        let ids = vec!["TX1".to_string(), "TX10".to_string(), "TX2".to_string()];
+        let output = "TX1 in\nTX10 in\n[TX1] TX2 out\nTX100 only\n";
+        let (counts, total, truncated) = count_trace_ids(output, &ids);
+        let counts: Vec<u64> = counts.iter().map(|c| c.matches).collect();
+        assert_eq!(counts, [2, 1, 1]);
+        assert_eq!(total, 3);
+        assert!(!truncated);
+    }
+
+    #[test]
+    fn test_tail_command() {
+        assert_eq!(build_tail_command("/logs/a b.log", 50).unwrap(), "tail -n 50 '/logs/a b.log' 2>/dev/null");
+        assert_eq!(
+            build_tail_command("/logs/a.log.bz2", 1).unwrap(),
+            "bzcat '/logs/a.log.bz2' 2>/dev/null | tail -n 1"
+        );
+        assert!(build_tail_command("/logs/a.log", 0).is_err());
+        assert!(build_tail_command("/logs/a.log", MAX_PEEK_LINES + 1).is_err());
+    }
+
+    #[test]
+    fn test_routing_loop_warning() {
+        assert_eq!(routing_loop_warning(&[]), None);
+        let loops = vec![
+            RoutingLoop { from_ip: "10.0.0.2".to_string(), to_ip: "10.0.0.1".to_string() },
+            RoutingLoop { from_ip: "10.0.0.3".to_string(), to_ip: "10.0.0.3".to_string() },
+        ];
+        assert_eq!(
+            routing_loop_warning(&loops).unwrap(),
+            "[WARN] 2 routing loop(s) detected, check the routing configuration: \
+             10.0.0.2 -> 10.0.0.1, 10.0.0.3 -> 10.0.0.3"
+        );
+
+        // Results saved before loops were reported still load
+        let result: ChainTraceResult = serde_json::from_str(
+            r#"{"nodes":[],"trace_log":[],"total_hops":0,"duration_ms":0,"error":null}"#,
+        )
+        .unwrap();
+        assert!(result.loops.is_empty());
+    }
 }
@@ -0,0 +1,123 @@
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use std::time::Duration;
+
+use crate::connection_pool::CONNECTION_POOL;
+use crate::shell::shell_quote;
+use crate::ssh_connect::SshTarget;
+use crate::{run_remote, GrepOptions, LogCompression};
+
+// Matching lines fetched per server; one more is requested to detect the cut
+const MAX_DIFF_LINES: usize = 5000;
+
+/// Which side of `diff_remote_logs` a line was found on.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffSide {
+    Common,
+    OnlyA,
+    OnlyB,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct DiffLine {
+    pub side: DiffSide,
+    pub text: String,
+}
+
+/// The matching lines of one file on two servers, in unified-diff order.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LogDiff {
+    pub lines: Vec<DiffLine>,
+    pub common: u32,
+    pub only_a: u32,
+    pub only_b: u32,
+    // Either side had more than `MAX_DIFF_LINES` matches and was cut short
+    pub truncated: bool,
+}
+
+// The lines of `file_path` matching `trace_id`, without line numbers (they
+// differ between nodes even when the content doesn't)
+fn build_match_command(file_path: &str, trace_id: &str, grep: &GrepOptions) -> String {
+    let compression = LogCompression::detect(file_path);
+    format!(
+        "{} {} 2>/dev/null | head -n {}",
+        grep.grep_command(compression.grep_program(), "", trace_id),
+        shell_quote(file_path),
+        MAX_DIFF_LINES + 1
+    )
+}
+
+/// The lines of `file_path` on `target` matching `trace_id`.
+pub fn fetch_matching_lines(
+    target: &SshTarget,
+    file_path: &str,
+    trace_id: &str,
+    grep: &GrepOptions,
+) -> Result<String, String> {
+    let command = build_match_command(file_path, trace_id, grep);
+    CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| run_remote(sess, &command))
+}
+
+/// Diffs the matching lines of server A (`a`) against those of server B (`b`).
+pub fn diff_lines(a: &str, b: &str) -> LogDiff {
+    let mut a: Vec<&str> = a.lines().collect();
+    let mut b: Vec<&str> = b.lines().collect();
+    let truncated = a.len() > MAX_DIFF_LINES || b.len() > MAX_DIFF_LINES;
+    a.truncate(MAX_DIFF_LINES);
+    b.truncate(MAX_DIFF_LINES);
+
+    let mut diff = LogDiff {
+        lines: Vec::new(),
+        common: 0,
+        only_a: 0,
+        only_b: 0,
+        truncated,
+    };
+    for change in TextDiff::from_slices(&a, &b).iter_all_changes() {
+        let side = match change.tag() {
+            ChangeTag::Equal => {
+                diff.common += 1;
+                DiffSide::Common
+            }
+            ChangeTag::Delete => {
+                diff.only_a += 1;
+                DiffSide::OnlyA
+            }
+            ChangeTag::Insert => {
+                diff.only_b += 1;
+                DiffSide::OnlyB
+            }
+        };
+        diff.lines.push(DiffLine {
+            side,
+            text: change.value().to_string(),
+        });
+    }
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_lines() {
+        let a = "TX1 start\nTX1 route=node-a\nTX1 done\n";
+        let b = "TX1 start\nTX1 route=node-b\nTX1 done\nTX1 retry\n";
+        let diff = diff_lines(a, b);
+        assert_eq!((diff.common, diff.only_a, diff.only_b), (2, 1, 2));
+        let sides: Vec<DiffSide> = diff.lines.iter().map(|l| l.side).collect();
+        assert_eq!(
+            sides,
+            [DiffSide::Common, DiffSide::OnlyA, DiffSide::OnlyB, DiffSide::Common, DiffSide::OnlyB]
+        );
+        assert_eq!(diff.lines[1].text, "TX1 route=node-a");
+        assert!(!diff.truncated);
+
+        assert_eq!(
+            build_match_command("/logs/a.log.gz", "TX'1", &GrepOptions::default()),
+            "zgrep -F -e 'TX'\\''1' '/logs/a.log.gz' 2>/dev/null | head -n 5001"
+        );
+    }
+}
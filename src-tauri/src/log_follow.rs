@@ -0,0 +1,166 @@
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use ssh2::{Channel, Session};
+use std::io::Read;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tauri::{AppHandle, Emitter};
+use uuid::Uuid;
+
+use crate::shell::shell_quote;
+use crate::ssh_connect::{self, SshTarget};
+
+#[derive(Clone, Serialize)]
+pub struct LogFollowLine {
+    pub follow_id: String,
+    pub line: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct LogFollowEnd {
+    pub follow_id: String,
+}
+
+pub struct LogFollow {
+    channel: Channel,
+    session: Session,
+    shutdown: Arc<AtomicBool>,
+}
+
+impl LogFollow {
+    fn close(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Back to blocking so EOF/close are actually sent rather than hitting WouldBlock;
+        // the socket read timeout set at connect time bounds this on a dead link.
+        self.session.set_blocking(true);
+        let _ = self.channel.send_eof();
+        // tail -f ignores stdin EOF, so close the channel to stop it remotely
+        let _ = self.channel.close();
+        let _ = self.session.disconnect(None, "Log follow stopped", None);
+    }
+}
+
+lazy_static! {
+    pub static ref FOLLOW_MANAGER: FollowManager = FollowManager::new();
+}
+
+pub struct FollowManager {
+    follows: DashMap<String, Arc<Mutex<LogFollow>>>,
+}
+
+impl FollowManager {
+    pub fn new() -> Self {
+        Self {
+            follows: DashMap::new(),
+        }
+    }
+
+    /// Starts `tail -f` on `file_path` and streams each new line as a
+    /// `log-follow-line` event until `stop_follow` is called or the remote ends.
+    pub fn start_follow(
+        &self,
+        app_handle: AppHandle,
+        target: SshTarget,
+        file_path: String,
+    ) -> Result<String, String> {
+        let follow_id = Uuid::new_v4().to_string();
+
+        let sess = ssh_connect::connect(&target, Duration::from_secs(30))?;
+        let mut channel = sess
+            .channel_session()
+            .map_err(|e| format!("Failed to open channel: {}", e))?;
+        channel
+            .exec(&format!("tail -f {}", shell_quote(&file_path)))
+            .map_err(|e| format!("Failed to start tail: {}", e))?;
+
+        // Non-blocking so the reader can notice shutdown while the log is quiet
+        sess.set_blocking(false);
+
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let shutdown_clone = shutdown.clone();
+        let follow_id_clone = follow_id.clone();
+
+        let follow_arc = Arc::new(Mutex::new(LogFollow {
+            channel,
+            session: sess,
+            shutdown,
+        }));
+        self.follows.insert(follow_id.clone(), follow_arc.clone());
+
+        thread::spawn(move || {
+            let mut buffer = [0u8; 4096];
+            // Bytes of a line that has not been terminated yet
+            let mut pending: Vec<u8> = Vec::new();
+
+            loop {
+                if shutdown_clone.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                let bytes_read = {
+                    let mut follow = match follow_arc.lock() {
+                        Ok(f) => f,
+                        Err(_) => break,
+                    };
+
+                    match follow.channel.read(&mut buffer) {
+                        Ok(0) => break,
+                        Ok(n) => n,
+                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                            drop(follow);
+                            thread::sleep(Duration::from_millis(50));
+                            continue;
+                        }
+                        Err(_) => break,
+                    }
+                };
+
+                pending.extend_from_slice(&buffer[..bytes_read]);
+                while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                    let raw: Vec<u8> = pending.drain(..=pos).collect();
+                    let line = String::from_utf8_lossy(&raw[..raw.len() - 1])
+                        .trim_end_matches('\r')
+                        .to_string();
+                    let _ = app_handle.emit(
+                        "log-follow-line",
+                        LogFollowLine {
+                            follow_id: follow_id_clone.clone(),
+                            line,
+                        },
+                    );
+                }
+            }
+
+            FOLLOW_MANAGER.follows.remove(&follow_id_clone);
+            let _ = app_handle.emit(
+                "log-follow-end",
+                LogFollowEnd {
+                    follow_id: follow_id_clone,
+                },
+            );
+        });
+
+        Ok(follow_id)
+    }
+
+    /// Stops every follow. Returns how many were running.
+    pub fn stop_all(&self) -> usize {
+        let ids: Vec<String> = self.follows.iter().map(|entry| entry.key().clone()).collect();
+        for id in &ids {
+            let _ = self.stop_follow(id);
+        }
+        ids.len()
+    }
+
+    pub fn stop_follow(&self, follow_id: &str) -> Result<(), String> {
+        if let Some((_, follow)) = self.follows.remove(follow_id) {
+            if let Ok(mut f) = follow.lock() {
+                f.close();
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::{DateTime, FixedOffset, NaiveDateTime, TimeZone};
+use serde::Serialize;
+
+/// A log line with its leading timestamp as Unix milliseconds, if it had one.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TimestampedLine {
+    pub line: String,
+    pub epoch_ms: Option<i64>,
+}
+
+// The timestamp at the start of `line` in `format`. Formats without `%z` are
+// read as local time at `offset`.
+fn leading_timestamp(line: &str, format: &str, offset: FixedOffset) -> Option<i64> {
+    let line = line.trim_start();
+    if let Ok((time, _)) = DateTime::parse_and_remainder(line, format) {
+        return Some(time.timestamp_millis());
+    }
+    let (naive, _) = NaiveDateTime::parse_and_remainder(line, format).ok()?;
+    offset
+        .from_local_datetime(&naive)
+        .single()
+        .map(|time| time.timestamp_millis())
+}
+
+/// Reads the leading timestamp of each line with `format` (strptime-style,
+/// e.g. `%Y-%m-%d %H:%M:%S%.3f`). `utc_offset_minutes` is the servers' time
+/// zone for formats that don't carry one. Lines that don't start with a
+/// timestamp get `None`.
+pub fn parse_lines(lines: Vec<String>, format: &str, utc_offset_minutes: i32) -> Result<Vec<TimestampedLine>, String> {
+    if format.trim().is_empty() {
+        return Err("Timestamp format is empty".to_string());
+    }
+    let offset = FixedOffset::east_opt(utc_offset_minutes * 60)
+        .ok_or_else(|| format!("Invalid UTC offset: {} minutes", utc_offset_minutes))?;
+    Ok(lines
+        .into_iter()
+        .map(|line| TimestampedLine {
+            epoch_ms: leading_timestamp(&line, format, offset),
+            line,
+        })
+        .collect())
+}
+
+/// One line of a merged timeline, tagged with the server it came from.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TimelineEntry {
+    pub host: String,
+    pub epoch_ms: Option<i64>,
+    pub line: String,
+    // The line had no timestamp in the expected format; it sorts to the end
+    pub unparsed: bool,
+}
+
+/// Merges the lines read from each host into one time-ordered list. Lines
+/// with equal or missing timestamps keep their order within their host.
+pub fn merge_timeline(
+    outputs: Vec<(String, String)>,
+    format: &str,
+    utc_offset_minutes: i32,
+) -> Result<Vec<TimelineEntry>, String> {
+    let mut entries = Vec::new();
+    for (host, output) in outputs {
+        let lines = output.lines().map(str::to_string).collect();
+        for parsed in parse_lines(lines, format, utc_offset_minutes)? {
+            entries.push(TimelineEntry {
+                host: host.clone(),
+                epoch_ms: parsed.epoch_ms,
+                unparsed: parsed.epoch_ms.is_none(),
+                line: parsed.line,
+            });
+        }
+    }
+    // Stable, so ties stay in the order they were read
+    entries.sort_by_key(|entry| (entry.unparsed, entry.epoch_ms));
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_leading_timestamps() {
+        let lines = vec![
+            "2024-03-01 12:00:00.250 INFO TX1 start".to_string(),
+            "  2024-03-01 12:00:01.000 WARN TX1 slow".to_string(),
+            "\tat com.example.Gateway.route(Gateway.java:42)".to_string(),
+        ];
+        let parsed = parse_lines(lines, "%Y-%m-%d %H:%M:%S%.3f", 0).unwrap();
+        assert_eq!(parsed[0].epoch_ms, Some(1_709_294_400_250));
+        assert_eq!(parsed[1].epoch_ms, Some(1_709_294_401_000));
+        assert_eq!(parsed[2].epoch_ms, None);
+        assert_eq!(parsed[2].line, "\tat com.example.Gateway.route(Gateway.java:42)");
+
+        // Local time at UTC+8, and an explicit offset in the line itself
+        let parsed = parse_lines(vec!["2024-03-01 20:00:00 x".to_string()], "%Y-%m-%d %H:%M:%S", 480).unwrap();
+        assert_eq!(parsed[0].epoch_ms, Some(1_709_294_400_000));
+        let parsed = parse_lines(vec!["[01/Mar/2024:12:00:00 +0000] GET /".to_string()], "[%d/%b/%Y:%H:%M:%S %z]", 480)
+            .unwrap();
+        assert_eq!(parsed[0].epoch_ms, Some(1_709_294_400_000));
+
+        assert!(parse_lines(Vec::new(), " ", 0).is_err());
+        assert!(parse_lines(Vec::new(), "%Y", 24 * 60).is_err());
+    }
+
+    #[test]
+    fn test_merge_timeline() {
+        let outputs = vec![
+            (
+                "gw".to_string(),
+                "2024-03-01 12:00:00 TX1 in\nno timestamp TX1\n2024-03-01 12:00:03 TX1 out\n".to_string(),
+            ),
+            ("svc".to_string(), "2024-03-01 12:00:01 TX1 handle\n2024-03-01 12:00:03 TX1 done\n".to_string()),
+        ];
+        let merged = merge_timeline(outputs, "%Y-%m-%d %H:%M:%S", 0).unwrap();
+        let order: Vec<(&str, &str)> = merged
+            .iter()
+            .map(|e| (e.host.as_str(), e.line.rsplit("TX1 ").next().unwrap()))
+            .collect();
+        assert_eq!(
+            order,
+            [("gw", "in"), ("svc", "handle"), ("gw", "out"), ("svc", "done"), ("gw", "no timestamp TX1")]
+        );
+        assert!(merged[4].unparsed);
+        assert_eq!(merged[4].epoch_ms, None);
+    }
+}
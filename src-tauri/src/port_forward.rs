@@ -0,0 +1,198 @@
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use ssh2::Session;
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::ssh_connect::{self, SshTarget};
+
+#[derive(Clone, Serialize)]
+pub struct PortForwardInfo {
+    pub forward_id: String,
+    pub local_port: u16,
+}
+
+lazy_static! {
+    pub static ref PORT_FORWARD_MANAGER: PortForwardManager = PortForwardManager::new();
+}
+
+pub struct PortForwardManager {
+    // Stop flags of the listener and relay threads, keyed by forward id. The
+    // listener thread removes its own entry when it exits.
+    forwards: Arc<DashMap<String, Arc<AtomicBool>>>,
+}
+
+// Bridges one accepted local connection to `remote_host:remote_port` through
+// its own SSH session, so connections don't contend for one non-blocking session.
+// `checked` is the session `start_forward` logged in with; it serves the first
+// connection, falling back to a new login if it no longer opens channels.
+fn forward_connection(
+    target: &SshTarget,
+    checked: Option<Session>,
+    remote_host: &str,
+    remote_port: u16,
+    socket: TcpStream,
+    shutdown: &AtomicBool,
+) -> Result<(), String> {
+    let open = |sess: &Session| {
+        sess.channel_direct_tcpip(remote_host, remote_port, None)
+            .map_err(|e| format!("{} could not reach {}:{}: {}", target.host, remote_host, remote_port, e))
+    };
+    let reused = checked.and_then(|sess| open(&sess).ok().map(|channel| (sess, channel)));
+    let (sess, channel) = match reused {
+        Some(opened) => opened,
+        None => {
+            let sess = ssh_connect::connect(target, Duration::from_secs(30))?;
+            let channel = open(&sess)?;
+            (sess, channel)
+        }
+    };
+    ssh_connect::relay_channel(sess, channel, socket, shutdown);
+    Ok(())
+}
+
+impl PortForwardManager {
+    pub fn new() -> Self {
+        Self {
+            forwards: Arc::new(DashMap::new()),
+        }
+    }
+
+    /// Listens on `127.0.0.1:local_port` (0 picks a free port) and relays every
+    /// connection to `remote_host:remote_port` from the server's side.
+    pub fn start_forward(
+        &self,
+        target: SshTarget,
+        remote_host: String,
+        remote_port: u16,
+        local_port: u16,
+    ) -> Result<PortForwardInfo, String> {
+        // Fail now on bad credentials rather than on the first connection,
+        // which then reuses this session instead of logging in again
+        let checked = ssh_connect::connect(&target, Duration::from_secs(30))?;
+        let checked = Mutex::new(Some(checked));
+
+        self.listen(local_port, move |socket, shutdown| {
+            let checked = checked.lock().ok().and_then(|mut checked| checked.take());
+            if let Err(e) = forward_connection(&target, checked, &remote_host, remote_port, socket, shutdown) {
+                tracing::warn!(remote_host = %remote_host, remote_port, error = %e, "port forward connection failed");
+            }
+        })
+    }
+
+    // Listens on `127.0.0.1:local_port` and hands every accepted connection to
+    // `relay` on its own thread, until `stop_forward` or an accept error
+    fn listen<F>(&self, local_port: u16, relay: F) -> Result<PortForwardInfo, String>
+    where
+        F: Fn(TcpStream, &AtomicBool) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind(("127.0.0.1", local_port))
+            .map_err(|e| format!("Failed to listen on local port {}: {}", local_port, e))?;
+        let local_port = listener.local_addr().map_err(|e| e.to_string())?.port();
+        // Polled, so the listener notices `stop_forward`
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to configure listener: {}", e))?;
+
+        let forward_id = Uuid::new_v4().to_string();
+        let shutdown = Arc::new(AtomicBool::new(false));
+        self.forwards.insert(forward_id.clone(), shutdown.clone());
+
+        let forwards = self.forwards.clone();
+        let id = forward_id.clone();
+        let relay = Arc::new(relay);
+        thread::spawn(move || {
+            while !shutdown.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((socket, _)) => {
+                        let relay = relay.clone();
+                        let shutdown = shutdown.clone();
+                        thread::spawn(move || relay(socket, &shutdown));
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(50));
+                    }
+                    Err(e) => {
+                        tracing::error!(local_port, error = %e, "port forward listener failed");
+                        break;
+                    }
+                }
+            }
+            // A failed listener takes its connections down with it, as
+            // `stop_forward` would, since nothing can stop them afterwards
+            shutdown.store(true, Ordering::SeqCst);
+            forwards.remove(&id);
+        });
+
+        Ok(PortForwardInfo {
+            forward_id,
+            local_port,
+        })
+    }
+
+    /// Stops every forward. Returns how many there were.
+    pub fn stop_all(&self) -> usize {
+        let ids: Vec<String> = self.forwards.iter().map(|entry| entry.key().clone()).collect();
+        for id in &ids {
+            let _ = self.stop_forward(id);
+        }
+        ids.len()
+    }
+
+    /// Closes the listener and every connection relayed through it.
+    pub fn stop_forward(&self, forward_id: &str) -> Result<(), String> {
+        if let Some((_, shutdown)) = self.forwards.remove(forward_id) {
+            shutdown.store(true, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait_until(condition: impl Fn() -> bool) -> bool {
+        for _ in 0..40 {
+            if condition() {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+
+    #[test]
+    fn test_listen_on_port_zero_reports_bound_port() {
+        let manager = PortForwardManager::new();
+        let (accepted, received) = std::sync::mpsc::channel();
+        let accepted = Mutex::new(accepted);
+        let info = manager
+            .listen(0, move |_, _| {
+                let _ = accepted.lock().unwrap().send(());
+            })
+            .unwrap();
+
+        assert_ne!(info.local_port, 0);
+        TcpStream::connect(("127.0.0.1", info.local_port)).unwrap();
+        received.recv_timeout(Duration::from_secs(2)).unwrap();
+        manager.stop_forward(&info.forward_id).unwrap();
+    }
+
+    #[test]
+    fn test_stop_forward_closes_listener_and_removes_entry() {
+        let manager = PortForwardManager::new();
+        let info = manager.listen(0, |_, _| {}).unwrap();
+        assert!(manager.forwards.contains_key(&info.forward_id));
+
+        manager.stop_forward(&info.forward_id).unwrap();
+        assert!(manager.forwards.is_empty());
+        assert!(wait_until(|| TcpStream::connect(("127.0.0.1", info.local_port)).is_err()));
+    }
+}
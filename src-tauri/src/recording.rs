@@ -0,0 +1,104 @@
+use serde_json::json;
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Writes a terminal session as an asciinema v2 `.cast` file: a JSON header
+/// line followed by one `[seconds, type, data]` event per line.
+///
+/// Every line is flushed as soon as it is written, so a crash or forced quit
+/// leaves a file that still plays up to the last frame.
+pub struct CastRecorder {
+    out: LineWriter<File>,
+    started: Instant,
+    record_input: bool,
+}
+
+impl CastRecorder {
+    pub fn create(path: &str, cols: u32, rows: u32, record_input: bool) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("Failed to create recording {}: {}", path, e))?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut recorder = Self {
+            out: LineWriter::new(file),
+            started: Instant::now(),
+            record_input,
+        };
+        let header = json!({
+            "version": 2,
+            "width": cols,
+            "height": rows,
+            "timestamp": timestamp,
+            "env": { "TERM": "xterm-256color" },
+        });
+        recorder
+            .write_line(&header.to_string())
+            .map_err(|e| format!("Failed to write recording {}: {}", path, e))?;
+        Ok(recorder)
+    }
+
+    fn write_line(&mut self, line: &str) -> std::io::Result<()> {
+        self.out.write_all(line.as_bytes())?;
+        self.out.write_all(b"\n")
+    }
+
+    fn event(&mut self, kind: &str, data: &str) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        // A failed write loses that frame; the session itself keeps running
+        let _ = self.write_line(&json!([elapsed, kind, data]).to_string());
+    }
+
+    pub fn output(&mut self, data: &str) {
+        self.event("o", data);
+    }
+
+    pub fn input(&mut self, data: &str) {
+        if self.record_input {
+            self.event("i", data);
+        }
+    }
+
+    pub fn resize(&mut self, cols: u32, rows: u32) {
+        self.event("r", &format!("{}x{}", cols, rows));
+    }
+
+    pub fn finish(&mut self) {
+        let _ = self.out.flush();
+        let _ = self.out.get_ref().sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cast_file_layout() {
+        let path = std::env::temp_dir().join(format!("session_{}.cast", uuid::Uuid::new_v4()));
+        let path_str = path.to_str().unwrap();
+
+        let mut recorder = CastRecorder::create(path_str, 80, 24, false).unwrap();
+        recorder.output("$ ls\r\n");
+        recorder.input("ls\r");
+        recorder.resize(100, 30);
+        // Lines are flushed as they are written, before finish() is called
+        let content = std::fs::read_to_string(&path).unwrap();
+        recorder.finish();
+        let _ = std::fs::remove_file(&path);
+
+        let lines: Vec<serde_json::Value> = content
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0]["version"], 2);
+        assert_eq!(lines[0]["width"], 80);
+        assert_eq!(lines[0]["height"], 24);
+        assert_eq!(lines[1][1], "o");
+        assert_eq!(lines[1][2], "$ ls\r\n");
+        assert_eq!(lines[2][1], "r");
+        assert_eq!(lines[2][2], "100x30");
+    }
+}
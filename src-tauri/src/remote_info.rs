@@ -0,0 +1,196 @@
+use serde::Serialize;
+use std::io::Read;
+use std::time::Duration;
+
+use crate::connection_pool::CONNECTION_POOL;
+use crate::shell::shell_quote;
+use crate::ssh_connect::SshTarget;
+use crate::SystemInfo;
+
+const KB_PER_GB: f64 = 1_048_576.0;
+
+/// `SystemInfo` of a remote server, plus usage of the partition holding `disk_path`.
+#[derive(Serialize)]
+pub struct RemoteSystemInfo {
+    #[serde(flatten)]
+    pub system: SystemInfo,
+    pub disk_path: String,
+    pub disk_used_gb: f64,
+    pub disk_total_gb: f64,
+    pub disk_usage_percent: f32,
+}
+
+// Prints the OS name, two samples of the /proc/stat CPU lines a second apart,
+// the meminfo totals and one `df` line, each recognisable by its first word
+fn build_probe_command(disk_path: &str) -> String {
+    format!(
+        "echo \"os $(uname -s)\"; \
+         if [ -r /proc/stat ]; then grep '^cpu' /proc/stat; sleep 1; grep '^cpu' /proc/stat; fi; \
+         grep -E '^(MemTotal|MemAvailable):' /proc/meminfo 2>/dev/null; \
+         df -Pk {} 2>/dev/null | tail -n 1 | awk '{{print \"disk\", $2, $3}}'",
+        shell_quote(disk_path)
+    )
+}
+
+fn percent(part: f64, whole: f64) -> f32 {
+    if whole > 0.0 {
+        (part / whole * 100.0) as f32
+    } else {
+        0.0
+    }
+}
+
+// Usage between two (busy, total) jiffy samples of the same CPU
+fn cpu_percent(first: (u64, u64), second: (u64, u64)) -> f32 {
+    percent(second.0.saturating_sub(first.0) as f64, second.1.saturating_sub(first.1) as f64)
+}
+
+// (busy, total) jiffies of a `cpu  user nice system idle iowait ...` line
+fn cpu_sample(line: &str) -> Option<(u64, u64)> {
+    let values: Vec<u64> = line
+        .split_whitespace()
+        .skip(1)
+        .map(|v| v.parse().ok())
+        .collect::<Option<_>>()?;
+    if values.len() < 4 {
+        return None;
+    }
+    let total: u64 = values.iter().sum();
+    let idle = values[3] + values.get(4).copied().unwrap_or(0);
+    Some((total - idle, total))
+}
+
+fn parse_probe_output(output: &str, disk_path: &str) -> Result<RemoteSystemInfo, String> {
+    let mut os = "";
+    // Samples per CPU name ("cpu" is the total), in the order they appear
+    let mut cpu_samples: Vec<(String, Vec<(u64, u64)>)> = Vec::new();
+    let mut mem_total_kb = None;
+    let mut mem_available_kb = None;
+    let mut disk = None;
+
+    for line in output.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("os") => os = line[2..].trim(),
+            Some(name) if name.starts_with("cpu") => {
+                if let Some(sample) = cpu_sample(line) {
+                    match cpu_samples.iter_mut().find(|(n, _)| n == name) {
+                        Some((_, samples)) => samples.push(sample),
+                        None => cpu_samples.push((name.to_string(), vec![sample])),
+                    }
+                }
+            }
+            Some("MemTotal:") => mem_total_kb = fields.next().and_then(|v| v.parse::<u64>().ok()),
+            Some("MemAvailable:") => mem_available_kb = fields.next().and_then(|v| v.parse::<u64>().ok()),
+            Some("disk") => {
+                let total = fields.next().and_then(|v| v.parse::<u64>().ok());
+                let used = fields.next().and_then(|v| v.parse::<u64>().ok());
+                disk = total.zip(used);
+            }
+            _ => {}
+        }
+    }
+
+    // BSD and macOS have no /proc; their sysctl/vm_stat output isn't parsed
+    if os != "Linux" {
+        return Err(format!("Unsupported platform: {}", if os.is_empty() { "unknown" } else { os }));
+    }
+
+    let mut cpu_usage = None;
+    let mut per_core = Vec::new();
+    for (name, samples) in &cpu_samples {
+        if let [first, second, ..] = samples[..] {
+            if name == "cpu" {
+                cpu_usage = Some(cpu_percent(first, second));
+            } else {
+                per_core.push(cpu_percent(first, second));
+            }
+        }
+    }
+    let cpu_usage = cpu_usage.ok_or("Failed to read /proc/stat")?;
+    let (mem_total_kb, mem_available_kb) = mem_total_kb
+        .zip(mem_available_kb)
+        .ok_or("Failed to read /proc/meminfo")?;
+    let (disk_total_kb, disk_used_kb) =
+        disk.ok_or_else(|| format!("Failed to read disk usage of {}", disk_path))?;
+
+    let memory_total = mem_total_kb as f64 / KB_PER_GB;
+    let memory_used = mem_total_kb.saturating_sub(mem_available_kb) as f64 / KB_PER_GB;
+    let disk_total = disk_total_kb as f64 / KB_PER_GB;
+    let disk_used = disk_used_kb as f64 / KB_PER_GB;
+
+    Ok(RemoteSystemInfo {
+        system: SystemInfo {
+            cpu_usage,
+            memory_used_gb: memory_used,
+            memory_total_gb: memory_total,
+            memory_usage_percent: percent(memory_used, memory_total),
+            per_core,
+            // The log partition is reported below; network rates aren't sampled
+            disks: None,
+            net_rx_bytes_per_sec: None,
+            net_tx_bytes_per_sec: None,
+        },
+        disk_path: disk_path.to_string(),
+        disk_used_gb: disk_used,
+        disk_total_gb: disk_total,
+        disk_usage_percent: percent(disk_used, disk_total),
+    })
+}
+
+/// Samples CPU, memory and disk usage of a Linux server over SSH. Takes about
+/// a second, since CPU usage is measured between two `/proc/stat` readings.
+pub fn fetch(target: &SshTarget, disk_path: &str) -> Result<RemoteSystemInfo, String> {
+    let command = build_probe_command(disk_path);
+    let output = CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+        let mut channel = sess
+            .channel_session()
+            .map_err(|e| format!("Failed to open channel: {}", e))?;
+        channel
+            .exec(&command)
+            .map_err(|e| format!("Failed to execute command: {}", e))?;
+        let mut output = String::new();
+        channel
+            .read_to_string(&mut output)
+            .map_err(|e| format!("Failed to read output: {}", e))?;
+        channel.wait_close().ok();
+        Ok(output)
+    })?;
+    parse_probe_output(&output, disk_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_linux_probe() {
+        let output = "os Linux\n\
+                      cpu  100 0 100 700 100 0 0 0 0 0\n\
+                      cpu0 50 0 50 350 50 0 0 0 0 0\n\
+                      cpu1 50 0 50 350 50 0 0 0 0 0\n\
+                      cpu  150 0 150 750 150 0 0 0 0 0\n\
+                      cpu0 100 0 100 350 50 0 0 0 0 0\n\
+                      cpu1 50 0 50 400 50 0 0 0 0 0\n\
+                      MemTotal:        8388608 kB\n\
+                      MemAvailable:    2097152 kB\n\
+                      disk 104857600 26214400\n";
+        let info = parse_probe_output(output, "/var/log").unwrap();
+        assert_eq!(info.system.cpu_usage, 50.0);
+        assert_eq!(info.system.per_core, [100.0, 0.0]);
+        assert_eq!(info.system.memory_total_gb, 8.0);
+        assert_eq!(info.system.memory_used_gb, 6.0);
+        assert_eq!(info.system.memory_usage_percent, 75.0);
+        assert_eq!(info.disk_total_gb, 100.0);
+        assert_eq!(info.disk_usage_percent, 25.0);
+    }
+
+    #[test]
+    fn test_non_linux_is_unsupported() {
+        let output = "os Darwin\ndisk 1000 500\n";
+        assert_eq!(
+            parse_probe_output(output, "/").err().unwrap(),
+            "Unsupported platform: Darwin"
+        );
+    }
+}
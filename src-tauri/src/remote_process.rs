@@ -0,0 +1,130 @@
+use serde::Serialize;
+use ssh2::Session;
+use std::io::Read;
+use std::time::Duration;
+
+use crate::ssh_connect::{self, SshTarget};
+
+// Portable across Linux and the BSDs; the trailing `=` drops the header line
+const PS_COMMAND: &str = "ps -eo pid=,user=,pcpu=,pmem=,args=";
+
+/// One row of `list_remote_processes`.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    pub user: String,
+    pub cpu: f32,
+    pub mem: f32,
+    pub command: String,
+}
+
+// Runs `command` and returns its stdout and exit status
+fn run(sess: &Session, command: &str) -> Result<(String, i32), String> {
+    let mut channel = sess
+        .channel_session()
+        .map_err(|e| format!("Channel failed: {}", e))?;
+    channel
+        .exec(command)
+        .map_err(|e| format!("Exec failed: {}", e))?;
+    let mut stdout = String::new();
+    channel
+        .read_to_string(&mut stdout)
+        .map_err(|e| format!("Read failed: {}", e))?;
+    channel.wait_close().ok();
+    Ok((stdout, channel.exit_status().unwrap_or(-1)))
+}
+
+fn parse_ps_line(line: &str) -> Option<ProcessInfo> {
+    let mut fields = line.split_whitespace();
+    let pid = fields.next()?.parse().ok()?;
+    let user = fields.next()?.to_string();
+    let cpu = fields.next()?.parse().ok()?;
+    let mem = fields.next()?.parse().ok()?;
+    let command = fields.collect::<Vec<_>>().join(" ");
+    Some(ProcessInfo {
+        pid,
+        user,
+        cpu,
+        mem,
+        command,
+    })
+}
+
+fn parse_ps_output(output: &str, filter: Option<&str>) -> Vec<ProcessInfo> {
+    let filter = filter.map(str::to_lowercase).filter(|f| !f.is_empty());
+    output
+        .lines()
+        .filter_map(parse_ps_line)
+        .filter(|p| match &filter {
+            Some(f) => p.command.to_lowercase().contains(f) || p.user.to_lowercase().contains(f),
+            None => true,
+        })
+        .collect()
+}
+
+/// Processes on the server, optionally only those whose command line or user
+/// contains `filter` (case-insensitive). Filtering happens locally, so the
+/// filter text never reaches the remote shell.
+pub fn list(target: &SshTarget, filter: Option<&str>) -> Result<Vec<ProcessInfo>, String> {
+    let sess = ssh_connect::connect(target, Duration::from_secs(30))?;
+    let (output, _) = run(&sess, PS_COMMAND)?;
+    Ok(parse_ps_output(&output, filter))
+}
+
+// Builds `kill -<signal> <pid>`. The pid is already a number; pid 0 would
+// signal the whole process group, so it is refused. Signals are names like
+// TERM/SIGKILL or numbers, nothing the shell could interpret.
+fn kill_command(pid: u32, signal: &str) -> Result<String, String> {
+    if pid == 0 {
+        return Err("Invalid pid: 0".to_string());
+    }
+    let signal = signal.trim();
+    if signal.is_empty() || signal.len() > 10 || !signal.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Invalid signal: {:?}", signal));
+    }
+    Ok(format!("kill -{} {}", signal, pid))
+}
+
+/// Sends `signal` to `pid` and returns the exit status of `kill`.
+pub fn kill(target: &SshTarget, pid: u32, signal: &str) -> Result<i32, String> {
+    let command = kill_command(pid, signal)?;
+    let sess = ssh_connect::connect(target, Duration::from_secs(30))?;
+    let (_, exit_status) = run(&sess, &command)?;
+    Ok(exit_status)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ps_output() {
+        let output = "    1 root      0.0  0.1 /sbin/init splash\n\
+                      4242 app      97.5  3.2 java -jar gateway.jar --port 8080\n\
+                      garbage\n";
+        let all = parse_ps_output(output, None);
+        assert_eq!(all.len(), 2);
+        assert_eq!(
+            all[1],
+            ProcessInfo {
+                pid: 4242,
+                user: "app".to_string(),
+                cpu: 97.5,
+                mem: 3.2,
+                command: "java -jar gateway.jar --port 8080".to_string(),
+            }
+        );
+        let filtered = parse_ps_output(output, Some("GATEWAY"));
+        assert_eq!(filtered.len(), 1);
+        assert_eq!(filtered[0].pid, 4242);
+    }
+
+    #[test]
+    fn test_kill_command_validation() {
+        assert_eq!(kill_command(4242, "TERM").unwrap(), "kill -TERM 4242");
+        assert_eq!(kill_command(4242, "9").unwrap(), "kill -9 4242");
+        assert!(kill_command(0, "TERM").is_err());
+        assert!(kill_command(4242, "9; rm -rf /").is_err());
+        assert!(kill_command(4242, "").is_err());
+    }
+}
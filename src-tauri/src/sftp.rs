@@ -0,0 +1,529 @@
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::connection_pool::CONNECTION_POOL;
+use crate::ssh_connect::{self, SshTarget};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+// Progress events are throttled so a fast link doesn't flood the frontend
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Serialize)]
+pub struct TransferProgress {
+    pub remote_path: String,
+    pub local_path: String,
+    pub bytes_transferred: u64,
+    pub total_bytes: Option<u64>,
+}
+
+fn io_error_message(action: &str, path: &str, e: &io::Error) -> String {
+    if e.kind() == io::ErrorKind::StorageFull {
+        format!("Disk full while {} {}", action, path)
+    } else {
+        format!("Failed while {} {}: {}", action, path, e)
+    }
+}
+
+/// Copies `source` to `dest` in chunks, calling `on_progress` with the running
+/// byte count. `write_all` takes care of short writes.
+fn copy_chunks(
+    source: &mut impl Read,
+    source_name: &str,
+    dest: &mut impl Write,
+    dest_name: &str,
+    mut on_progress: impl FnMut(u64),
+) -> Result<u64, String> {
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut transferred = 0u64;
+    let mut last_report = Instant::now();
+
+    loop {
+        let n = match source.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(io_error_message("reading", source_name, &e)),
+        };
+        dest.write_all(&buffer[..n])
+            .map_err(|e| io_error_message("writing", dest_name, &e))?;
+        transferred += n as u64;
+
+        if last_report.elapsed() >= PROGRESS_INTERVAL {
+            on_progress(transferred);
+            last_report = Instant::now();
+        }
+    }
+
+    dest.flush()
+        .map_err(|e| io_error_message("writing", dest_name, &e))?;
+    on_progress(transferred);
+    Ok(transferred)
+}
+
+fn emit_progress(
+    app_handle: &AppHandle,
+    event: &str,
+    remote_path: &str,
+    local_path: &str,
+    bytes_transferred: u64,
+    total_bytes: Option<u64>,
+) {
+    let _ = app_handle.emit(
+        event,
+        TransferProgress {
+            remote_path: remote_path.to_string(),
+            local_path: local_path.to_string(),
+            bytes_transferred,
+            total_bytes,
+        },
+    );
+}
+
+/// Downloads `remote_path` over SFTP to `local_path`, emitting `download-progress`.
+/// Data goes to `<local_path>.part` first, so an interrupted transfer never
+/// leaves a truncated file under the final name. Returns the bytes written.
+pub fn download(
+    app_handle: &AppHandle,
+    target: &SshTarget,
+    remote_path: &str,
+    local_path: &str,
+) -> Result<u64, String> {
+    let sess = ssh_connect::connect(target, Duration::from_secs(30))?;
+    let sftp = sess
+        .sftp()
+        .map_err(|e| format!("Failed to start SFTP: {}", e))?;
+
+    let total = sftp
+        .stat(Path::new(remote_path))
+        .map_err(|e| format!("Failed to stat {}: {}", remote_path, e))?
+        .size;
+    let mut remote = sftp
+        .open(Path::new(remote_path))
+        .map_err(|e| format!("Failed to open {}: {}", remote_path, e))?;
+
+    let part_path = PathBuf::from(format!("{}.part", local_path));
+    let mut local = File::create(&part_path)
+        .map_err(|e| io_error_message("creating", local_path, &e))?;
+
+    let result = copy_chunks(&mut remote, remote_path, &mut local, local_path, |done| {
+        emit_progress(app_handle, "download-progress", remote_path, local_path, done, total)
+    })
+    // Some filesystems only report a full disk once the data is synced
+    .and_then(|n| {
+        local
+            .sync_all()
+            .map_err(|e| io_error_message("writing", local_path, &e))?;
+        Ok(n)
+    });
+    drop(local);
+
+    match result {
+        Ok(n) => {
+            fs::rename(&part_path, local_path)
+                .map_err(|e| io_error_message("saving", local_path, &e))?;
+            Ok(n)
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&part_path);
+            Err(e)
+        }
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct BulkDownloadProgress {
+    pub remote_path: String,
+    pub files_done: u32,
+    pub files_total: u32,
+    pub error: Option<String>, // Set when this file was skipped
+}
+
+#[derive(Clone, Serialize)]
+pub struct FailedDownload {
+    pub remote_path: String,
+    pub error: String,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BulkDownloadResult {
+    pub zip_path: String,
+    pub files_written: u32,
+    pub bytes_written: u64,
+    pub failed: Vec<FailedDownload>,
+}
+
+// Path of a remote file inside the archive: its remote path without the
+// leading `/`, and without `.`/`..` parts that could escape the extract dir
+fn archive_name(remote_path: &str) -> String {
+    remote_path
+        .split('/')
+        .filter(|part| !part.is_empty() && *part != "." && *part != "..")
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Downloads every file in `remote_paths` over one SFTP session into a zip at
+/// `zip_path`, keeping their remote directories. A file that fails is skipped
+/// and reported; `bulk-download-progress` is emitted after each file.
+pub fn download_zip(
+    app_handle: &AppHandle,
+    target: &SshTarget,
+    remote_paths: &[String],
+    zip_path: &str,
+) -> Result<BulkDownloadResult, String> {
+    let sess = ssh_connect::connect(target, Duration::from_secs(30))?;
+    let sftp = sess
+        .sftp()
+        .map_err(|e| format!("Failed to start SFTP: {}", e))?;
+
+    let part_path = PathBuf::from(format!("{}.part", zip_path));
+    let file = File::create(&part_path)
+        .map_err(|e| io_error_message("creating", zip_path, &e))?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(true);
+
+    let mut files_written = 0;
+    let mut bytes_written = 0;
+    let mut failed = Vec::new();
+    let files_total = remote_paths.len() as u32;
+
+    for (i, remote_path) in remote_paths.iter().enumerate() {
+        let result = sftp
+            .open(Path::new(remote_path))
+            .map_err(|e| format!("Failed to open {}: {}", remote_path, e))
+            .and_then(|mut remote| {
+                zip.start_file(archive_name(remote_path), options)
+                    .map_err(|e| format!("Failed to add {} to the archive: {}", remote_path, e))?;
+                copy_chunks(&mut remote, remote_path, &mut zip, zip_path, |_| {}).inspect_err(|_| {
+                    // Drop the half-written entry
+                    let _ = zip.abort_file();
+                })
+            });
+
+        let error = match result {
+            Ok(n) => {
+                files_written += 1;
+                bytes_written += n;
+                None
+            }
+            Err(e) => {
+                failed.push(FailedDownload {
+                    remote_path: remote_path.clone(),
+                    error: e.clone(),
+                });
+                Some(e)
+            }
+        };
+        let _ = app_handle.emit(
+            "bulk-download-progress",
+            BulkDownloadProgress {
+                remote_path: remote_path.clone(),
+                files_done: i as u32 + 1,
+                files_total,
+                error,
+            },
+        );
+    }
+
+    let finished = zip
+        .finish()
+        .map_err(|e| format!("Failed to write {}: {}", zip_path, e))
+        .and_then(|file| file.sync_all().map_err(|e| io_error_message("writing", zip_path, &e)));
+    if let Err(e) = finished {
+        let _ = fs::remove_file(&part_path);
+        return Err(e);
+    }
+    if files_written == 0 && !failed.is_empty() {
+        let _ = fs::remove_file(&part_path);
+        return Err(format!("None of the {} files could be downloaded: {}", files_total, failed[0].error));
+    }
+    fs::rename(&part_path, zip_path)
+        .map_err(|e| io_error_message("saving", zip_path, &e))?;
+
+    Ok(BulkDownloadResult {
+        zip_path: zip_path.to_string(),
+        files_written,
+        bytes_written,
+        failed,
+    })
+}
+
+/// One entry of `list_dir`.
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct RemoteDirEntry {
+    pub name: String,
+    pub is_dir: bool,
+    pub size: Option<u64>,
+    // Unix seconds
+    pub modified: Option<u64>,
+    // `ls -l` style, e.g. "drwxr-x---"
+    pub permissions: Option<String>,
+    // Set when the entry couldn't be inspected; it is still listed
+    pub error: Option<String>,
+}
+
+fn format_permissions(perm: u32) -> String {
+    let mut mode = String::with_capacity(10);
+    mode.push(match perm & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        _ => '-',
+    });
+    for shift in [6, 3, 0] {
+        let bits = perm >> shift;
+        mode.push(if bits & 4 != 0 { 'r' } else { '-' });
+        mode.push(if bits & 2 != 0 { 'w' } else { '-' });
+        mode.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    mode
+}
+
+// SFTP status codes from libssh2
+const LIBSSH2_FX_NO_SUCH_FILE: i32 = 2;
+const LIBSSH2_FX_PERMISSION_DENIED: i32 = 3;
+
+fn entry_error(e: &ssh2::Error) -> String {
+    match e.code() {
+        ssh2::ErrorCode::SFTP(LIBSSH2_FX_NO_SUCH_FILE) => "Broken link".to_string(),
+        ssh2::ErrorCode::SFTP(LIBSSH2_FX_PERMISSION_DENIED) => "Permission denied".to_string(),
+        _ => e.to_string(),
+    }
+}
+
+fn dir_entry(name: String, stat: &ssh2::FileStat, error: Option<String>) -> RemoteDirEntry {
+    // The server may leave out attributes it won't disclose
+    let error = error.or_else(|| stat.perm.is_none().then(|| "Permission denied".to_string()));
+    RemoteDirEntry {
+        name,
+        is_dir: stat.is_dir(),
+        size: stat.size,
+        modified: stat.mtime,
+        permissions: stat.perm.map(format_permissions),
+        error,
+    }
+}
+
+// Directories first, then by name
+fn sort_listing(entries: &mut [RemoteDirEntry]) {
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+}
+
+/// Lists the directory at `path` (the login directory when empty). Symlinks
+/// are described by what they point to; entries that can't be inspected are
+/// returned with `error` set rather than failing the listing.
+pub fn list_dir(target: &SshTarget, path: &str) -> Result<Vec<RemoteDirEntry>, String> {
+    let path = if path.trim().is_empty() { "." } else { path };
+    CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+        let sftp = sess
+            .sftp()
+            .map_err(|e| format!("Failed to start SFTP: {}", e))?;
+        let entries = sftp
+            .readdir(Path::new(path))
+            .map_err(|e| format!("Failed to list {}: {}", path, entry_error(&e)))?;
+
+        let mut listing: Vec<RemoteDirEntry> = entries
+            .into_iter()
+            .map(|(entry_path, stat)| {
+                let name = entry_path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| entry_path.to_string_lossy().into_owned());
+                if !stat.file_type().is_symlink() {
+                    return dir_entry(name, &stat, None);
+                }
+                match sftp.stat(&entry_path) {
+                    Ok(resolved) => dir_entry(name, &resolved, None),
+                    Err(e) => dir_entry(name, &stat, Some(entry_error(&e))),
+                }
+            })
+            .collect();
+        sort_listing(&mut listing);
+        Ok(listing)
+    })
+}
+
+/// Size and age of a remote file, from `stat_file`.
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct RemoteFileStat {
+    pub size_bytes: Option<u64>,
+    // Unix seconds
+    pub modified_epoch: Option<u64>,
+    // `ls -l` style, e.g. "-rw-r-----"
+    pub mode: Option<String>,
+    pub is_dir: bool,
+}
+
+// Tells a missing file apart from one the user may not see
+fn stat_error(path: &str, e: &ssh2::Error) -> String {
+    match e.code() {
+        ssh2::ErrorCode::SFTP(LIBSSH2_FX_NO_SUCH_FILE) => format!("Not found: {}", path),
+        ssh2::ErrorCode::SFTP(LIBSSH2_FX_PERMISSION_DENIED) => format!("Permission denied: {}", path),
+        _ => format!("Failed to stat {}: {}", path, e),
+    }
+}
+
+/// Stats `path` over SFTP without reading it, following symlinks.
+pub fn stat_file(target: &SshTarget, path: &str) -> Result<RemoteFileStat, String> {
+    CONNECTION_POOL.with_session(target, Duration::from_secs(30), |sess| {
+        let sftp = sess
+            .sftp()
+            .map_err(|e| format!("Failed to start SFTP: {}", e))?;
+        let stat = sftp.stat(Path::new(path)).map_err(|e| stat_error(path, &e))?;
+        Ok(RemoteFileStat {
+            size_bytes: stat.size,
+            modified_epoch: stat.mtime,
+            mode: stat.perm.map(format_permissions),
+            is_dir: stat.is_dir(),
+        })
+    })
+}
+
+/// Uploads `local_path` over SFTP to `remote_path`, emitting `upload-progress`.
+/// An existing remote file keeps its permission bits. Fails before touching the
+/// remote side if the local file can't be read or the remote directory is
+/// missing. Returns the bytes written.
+pub fn upload(
+    app_handle: &AppHandle,
+    target: &SshTarget,
+    local_path: &str,
+    remote_path: &str,
+) -> Result<u64, String> {
+    let mut local = File::open(local_path)
+        .map_err(|e| io_error_message("opening", local_path, &e))?;
+    let total = local.metadata().ok().map(|m| m.len());
+
+    let sess = ssh_connect::connect(target, Duration::from_secs(30))?;
+    let sftp = sess
+        .sftp()
+        .map_err(|e| format!("Failed to start SFTP: {}", e))?;
+
+    let remote = Path::new(remote_path);
+    if let Some(dir) = remote.parent().filter(|d| !d.as_os_str().is_empty()) {
+        match sftp.stat(dir) {
+            Ok(stat) if stat.is_dir() => {}
+            Ok(_) => return Err(format!("Remote path {} is not a directory", dir.display())),
+            Err(_) => return Err(format!("Remote directory {} does not exist", dir.display())),
+        }
+    }
+    let existing_mode = sftp.stat(remote).ok().and_then(|stat| stat.perm);
+
+    let mut file = sftp
+        .create(remote)
+        .map_err(|e| format!("Failed to create {}: {}", remote_path, e))?;
+    let written = copy_chunks(&mut local, local_path, &mut file, remote_path, |done| {
+        emit_progress(app_handle, "upload-progress", remote_path, local_path, done, total)
+    })?;
+    drop(file);
+
+    if let Some(mode) = existing_mode {
+        sftp.setstat(
+            remote,
+            ssh2::FileStat {
+                size: None,
+                uid: None,
+                gid: None,
+                perm: Some(mode),
+                atime: None,
+                mtime: None,
+            },
+        )
+        .map_err(|e| format!("Uploaded, but failed to restore mode of {}: {}", remote_path, e))?;
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Accepts at most 3 bytes per write call
+    struct ShortWriter(Vec<u8>);
+
+    impl Write for ShortWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let n = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..n]);
+            Ok(n)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::StorageFull))
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_copy_handles_short_writes() {
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        let mut dest = ShortWriter(Vec::new());
+        let mut last = 0;
+        let n = copy_chunks(&mut data.as_slice(), "remote", &mut dest, "local", |done| last = done).unwrap();
+        assert_eq!(n, data.len() as u64);
+        assert_eq!(last, n);
+        assert_eq!(dest.0, data);
+    }
+
+    #[test]
+    fn test_dir_listing_format() {
+        assert_eq!(format_permissions(0o040750), "drwxr-x---");
+        assert_eq!(format_permissions(0o100644), "-rw-r--r--");
+
+        let stat = |perm| ssh2::FileStat {
+            size: Some(10),
+            uid: None,
+            gid: None,
+            perm,
+            atime: None,
+            mtime: Some(1_700_000_000),
+        };
+        let mut listing = vec![
+            dir_entry("b.log".to_string(), &stat(Some(0o100644)), None),
+            dir_entry("secret".to_string(), &stat(None), None),
+            dir_entry("archive".to_string(), &stat(Some(0o040755)), None),
+            dir_entry("a.log".to_string(), &stat(Some(0o100600)), None),
+        ];
+        sort_listing(&mut listing);
+        let names: Vec<&str> = listing.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["archive", "a.log", "b.log", "secret"]);
+        assert!(listing[0].is_dir);
+        assert_eq!(listing[3].error.as_deref(), Some("Permission denied"));
+        assert_eq!(listing[3].permissions, None);
+    }
+
+    #[test]
+    fn test_stat_errors_are_distinct() {
+        let not_found = ssh2::Error::new(ssh2::ErrorCode::SFTP(LIBSSH2_FX_NO_SUCH_FILE), "no such file");
+        let denied = ssh2::Error::new(ssh2::ErrorCode::SFTP(LIBSSH2_FX_PERMISSION_DENIED), "permission denied");
+        assert_eq!(stat_error("/logs/a.log", &not_found), "Not found: /logs/a.log");
+        assert_eq!(stat_error("/logs/a.log", &denied), "Permission denied: /logs/a.log");
+    }
+
+    #[test]
+    fn test_archive_name_keeps_directories() {
+        assert_eq!(archive_name("/var/log/app/app.log"), "var/log/app/app.log");
+        assert_eq!(archive_name("/var/log/../../etc/./passwd"), "var/log/etc/passwd");
+    }
+
+    #[test]
+    fn test_disk_full_is_reported() {
+        let err = copy_chunks(&mut &b"data"[..], "remote", &mut FullDisk, "/tmp/out.log", |_| {}).unwrap_err();
+        assert_eq!(err, "Disk full while writing /tmp/out.log");
+    }
+}
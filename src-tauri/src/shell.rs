@@ -0,0 +1,45 @@
+/// Quotes a value for safe interpolation into a POSIX shell command.
+///
+/// The value is wrapped in single quotes, inside which the shell performs no
+/// expansion at all (`$`, backticks, `\` and globs are literal). The only
+/// character that cannot appear inside single quotes is `'` itself, so each
+/// one is emitted as `'\''`: close the quote, add an escaped quote, reopen.
+pub fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_value() {
+        assert_eq!(shell_quote("TX123"), "'TX123'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn test_embedded_single_quote() {
+        assert_eq!(shell_quote("it's"), "'it'\\''s'");
+    }
+
+    #[test]
+    fn test_metacharacters_stay_inside_quotes() {
+        assert_eq!(shell_quote("$(rm -rf /)"), "'$(rm -rf /)'");
+        assert_eq!(shell_quote("`id`"), "'`id`'");
+        assert_eq!(shell_quote("a\"b"), "'a\"b'");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_round_trip() {
+        for value in ["it's", "$HOME", "`id`", "a\"b", "x'; echo pwned; '", "$(id)"] {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("printf %s {}", shell_quote(value)))
+                .output()
+                .expect("sh should run");
+            assert_eq!(String::from_utf8_lossy(&output.stdout), value);
+        }
+    }
+}
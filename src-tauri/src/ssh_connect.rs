@@ -0,0 +1,579 @@
+use serde::{Deserialize, Serialize};
+use ssh2::{Channel, Session};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+use zeroize::Zeroize;
+
+use crate::known_hosts::KNOWN_HOSTS;
+use crate::ssh_limit::CONNECT_THROTTLE;
+
+/// TCP connect timeout used when the server doesn't configure one.
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Shortest secret `SshTarget::redact` masks.
+pub const MIN_REDACTED_SECRET_LEN: usize = 6;
+
+/// How a session proves its identity to the server.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMethod {
+    Password,
+    PublicKey,
+    /// Use whatever identities the local ssh-agent holds.
+    Agent,
+}
+
+/// Everything needed to open an authenticated SSH session to one host.
+#[derive(Deserialize, Clone, Debug, Default)]
+pub struct SshTarget {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub auth_method: Option<AuthMethod>,
+    #[serde(default)]
+    pub jump_host: Option<JumpConfig>,
+    /// Overrides `DEFAULT_CONNECT_TIMEOUT`.
+    #[serde(default)]
+    pub connect_timeout_secs: Option<u64>,
+    /// Overrides the default socket read timeout of the calling path.
+    #[serde(default)]
+    pub read_timeout_secs: Option<u64>,
+    /// Asks for zlib compression of the SSH stream (see `try_start_session`).
+    #[serde(default)]
+    pub use_compression: bool,
+}
+
+/// A bastion the target is reached through (ProxyJump). Only a single hop is
+/// supported, so a jump host has no jump host of its own.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct JumpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub auth_method: Option<AuthMethod>,
+}
+
+impl Drop for JumpConfig {
+    fn drop(&mut self) {
+        self.password.zeroize();
+        self.passphrase.zeroize();
+    }
+}
+
+impl JumpConfig {
+    pub fn target(&self) -> SshTarget {
+        SshTarget {
+            host: self.host.clone(),
+            port: self.port,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            private_key_path: self.private_key_path.clone(),
+            passphrase: self.passphrase.clone(),
+            auth_method: self.auth_method,
+            jump_host: None,
+            connect_timeout_secs: None,
+            read_timeout_secs: None,
+            // The bastion only relays the already-compressed inner session
+            use_compression: false,
+        }
+    }
+
+    /// Copy with `f` applied to the password and passphrase (to encrypt or decrypt them).
+    pub fn with_secrets(&self, f: impl Fn(&str) -> Result<String, String>) -> Result<JumpConfig, String> {
+        Ok(JumpConfig {
+            host: self.host.clone(),
+            port: self.port,
+            username: self.username.clone(),
+            password: f(&self.password)?,
+            private_key_path: self.private_key_path.clone(),
+            passphrase: self.passphrase.as_deref().map(f).transpose()?,
+            auth_method: self.auth_method,
+        })
+    }
+}
+
+impl Drop for SshTarget {
+    fn drop(&mut self) {
+        self.password.zeroize();
+        self.passphrase.zeroize();
+    }
+}
+
+impl SshTarget {
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// The configured connect timeout, if any. A value of 0 counts as unset.
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        self.connect_timeout_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// The configured read timeout, or `default` (the calling path's usual value).
+    pub fn read_timeout(&self, default: Duration) -> Duration {
+        self.read_timeout_secs
+            .filter(|secs| *secs > 0)
+            .map(Duration::from_secs)
+            .unwrap_or(default)
+    }
+
+    /// The private key path, treating an empty string the same as "not set".
+    pub fn key_path(&self) -> Option<&str> {
+        self.private_key_path
+            .as_deref()
+            .filter(|p| !p.trim().is_empty())
+    }
+
+    /// The effective auth method. When none was chosen explicitly, a configured
+    /// private key wins over the password, matching the behaviour before the
+    /// method could be selected.
+    pub fn resolved_auth_method(&self) -> AuthMethod {
+        match self.auth_method {
+            Some(method) => method,
+            None if self.key_path().is_some() => AuthMethod::PublicKey,
+            None => AuthMethod::Password,
+        }
+    }
+
+    /// `message` with this target's password and passphrase (and those of its
+    /// jump host) replaced by `***`, in case a libssh2 error echoes them back.
+    /// Secrets shorter than `MIN_REDACTED_SECRET_LEN` are left alone: masking
+    /// every "22" or "root" would garble the message and reveal the secret anyway.
+    pub fn redact(&self, message: &str) -> String {
+        let jump = self.jump_host.as_ref();
+        let secrets = [
+            Some(self.password.as_str()),
+            self.passphrase.as_deref(),
+            jump.map(|j| j.password.as_str()),
+            jump.and_then(|j| j.passphrase.as_deref()),
+        ];
+        secrets
+            .into_iter()
+            .flatten()
+            .filter(|secret| secret.chars().count() >= MIN_REDACTED_SECRET_LEN)
+            .fold(message.to_string(), |message, secret| message.replace(secret, "***"))
+    }
+}
+
+/// Authenticates an already handshaken session using the target's auth method.
+/// Errors never contain the target's secrets.
+pub fn authenticate(sess: &Session, target: &SshTarget) -> Result<(), String> {
+    try_authenticate(sess, target).map_err(|e| target.redact(&e))
+}
+
+fn try_authenticate(sess: &Session, target: &SshTarget) -> Result<(), String> {
+    match target.resolved_auth_method() {
+        AuthMethod::Agent => {
+            sess.userauth_agent(&target.username)
+                .map_err(|_| format!("no agent identity accepted for {}", target.username))?;
+        }
+        AuthMethod::PublicKey => {
+            let key_path = target
+                .key_path()
+                .ok_or("Public key authentication selected but no private key path is set")?;
+            let passphrase = target.passphrase.as_deref().filter(|p| !p.is_empty());
+            sess.userauth_pubkey_file(&target.username, None, Path::new(key_path), passphrase)
+                .map_err(|e| format!("Public key authentication failed: {}", e))?;
+        }
+        AuthMethod::Password => {
+            sess.userauth_password(&target.username, &target.password)
+                .map_err(|e| format!("Authentication failed: {}", e))?;
+        }
+    }
+
+    if !sess.authenticated() {
+        return Err("Authentication failed".to_string());
+    }
+    Ok(())
+}
+
+/// How `connect_with_retry` retries transient failures.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Total connection attempts, including the first.
+    pub attempts: u32,
+    /// Wait before the first retry; doubled after each further failure.
+    pub backoff: Duration,
+}
+
+// A failed connect, and whether trying again could help: network and handshake
+// trouble may pass, a rejected host key or bad credentials will not
+struct ConnectError {
+    message: String,
+    retryable: bool,
+}
+
+impl ConnectError {
+    fn transient(message: String) -> Self {
+        Self { message, retryable: true }
+    }
+
+    fn fatal(message: String) -> Self {
+        Self { message, retryable: false }
+    }
+}
+
+/// Opens the stream an SSH session to `target` runs over: a direct TCP
+/// connection, or a loopback socket relayed through the jump host.
+pub fn open_stream(target: &SshTarget) -> Result<TcpStream, String> {
+    try_open_stream(target).map_err(|e| e.message)
+}
+
+// Every new connection passes the per-host throttle here, a tunneled one for
+// its target and then, through the bastion login, for the jump host
+fn try_open_stream(target: &SshTarget) -> Result<TcpStream, ConnectError> {
+    CONNECT_THROTTLE.reserve(&target.host).map_err(ConnectError::fatal)?;
+    match &target.jump_host {
+        Some(jump) => open_jump_stream(&jump.target(), &target.host, target.port),
+        None => connect_tcp(target).map_err(ConnectError::transient),
+    }
+}
+
+// A timed-out connect gets its own wording so the frontend can tell an offline
+// host apart from a refused connection or an authentication failure.
+fn tcp_error(host: &str, timeout: Duration, e: &io::Error) -> String {
+    match e.kind() {
+        ErrorKind::TimedOut | ErrorKind::WouldBlock => {
+            format!("Host {} unreachable within {}s", host, timeout.as_secs())
+        }
+        _ => format!("TCP connection to {} failed: {}", host, e),
+    }
+}
+
+// A direct TCP connection to the target, ignoring any jump host. Each resolved
+// address is tried in turn with a bounded timeout instead of the OS default
+// (often over a minute). Not throttled; callers go through `try_open_stream`.
+fn connect_tcp(target: &SshTarget) -> Result<TcpStream, String> {
+    let timeout = target.connect_timeout().unwrap_or(DEFAULT_CONNECT_TIMEOUT);
+    let addrs = (target.host.as_str(), target.port)
+        .to_socket_addrs()
+        .map_err(|e| format!("Failed to resolve {}: {}", target.host, e))?;
+
+    let mut last_error = format!("No addresses found for {}", target.host);
+    for addr in addrs {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = tcp_error(&target.host, timeout, &e),
+        }
+    }
+    Err(last_error)
+}
+
+/// libssh2 sessions need a real socket, so a `direct-tcpip` channel on the
+/// bastion is bridged to a loopback TCP pair by a relay thread.
+fn open_jump_stream(jump: &SshTarget, host: &str, port: u16) -> Result<TcpStream, ConnectError> {
+    let bastion = try_connect(jump, Duration::from_secs(30)).map_err(|e| ConnectError {
+        message: format!("Jump host {}: {}", jump.host, e.message),
+        retryable: e.retryable,
+    })?;
+    tunnel(bastion, &jump.host, host, port).map_err(ConnectError::transient)
+}
+
+/// Opens a `direct-tcpip` channel from the authenticated `bastion` (named
+/// `jump_host` in errors) to `host:port`, bridged to a local socket. Counts
+/// as a connection to `host` for the per-host throttle.
+pub fn open_tunnel(bastion: Session, jump_host: &str, host: &str, port: u16) -> Result<TcpStream, String> {
+    CONNECT_THROTTLE.reserve(host)?;
+    tunnel(bastion, jump_host, host, port)
+}
+
+fn tunnel(bastion: Session, jump_host: &str, host: &str, port: u16) -> Result<TcpStream, String> {
+    let channel = bastion
+        .channel_direct_tcpip(host, port, None)
+        .map_err(|e| format!("Jump host {} could not reach {}:{}: {}", jump_host, host, port, e))?;
+    bridge_channel(bastion, channel)
+}
+
+// Hands out one end of a loopback pair and relays the other through `channel`
+fn bridge_channel(bastion: Session, channel: Channel) -> Result<TcpStream, String> {
+    let listener = TcpListener::bind("127.0.0.1:0")
+        .map_err(|e| format!("Failed to open relay socket: {}", e))?;
+    let local_addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let client = TcpStream::connect(local_addr)
+        .map_err(|e| format!("Failed to connect relay socket: {}", e))?;
+    let client_addr = client.local_addr().map_err(|e| e.to_string())?;
+    // Only accept our own connection, not another local process racing for the port
+    let relay = loop {
+        let (stream, peer) = listener
+            .accept()
+            .map_err(|e| format!("Failed to accept relay socket: {}", e))?;
+        if peer == client_addr {
+            break stream;
+        }
+    };
+
+    thread::spawn(move || relay_channel(bastion, channel, relay, &AtomicBool::new(false)));
+    Ok(client)
+}
+
+/// Copies bytes between `socket` and `channel` both ways until either side
+/// closes or `stop` is set, then closes the channel and disconnects `bastion`.
+pub fn relay_channel(bastion: Session, mut channel: Channel, mut socket: TcpStream, stop: &AtomicBool) {
+    bastion.set_blocking(false);
+    if socket.set_nonblocking(true).is_err() {
+        return;
+    }
+    let mut buffer = [0u8; 16 * 1024];
+    let mut to_channel: Vec<u8> = Vec::new();
+    let mut to_socket: Vec<u8> = Vec::new();
+
+    'relay: loop {
+        if stop.load(Ordering::SeqCst) {
+            break 'relay;
+        }
+        let mut idle = true;
+
+        if to_channel.is_empty() {
+            match socket.read(&mut buffer) {
+                Ok(0) => break 'relay,
+                Ok(n) => {
+                    to_channel.extend_from_slice(&buffer[..n]);
+                    idle = false;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => break 'relay,
+            }
+        }
+        if !to_channel.is_empty() {
+            match channel.write(&to_channel) {
+                Ok(n) => {
+                    to_channel.drain(..n);
+                    idle = false;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => break 'relay,
+            }
+        }
+
+        if to_socket.is_empty() {
+            match channel.read(&mut buffer) {
+                Ok(0) if channel.eof() => break 'relay,
+                Ok(0) => {}
+                Ok(n) => {
+                    to_socket.extend_from_slice(&buffer[..n]);
+                    idle = false;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => break 'relay,
+            }
+        }
+        if !to_socket.is_empty() {
+            match socket.write(&to_socket) {
+                Ok(n) => {
+                    to_socket.drain(..n);
+                    idle = false;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {}
+                Err(_) => break 'relay,
+            }
+        }
+
+        if idle {
+            thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    bastion.set_blocking(true);
+    let _ = channel.close();
+    let _ = bastion.disconnect(None, "Jump relay closed", None);
+}
+
+/// Connects TCP (directly or via the jump host), performs the SSH handshake
+/// and authenticates.
+/// `default_read_timeout` applies unless the target sets its own.
+#[tracing::instrument(skip_all, fields(host = %target.host, port = target.port, username = %target.username), err)]
+pub fn connect(target: &SshTarget, default_read_timeout: Duration) -> Result<Session, String> {
+    try_connect(target, default_read_timeout).map_err(|e| e.message)
+}
+
+/// Like `connect`, but retries network and handshake failures according to
+/// `policy`, calling `on_retry(attempt, error, wait)` before each new attempt.
+/// Host key, authentication and rate limit failures are returned right away.
+/// The wait between attempts is at least the per-host throttle interval.
+#[tracing::instrument(skip_all, fields(host = %target.host, port = target.port, username = %target.username), err)]
+pub fn connect_with_retry(
+    target: &SshTarget,
+    default_read_timeout: Duration,
+    policy: RetryPolicy,
+    mut on_retry: impl FnMut(u32, &str, Duration),
+) -> Result<Session, String> {
+    let mut backoff = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        match try_connect(target, default_read_timeout) {
+            Ok(sess) => return Ok(sess),
+            Err(e) if e.retryable && attempt < policy.attempts => {
+                let wait = backoff.max(CONNECT_THROTTLE.interval());
+                tracing::warn!(attempt, error = %e.message, "connect failed, retrying");
+                on_retry(attempt, &e.message, wait);
+                thread::sleep(wait);
+                backoff *= 2;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.message),
+        }
+    }
+}
+
+/// Lists the authentication methods the server offers `target.username`
+/// (e.g. `publickey,keyboard-interactive`) without trying to log in. The host
+/// key isn't checked: nothing secret is sent.
+pub fn auth_methods(target: &SshTarget) -> Result<String, String> {
+    let sess = try_handshake(target, DEFAULT_CONNECT_TIMEOUT).map_err(|e| e.message)?;
+    let methods = sess.auth_methods(&target.username).map(str::to_string);
+    // Servers that accept the "none" method log the user straight in
+    if sess.authenticated() {
+        return Ok("none".to_string());
+    }
+    let _ = sess.disconnect(None, "Auth method probe", None);
+    methods.map_err(|e| format!("Failed to query auth methods: {}", e))
+}
+
+// TCP connect and SSH handshake, before any host key check or authentication
+fn try_handshake(target: &SshTarget, default_read_timeout: Duration) -> Result<Session, ConnectError> {
+    let tcp = try_open_stream(target)?;
+    try_start_session(tcp, target, default_read_timeout)
+}
+
+/// Performs the SSH handshake over a stream from `open_stream`. The host key
+/// still has to be verified before authenticating.
+pub fn start_session(tcp: TcpStream, target: &SshTarget, default_read_timeout: Duration) -> Result<Session, String> {
+    try_start_session(tcp, target, default_read_timeout).map_err(|e| e.message)
+}
+
+fn try_start_session(
+    tcp: TcpStream,
+    target: &SshTarget,
+    default_read_timeout: Duration,
+) -> Result<Session, ConnectError> {
+    let read_timeout = target.read_timeout(default_read_timeout);
+
+    tcp.set_read_timeout(Some(read_timeout))
+        .map_err(|e| ConnectError::transient(format!("Failed to set timeout: {}", e)))?;
+
+    let mut sess = Session::new()
+        .map_err(|e| ConnectError::fatal(format!("Failed to create SSH session: {}", e)))?;
+
+    sess.set_tcp_stream(tcp);
+    // Must be set before the handshake, which negotiates it. Plain-text logs
+    // shrink several times over on slow links; a .gz/.bz2 file fetched as-is
+    // doesn't shrink at all, so paths moving raw compressed bytes leave it off.
+    sess.set_compress(target.use_compression);
+    sess.handshake()
+        .map_err(|e| ConnectError::transient(format!("SSH handshake failed: {}", e)))?;
+    Ok(sess)
+}
+
+fn try_connect(target: &SshTarget, default_read_timeout: Duration) -> Result<Session, ConnectError> {
+    let connect = || -> Result<Session, ConnectError> {
+        let sess = try_handshake(target, default_read_timeout)?;
+        KNOWN_HOSTS
+            .verify(&sess, &target.host, target.port)
+            .map_err(ConnectError::fatal)?;
+
+        authenticate(&sess, target).map_err(ConnectError::fatal)?;
+        Ok(sess)
+    };
+    connect().map_err(|e| ConnectError {
+        message: target.redact(&e.message),
+        retryable: e.retryable,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeout_error_is_distinct() {
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            tcp_error("10.0.0.9", timeout, &io::Error::from(ErrorKind::TimedOut)),
+            "Host 10.0.0.9 unreachable within 5s"
+        );
+        assert!(tcp_error("10.0.0.9", timeout, &io::Error::from(ErrorKind::ConnectionRefused))
+            .starts_with("TCP connection to 10.0.0.9 failed"));
+    }
+
+    #[test]
+    fn test_refused_connect_is_retried() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+
+        let mut target = SshTarget::default();
+        target.host = "127.0.0.1".to_string();
+        target.port = port;
+        let policy = RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(1),
+        };
+        let mut waits = Vec::new();
+        let result = connect_with_retry(&target, Duration::from_secs(1), policy, |attempt, _, wait| {
+            waits.push((attempt, wait))
+        });
+        assert!(result.is_err());
+        assert_eq!(waits, [(1, Duration::from_millis(1)), (2, Duration::from_millis(2))]);
+    }
+
+    #[test]
+    fn test_errors_never_contain_secrets() {
+        let mut target = SshTarget::default();
+        target.host = "127.0.0.1".to_string();
+        target.password = "hunter2-Pa55".to_string();
+        target.passphrase = Some("key-phrase".to_string());
+        let mut jump = JumpConfig::default();
+        jump.password = "jump-pw".to_string();
+        target.jump_host = Some(jump);
+        assert_eq!(
+            target.redact("Authentication failed: bad pass hunter2-Pa55 / key-phrase via jump-pw"),
+            "Authentication failed: bad pass *** / *** via ***"
+        );
+
+        // Nothing to redact with empty secrets
+        let mut bare = SshTarget::default();
+        bare.password = String::new();
+        assert_eq!(bare.redact("Authentication failed"), "Authentication failed");
+
+        // Too short to mask without mangling ports and user names
+        bare.password = "root".to_string();
+        assert_eq!(bare.redact("root@10.0.0.1:22 refused"), "root@10.0.0.1:22 refused");
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        target.port = listener.local_addr().unwrap().port();
+        target.jump_host = None;
+        drop(listener);
+        let error = connect(&target, Duration::from_secs(1)).err().unwrap();
+        assert!(!error.contains("hunter2-Pa55") && !error.contains("key-phrase"), "{}", error);
+    }
+
+    #[test]
+    fn test_timeout_overrides() {
+        let mut target = SshTarget::default();
+        assert_eq!(target.connect_timeout(), None);
+        assert_eq!(target.read_timeout(Duration::from_secs(30)), Duration::from_secs(30));
+
+        target.connect_timeout_secs = Some(3);
+        target.read_timeout_secs = Some(0);
+        assert_eq!(target.connect_timeout(), Some(Duration::from_secs(3)));
+        assert_eq!(target.read_timeout(Duration::from_secs(30)), Duration::from_secs(30));
+    }
+}
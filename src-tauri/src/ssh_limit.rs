@@ -0,0 +1,183 @@
+use dashmap::DashMap;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Blocking SSH tasks allowed at once unless the user changes it.
+pub const DEFAULT_SSH_CONCURRENCY: usize = 32;
+/// Minimum time between connection attempts to one host. Off by default, so
+/// searching many servers at once isn't slowed down.
+pub const DEFAULT_CONNECT_INTERVAL: Duration = Duration::ZERO;
+
+lazy_static! {
+    pub static ref SSH_LIMITER: SshLimiter = SshLimiter::new(DEFAULT_SSH_CONCURRENCY);
+    pub static ref CONNECT_THROTTLE: ConnectThrottle = ConnectThrottle::new(DEFAULT_CONNECT_INTERVAL);
+}
+
+#[derive(Clone, Serialize, Debug, PartialEq)]
+pub struct SshLimitStatus {
+    pub limit: usize,
+    pub in_use: usize,
+}
+
+/// Process-wide cap on SSH work running at the same time, so searching many
+/// servers at once can't exhaust file descriptors or trip sshd rate limits.
+pub struct SshLimiter {
+    semaphore: Arc<Semaphore>,
+    limit: AtomicUsize,
+    // Permits still to be removed after lowering the limit while they were in use
+    pending_removal: Arc<AtomicUsize>,
+}
+
+impl SshLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            limit: AtomicUsize::new(limit),
+            pending_removal: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Waits for a free slot; hold the permit until the SSH work is done.
+    pub async fn acquire(&self) -> Result<OwnedSemaphorePermit, String> {
+        self.semaphore
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| format!("Semaphore closed: {}", e))
+    }
+
+    pub fn status(&self) -> SshLimitStatus {
+        let limit = self.limit.load(Ordering::SeqCst);
+        let total = limit + self.pending_removal.load(Ordering::SeqCst);
+        SshLimitStatus {
+            limit,
+            in_use: total.saturating_sub(self.semaphore.available_permits()),
+        }
+    }
+
+    /// Changes the limit. Raising it takes effect at once; lowering it removes
+    /// free permits now and the rest as running tasks finish, which needs to
+    /// be called from within the Tokio runtime.
+    pub fn set_limit(&self, limit: usize) -> Result<(), String> {
+        if limit == 0 {
+            return Err("The SSH concurrency limit must be at least 1".to_string());
+        }
+        let previous = self.limit.swap(limit, Ordering::SeqCst);
+        if limit > previous {
+            self.semaphore.add_permits(limit - previous);
+            return Ok(());
+        }
+        let excess = previous - limit;
+        let remaining = excess - self.semaphore.forget_permits(excess);
+        if remaining > 0 {
+            self.pending_removal.fetch_add(remaining, Ordering::SeqCst);
+            let semaphore = self.semaphore.clone();
+            let pending_removal = self.pending_removal.clone();
+            tokio::spawn(async move {
+                if let Ok(permits) = semaphore.acquire_many_owned(remaining as u32).await {
+                    permits.forget();
+                }
+                pending_removal.fetch_sub(remaining, Ordering::SeqCst);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Spaces out connection attempts per host, so repeated tests or searches
+/// don't look like a brute-force attack to fail2ban and get the user banned.
+pub struct ConnectThrottle {
+    interval_ms: AtomicU64,
+    // Earliest time the next attempt to each host may start
+    next_slot: DashMap<String, Instant>,
+}
+
+impl ConnectThrottle {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval_ms: AtomicU64::new(interval.as_millis() as u64),
+            next_slot: DashMap::new(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms.load(Ordering::SeqCst))
+    }
+
+    /// Changes the minimum interval; zero turns the throttle off.
+    pub fn set_interval(&self, interval: Duration) {
+        self.interval_ms.store(interval.as_millis() as u64, Ordering::SeqCst);
+    }
+
+    /// Records an attempt to `host`, or fails with the time left until one is
+    /// allowed. Failing rather than waiting keeps a throttled host from holding
+    /// an SSH limiter slot that other servers could use.
+    pub fn reserve(&self, host: &str) -> Result<(), String> {
+        let interval = self.interval();
+        if interval.is_zero() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut next = self.next_slot.entry(host.to_ascii_lowercase()).or_insert(now);
+        if *next > now {
+            let wait = *next - now;
+            return Err(format!(
+                "Rate limited: retry connecting to {} in {}s",
+                host,
+                wait.as_secs_f64().ceil() as u64
+            ));
+        }
+        *next = now + interval;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize_limit() {
+        let limiter = SshLimiter::new(2);
+        let permit = limiter.semaphore.clone().try_acquire_owned().unwrap();
+        assert_eq!(limiter.status(), SshLimitStatus { limit: 2, in_use: 1 });
+
+        limiter.set_limit(4).unwrap();
+        assert_eq!(limiter.status(), SshLimitStatus { limit: 4, in_use: 1 });
+        assert_eq!(limiter.semaphore.available_permits(), 3);
+
+        // Only free permits can be removed right away
+        limiter.set_limit(1).unwrap();
+        assert_eq!(limiter.status(), SshLimitStatus { limit: 1, in_use: 1 });
+        assert_eq!(limiter.semaphore.available_permits(), 0);
+        drop(permit);
+        assert_eq!(limiter.status(), SshLimitStatus { limit: 1, in_use: 0 });
+
+        assert!(limiter.set_limit(0).is_err());
+    }
+
+    #[test]
+    fn test_connect_throttle() {
+        let throttle = ConnectThrottle::new(Duration::ZERO);
+        assert!(throttle.reserve("a").is_ok());
+        assert!(throttle.reserve("a").is_ok());
+
+        throttle.set_interval(Duration::from_secs(5));
+        assert!(throttle.reserve("a").is_ok());
+        assert_eq!(
+            throttle.reserve("A").unwrap_err(),
+            "Rate limited: retry connecting to A in 5s"
+        );
+        // Other hosts have their own clock
+        assert!(throttle.reserve("b").is_ok());
+
+        throttle.set_interval(Duration::from_millis(1));
+        assert!(throttle.reserve("c").is_ok());
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(throttle.reserve("c").is_ok());
+    }
+}
@@ -1,53 +1,446 @@
 use dashmap::DashMap;
 use lazy_static::lazy_static;
+use polling::{Event, Events, Poller};
 use serde::Serialize;
-use ssh2::{Channel, Session};
+use ssh2::{BlockDirections, Channel, Session};
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::net::TcpStream;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use tauri::{AppHandle, Emitter};
 use uuid::Uuid;
 
+use crate::known_hosts::KNOWN_HOSTS;
+use crate::recording::CastRecorder;
+use crate::ssh_connect::{self, SshTarget};
+
+/// Seconds between keepalive packets when the caller doesn't choose; 0 disables them.
+pub const DEFAULT_KEEPALIVE_INTERVAL: u32 = 30;
+
+/// Bytes of recent output kept per session when the caller doesn't choose.
+pub const DEFAULT_SCROLLBACK_BYTES: usize = 256 * 1024;
+
+/// Terminal settings chosen when a PTY session starts.
+pub struct PtyOptions {
+    pub cols: u32,
+    pub rows: u32,
+    /// Seconds between keepalive packets; 0 disables them.
+    pub keepalive_interval: u32,
+    /// Close the session after this long without input or output; zero disables it.
+    pub idle_timeout: Duration,
+    /// Write an asciinema `.cast` recording of the session to this path.
+    pub record_path: Option<String>,
+    /// Also record keystrokes as "i" events.
+    pub record_input: bool,
+    /// Reconnect with backoff when the connection drops instead of ending the session.
+    pub auto_reconnect: bool,
+    /// Environment variables requested for the shell; the server may refuse them.
+    pub env: HashMap<String, String>,
+    /// Typed into the shell right after it starts.
+    pub initial_command: Option<String>,
+    /// Bytes of recent output kept for `scrollback`; 0 keeps none.
+    pub scrollback_bytes: usize,
+}
+
+// How each shell of a session is set up: on start and again after a reconnect
+struct ShellSetup {
+    keepalive_interval: u32,
+    env: HashMap<String, String>,
+    initial_command: Option<String>,
+}
+
+// Reconnect attempts after a dropped connection, and the wait before the
+// first one; each further wait doubles, up to `RECONNECT_MAX_DELAY`
+const RECONNECT_ATTEMPTS: u32 = 5;
+const RECONNECT_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const RECONNECT_MAX_DELAY: Duration = Duration::from_secs(30);
+
+// Longest the reader blocks waiting for the socket, which bounds how late
+// keepalives and the idle timeout are checked. Closing a session wakes it at once.
+const POLL_TIMEOUT: Duration = Duration::from_millis(500);
+const SOCKET_KEY: usize = 0;
+
+// libssh2's LIBSSH2_ERROR_EAGAIN, returned by non-blocking calls that would block
+const LIBSSH2_ERROR_EAGAIN: i32 = -37;
+
 #[derive(Clone, Serialize)]
 pub struct SshOutput {
     pub session_id: String,
     pub data: String,
 }
 
+// Reasons carried by `ssh-exit`
+const EXIT_EOF: &str = "eof";
+const EXIT_IO_ERROR: &str = "io_error";
+const EXIT_CLOSED: &str = "closed";
+const EXIT_IDLE_TIMEOUT: &str = "idle_timeout";
+
 #[derive(Clone, Serialize)]
 pub struct SshExit {
     pub session_id: String,
+    pub reason: String, // "eof", "io_error", "closed" or "idle_timeout"
+}
+
+/// Emitted as `ssh-reconnecting` before each reconnect attempt.
+#[derive(Clone, Serialize)]
+pub struct SshReconnecting {
+    pub session_id: String,
+    pub attempt: u32,
+    pub max_attempts: u32,
+    pub delay_ms: u64,
+}
+
+/// Emitted as `ssh-reconnected` once a dropped session is back.
+#[derive(Clone, Serialize)]
+pub struct SshReconnected {
+    pub session_id: String,
+    pub attempt: u32,
+}
+
+// Wait before reconnect attempt `attempt` (1-based)
+fn reconnect_delay(attempt: u32) -> Duration {
+    RECONNECT_INITIAL_DELAY
+        .saturating_mul(1 << (attempt - 1).min(16))
+        .min(RECONNECT_MAX_DELAY)
+}
+
+// Sleeps for `duration` unless the session is closed meanwhile; false if it was
+fn sleep_unless_shutdown(shutdown: &AtomicBool, duration: Duration) -> bool {
+    let deadline = Instant::now() + duration;
+    while Instant::now() < deadline {
+        if shutdown.load(Ordering::SeqCst) {
+            return false;
+        }
+        thread::sleep(Duration::from_millis(100).min(deadline - Instant::now()));
+    }
+    !shutdown.load(Ordering::SeqCst)
+}
+
+// Connects, authenticates and starts a shell on a `cols`x`rows` PTY. The
+// session is left non-blocking for the reader thread. Errors reach the
+// terminal and the log, so they never contain the target's secrets.
+fn open_pty(
+    target: &SshTarget,
+    cols: u32,
+    rows: u32,
+    setup: &ShellSetup,
+) -> Result<(Session, Channel, TcpStream), String> {
+    try_open_pty(target, cols, rows, setup).map_err(|e| target.redact(&e))
+}
+
+fn try_open_pty(
+    target: &SshTarget,
+    cols: u32,
+    rows: u32,
+    setup: &ShellSetup,
+) -> Result<(Session, Channel, TcpStream), String> {
+    // Connect TCP (through the jump host if one is configured)
+    let tcp = ssh_connect::open_stream(target)?;
+
+    tcp.set_nonblocking(false)
+        .map_err(|e| format!("Failed to set blocking: {}", e))?;
+
+    // Create SSH session
+    let mut sess = Session::new()
+        .map_err(|e| format!("Failed to create session: {}", e))?;
+
+    sess.set_tcp_stream(tcp.try_clone().map_err(|e| e.to_string())?);
+    sess.handshake()
+        .map_err(|e| format!("SSH handshake failed: {}", e))?;
+    KNOWN_HOSTS.verify(&sess, &target.host, target.port)?;
+
+    // Authenticate
+    ssh_connect::authenticate(&sess, target)?;
+
+    // Open channel and request PTY
+    let mut channel = sess
+        .channel_session()
+        .map_err(|e| format!("Failed to open channel: {}", e))?;
+
+    channel
+        .request_pty("xterm-256color", None, Some((cols, rows, 0, 0)))
+        .map_err(|e| format!("Failed to request PTY: {}", e))?;
+
+    // Servers only accept the variables their `AcceptEnv` allows; a refusal
+    // leaves the shell usable, so it's only logged
+    for (name, value) in &setup.env {
+        if let Err(e) = channel.setenv(name, value) {
+            tracing::warn!(host = %target.host, name = %name, error = %e, "server refused environment variable");
+        }
+    }
+
+    channel
+        .shell()
+        .map_err(|e| format!("Failed to start shell: {}", e))?;
+
+    if let Some(command) = &setup.initial_command {
+        channel
+            .write_all(format!("{}\n", command).as_bytes())
+            .map_err(|e| format!("Failed to send initial command: {}", e))?;
+    }
+
+    // Keep idle connections alive through firewalls that drop quiet TCP
+    if setup.keepalive_interval > 0 {
+        sess.set_keepalive(true, setup.keepalive_interval);
+    }
+
+    // Set channel to non-blocking for reading
+    sess.set_blocking(false);
+    Ok((sess, channel, tcp))
+}
+
+/// Turns a byte stream into text without mangling characters that arrive split
+/// across two reads: an incomplete trailing sequence is held back until the
+/// rest of it shows up. Genuinely invalid bytes still become U+FFFD.
+#[derive(Default)]
+pub struct Utf8StreamDecoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8StreamDecoder {
+    pub fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut text = String::new();
+        let mut rest: &[u8] = &self.pending;
+
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    text.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    text.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            text.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        // Incomplete sequence at the end: wait for more bytes
+                        None => {
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        self.pending = rest.to_vec();
+        text
+    }
+}
+
+/// The most recent output of a session, at most `cap` bytes, so a reloaded
+/// frontend can repaint its terminal.
+pub struct Scrollback {
+    text: String,
+    cap: usize,
+}
+
+impl Scrollback {
+    pub fn new(cap: usize) -> Self {
+        Self {
+            text: String::new(),
+            cap,
+        }
+    }
+
+    pub fn push(&mut self, data: &str) {
+        if self.cap == 0 {
+            return;
+        }
+        self.text.push_str(data);
+        // Trim in batches, a quarter of the cap at a time, rather than
+        // shifting the whole buffer on every read
+        if self.text.len() > self.cap + self.cap / 4 {
+            let cut = self.cut_point(self.text.len() - self.cap);
+            self.text.drain(..cut);
+        }
+    }
+
+    /// The last `cap` bytes of output, starting on a character boundary.
+    pub fn contents(&self) -> String {
+        let start = self.cut_point(self.text.len().saturating_sub(self.cap));
+        self.text[start..].to_string()
+    }
+
+    // The first character boundary at or after `at`
+    fn cut_point(&self, mut at: usize) -> usize {
+        while !self.text.is_char_boundary(at) {
+            at += 1;
+        }
+        at
+    }
+}
+
+/// What `list_pty_sessions` reports about a live terminal.
+#[derive(Clone, Serialize)]
+pub struct PtySessionInfo {
+    pub session_id: String,
+    pub host: String,
+    pub username: String,
+    pub started_at: u64, // Unix time in milliseconds
+    pub cols: u32,
+    pub rows: u32,
 }
 
 pub struct SshSession {
-    #[allow(dead_code)]
-    pub id: String,
+    pub info: PtySessionInfo,
     pub channel: Channel,
-    #[allow(dead_code)]
     pub session: Session,
-    #[allow(dead_code)]
     tcp: TcpStream,
     shutdown: Arc<AtomicBool>,
+    last_activity: Instant,
+    recorder: Option<CastRecorder>,
+    // What the reader thread blocks on; notified on close
+    poller: Arc<Poller>,
 }
 
 impl SshSession {
     pub fn write(&mut self, data: &[u8]) -> Result<usize, String> {
+        self.last_activity = Instant::now();
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.input(&String::from_utf8_lossy(data));
+        }
         self.channel.write(data).map_err(|e| e.to_string())
     }
 
     pub fn resize(&mut self, cols: u32, rows: u32) -> Result<(), String> {
         self.channel
             .request_pty_size(cols, rows, None, None)
-            .map_err(|e| e.to_string())
+            .map_err(|e| e.to_string())?;
+        self.info.cols = cols;
+        self.info.rows = rows;
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.resize(cols, rows);
+        }
+        Ok(())
     }
 
     pub fn close(&mut self) {
         self.shutdown.store(true, Ordering::SeqCst);
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.finish();
+        }
         let _ = self.channel.send_eof();
         let _ = self.channel.wait_close();
+        let _ = self.poller.notify();
+    }
+}
+
+// A clone of the session socket registered with the reader's poller, so the
+// reader can sleep until there is something to read instead of spinning
+struct PollRegistration {
+    poller: Arc<Poller>,
+    socket: TcpStream,
+}
+
+impl PollRegistration {
+    fn new(poller: Arc<Poller>, tcp: &TcpStream) -> std::io::Result<Self> {
+        let socket = tcp.try_clone()?;
+        // Safety: `drop` removes the socket from the poller before it is closed
+        unsafe { poller.add(&socket, Event::none(SOCKET_KEY))? };
+        Ok(PollRegistration { poller, socket })
+    }
+
+    // Blocks until the socket is ready in the direction libssh2 is waiting
+    // on, the poller is notified, or `timeout` passes
+    fn wait(&self, directions: BlockDirections, timeout: Duration) -> std::io::Result<()> {
+        let interest = match directions {
+            BlockDirections::Outbound | BlockDirections::Both => Event::all(SOCKET_KEY),
+            BlockDirections::Inbound | BlockDirections::None => Event::readable(SOCKET_KEY),
+        };
+        // Interest is one-shot, so it's re-armed before every wait
+        self.poller.modify(&self.socket, interest)?;
+        let mut events = Events::new();
+        self.poller.wait(&mut events, Some(timeout))?;
+        Ok(())
+    }
+}
+
+impl Drop for PollRegistration {
+    fn drop(&mut self) {
+        let _ = self.poller.delete(&self.socket);
+    }
+}
+
+// Re-opens a dropped session with the same PTY size, retrying with backoff,
+// and swaps the new connection in. False when every attempt failed or the
+// session was closed in the meantime.
+fn reconnect(
+    app_handle: &AppHandle,
+    session_id: &str,
+    session_arc: &std::sync::Mutex<SshSession>,
+    target: &SshTarget,
+    setup: &ShellSetup,
+    shutdown: &AtomicBool,
+) -> bool {
+    for attempt in 1..=RECONNECT_ATTEMPTS {
+        let delay = reconnect_delay(attempt);
+        let _ = app_handle.emit(
+            "ssh-reconnecting",
+            SshReconnecting {
+                session_id: session_id.to_string(),
+                attempt,
+                max_attempts: RECONNECT_ATTEMPTS,
+                delay_ms: delay.as_millis() as u64,
+            },
+        );
+        if !sleep_unless_shutdown(shutdown, delay) {
+            return false;
+        }
+        let (cols, rows) = match session_arc.lock() {
+            Ok(session) => (session.info.cols, session.info.rows),
+            Err(_) => return false,
+        };
+        match open_pty(target, cols, rows, setup) {
+            Ok((sess, channel, tcp)) => {
+                let mut session = match session_arc.lock() {
+                    Ok(session) => session,
+                    Err(_) => return false,
+                };
+                session.session = sess;
+                session.channel = channel;
+                session.tcp = tcp;
+                session.last_activity = Instant::now();
+                drop(session);
+                let _ = app_handle.emit(
+                    "ssh-reconnected",
+                    SshReconnected {
+                        session_id: session_id.to_string(),
+                        attempt,
+                    },
+                );
+                return true;
+            }
+            Err(e) => tracing::warn!(session_id, attempt, error = %e, "reconnect failed"),
+        }
+    }
+    false
+}
+
+// Reader threads still running, so shutdown can wait for them to finish
+static ACTIVE_READERS: AtomicUsize = AtomicUsize::new(0);
+
+// Counts a reader thread as running until it is dropped at the thread's end
+struct ReaderGuard;
+
+impl ReaderGuard {
+    fn new() -> Self {
+        ACTIVE_READERS.fetch_add(1, Ordering::SeqCst);
+        ReaderGuard
+    }
+}
+
+impl Drop for ReaderGuard {
+    fn drop(&mut self) {
+        ACTIVE_READERS.fetch_sub(1, Ordering::SeqCst);
     }
 }
 
@@ -57,137 +450,201 @@ lazy_static! {
 
 pub struct SessionManager {
     sessions: DashMap<String, Arc<std::sync::Mutex<SshSession>>>,
+    // Kept apart from the session so reading it never waits on the session
+    // lock, which the reader thread takes for every read
+    scrollbacks: DashMap<String, Arc<std::sync::Mutex<Scrollback>>>,
 }
 
 impl SessionManager {
     pub fn new() -> Self {
         Self {
             sessions: DashMap::new(),
+            scrollbacks: DashMap::new(),
         }
     }
 
     pub fn start_session(
         &self,
         app_handle: AppHandle,
-        host: String,
-        port: u16,
-        username: String,
-        password: String,
-        cols: u32,
-        rows: u32,
+        target: SshTarget,
+        options: PtyOptions,
     ) -> Result<String, String> {
+        let PtyOptions {
+            cols,
+            rows,
+            keepalive_interval,
+            idle_timeout,
+            record_path,
+            record_input,
+            auto_reconnect,
+            env,
+            initial_command,
+            scrollback_bytes,
+        } = options;
+        let setup = ShellSetup {
+            keepalive_interval,
+            env,
+            initial_command: initial_command.filter(|c| !c.trim().is_empty()),
+        };
         let session_id = Uuid::new_v4().to_string();
-        let addr = format!("{}:{}", host, port);
-
-        // Connect TCP
-        let tcp = TcpStream::connect(&addr)
-            .map_err(|e| format!("TCP connection failed: {}", e))?;
 
-        tcp.set_nonblocking(false)
-            .map_err(|e| format!("Failed to set blocking: {}", e))?;
-
-        // Create SSH session
-        let mut sess = Session::new()
-            .map_err(|e| format!("Failed to create session: {}", e))?;
-
-        sess.set_tcp_stream(tcp.try_clone().map_err(|e| e.to_string())?);
-        sess.handshake()
-            .map_err(|e| format!("SSH handshake failed: {}", e))?;
-
-        // Authenticate
-        sess.userauth_password(&username, &password)
-            .map_err(|e| format!("Authentication failed: {}", e))?;
-
-        if !sess.authenticated() {
-            return Err("Authentication failed".to_string());
-        }
+        // Create the recording first so a bad path fails before connecting
+        let recorder = record_path
+            .as_deref()
+            .map(|path| CastRecorder::create(path, cols, rows, record_input))
+            .transpose()?;
 
-        // Open channel and request PTY
-        let mut channel = sess
-            .channel_session()
-            .map_err(|e| format!("Failed to open channel: {}", e))?;
-
-        channel
-            .request_pty("xterm-256color", None, Some((cols, rows, 0, 0)))
-            .map_err(|e| format!("Failed to request PTY: {}", e))?;
-
-        channel
-            .shell()
-            .map_err(|e| format!("Failed to start shell: {}", e))?;
-
-        // Set channel to non-blocking for reading
-        sess.set_blocking(false);
+        let (sess, channel, tcp) = open_pty(&target, cols, rows, &setup)?;
+        let poller = Arc::new(Poller::new().map_err(|e| format!("Failed to create poller: {}", e))?);
+        let registration = PollRegistration::new(poller.clone(), &tcp)
+            .map_err(|e| format!("Failed to watch session socket: {}", e))?;
 
         let shutdown = Arc::new(AtomicBool::new(false));
         let shutdown_clone = shutdown.clone();
         let session_id_clone = session_id.clone();
 
         // Create session object
+        let started_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
         let ssh_session = SshSession {
-            id: session_id.clone(),
+            info: PtySessionInfo {
+                session_id: session_id.clone(),
+                host: target.host.clone(),
+                username: target.username.clone(),
+                started_at,
+                cols,
+                rows,
+            },
             channel,
             session: sess,
             tcp,
             shutdown,
+            last_activity: Instant::now(),
+            recorder,
+            poller: poller.clone(),
         };
 
         let session_arc = Arc::new(std::sync::Mutex::new(ssh_session));
         self.sessions.insert(session_id.clone(), session_arc.clone());
+        let scrollback = Arc::new(std::sync::Mutex::new(Scrollback::new(scrollback_bytes)));
+        self.scrollbacks.insert(session_id.clone(), scrollback.clone());
 
         // Spawn reader thread
+        let reader = ReaderGuard::new();
         thread::spawn(move || {
+            let _reader = reader;
+            let mut registration = registration;
             let mut buffer = [0u8; 4096];
+            let mut decoder = Utf8StreamDecoder::default();
+            let mut next_keepalive = Instant::now();
             
-            loop {
-                if shutdown_clone.load(Ordering::SeqCst) {
-                    break;
-                }
+            let reason = loop {
+                let reason = loop {
+                    if shutdown_clone.load(Ordering::SeqCst) {
+                        break EXIT_CLOSED;
+                    }
 
-                // Try to read from channel
-                let bytes_read = {
-                    let mut session = match session_arc.lock() {
-                        Ok(s) => s,
-                        Err(_) => break,
-                    };
+                    // Try to read from channel
+                    let data = {
+                        let mut session = match session_arc.lock() {
+                            Ok(s) => s,
+                            Err(_) => break EXIT_IO_ERROR,
+                        };
                     
-                    match session.channel.read(&mut buffer) {
-                        Ok(0) => {
-                            // EOF - send exit event
-                            let _ = app_handle.emit(
-                                "ssh-exit",
-                                SshExit {
-                                    session_id: session_id_clone.clone(),
-                                },
-                            );
-                            break;
-                        }
-                        Ok(n) => n,
-                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                            // No data available, sleep briefly
-                            drop(session);
-                            thread::sleep(Duration::from_millis(10));
-                            continue;
+                        match session.channel.read(&mut buffer) {
+                            Ok(0) => break EXIT_EOF,
+                            Ok(n) => {
+                                session.last_activity = Instant::now();
+                                // Decode to string, holding back a character split across reads
+                                let data = decoder.decode(&buffer[..n]);
+                                if let Some(recorder) = session.recorder.as_mut() {
+                                    if !data.is_empty() {
+                                        recorder.output(&data);
+                                    }
+                                }
+                                data
+                            }
+                            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                                if !idle_timeout.is_zero() && session.last_activity.elapsed() >= idle_timeout {
+                                    session.close();
+                                    break EXIT_IDLE_TIMEOUT;
+                                }
+                                // Idle: send a keepalive when one is due. libssh2 tells us
+                                // how long until the next; a failure means the link is dead.
+                                if keepalive_interval > 0 && Instant::now() >= next_keepalive {
+                                    match session.session.keepalive_send() {
+                                        Ok(secs) => {
+                                            next_keepalive = Instant::now() + Duration::from_secs(secs.max(1) as u64);
+                                        }
+                                        Err(ref e) if e.code() == ssh2::ErrorCode::Session(LIBSSH2_ERROR_EAGAIN) => {}
+                                        Err(_) => break EXIT_IO_ERROR,
+                                    }
+                                }
+                                // No data available: sleep until the socket has some. The lock
+                                // is released first so input can be sent meanwhile.
+                                let directions = session.session.block_directions();
+                                drop(session);
+                                if registration.wait(directions, POLL_TIMEOUT).is_err() {
+                                    break EXIT_IO_ERROR;
+                                }
+                                continue;
+                            }
+                            Err(_) => break EXIT_IO_ERROR,
                         }
-                        Err(_) => {
-                            break;
+                    };
+
+                    if !data.is_empty() {
+                        // Taken after the session lock is released, never while holding it
+                        if let Ok(mut scrollback) = scrollback.lock() {
+                            scrollback.push(&data);
                         }
+                        // Emit to frontend
+                        let _ = app_handle.emit(
+                            "ssh-output",
+                            SshOutput {
+                                session_id: session_id_clone.clone(),
+                                data,
+                            },
+                        );
                     }
                 };
+                // Only a dropped connection is worth reconnecting; EOF means the shell exited
+                if reason != EXIT_IO_ERROR
+                    || !auto_reconnect
+                    || !reconnect(&app_handle, &session_id_clone, &session_arc, &target, &setup, &shutdown_clone)
+                {
+                    break reason;
+                }
+                decoder = Utf8StreamDecoder::default();
+                next_keepalive = Instant::now();
+                // The reconnect brought a new socket to wait on
+                let watched = session_arc
+                    .lock()
+                    .ok()
+                    .and_then(|session| PollRegistration::new(poller.clone(), &session.tcp).ok());
+                match watched {
+                    Some(watched) => registration = watched,
+                    None => break EXIT_IO_ERROR,
+                }
+            };
 
-                if bytes_read > 0 {
-                    // Convert to string (lossy for binary data)
-                    let data = String::from_utf8_lossy(&buffer[..bytes_read]).to_string();
-                    
-                    // Emit to frontend
-                    let _ = app_handle.emit(
-                        "ssh-output",
-                        SshOutput {
-                            session_id: session_id_clone.clone(),
-                            data,
-                        },
-                    );
+            // Whatever ended the loop, drop the session and tell the frontend why
+            if let Ok(mut session) = session_arc.lock() {
+                if let Some(recorder) = session.recorder.as_mut() {
+                    recorder.finish();
                 }
             }
+            SESSION_MANAGER.sessions.remove(&session_id_clone);
+            SESSION_MANAGER.scrollbacks.remove(&session_id_clone);
+            let _ = app_handle.emit(
+                "ssh-exit",
+                SshExit {
+                    session_id: session_id_clone,
+                    reason: reason.to_string(),
+                },
+            );
         });
 
         Ok(session_id)
@@ -214,7 +671,49 @@ impl SessionManager {
         session.resize(cols, rows)
     }
 
+    /// Metadata of every live session, oldest first.
+    pub fn list_sessions(&self) -> Vec<PtySessionInfo> {
+        let mut sessions: Vec<PtySessionInfo> = self
+            .sessions
+            .iter()
+            .filter_map(|entry| entry.value().lock().ok().map(|s| s.info.clone()))
+            .collect();
+        sessions.sort_by_key(|info| info.started_at);
+        sessions
+    }
+
+    /// The recent output of a live session.
+    pub fn scrollback(&self, session_id: &str) -> Result<String, String> {
+        let scrollback = self
+            .scrollbacks
+            .get(session_id)
+            .map(|entry| entry.value().clone())
+            .ok_or("Session not found")?;
+        let scrollback = scrollback.lock().map_err(|_| "Lock failed")?;
+        Ok(scrollback.contents())
+    }
+
+    /// Number of sessions still registered, for spotting leaks.
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Closes every session and waits up to `timeout` for their reader threads
+    /// to exit. Returns how many sessions were closed.
+    pub fn close_all(&self, timeout: Duration) -> usize {
+        let ids: Vec<String> = self.sessions.iter().map(|entry| entry.key().clone()).collect();
+        for id in &ids {
+            let _ = self.close_session(id);
+        }
+        let deadline = Instant::now() + timeout;
+        while ACTIVE_READERS.load(Ordering::SeqCst) > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(20));
+        }
+        ids.len()
+    }
+
     pub fn close_session(&self, session_id: &str) -> Result<(), String> {
+        self.scrollbacks.remove(session_id);
         if let Some((_, session)) = self.sessions.remove(session_id) {
             if let Ok(mut s) = session.lock() {
                 s.close();
@@ -223,3 +722,86 @@ impl SessionManager {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_multibyte_character() {
+        let euro = "€".as_bytes();
+        assert_eq!(euro.len(), 3);
+
+        let mut decoder = Utf8StreamDecoder::default();
+        let mut first = b"price: ".to_vec();
+        first.extend_from_slice(&euro[..2]);
+        assert_eq!(decoder.decode(&first), "price: ");
+
+        let mut second = euro[2..].to_vec();
+        second.extend_from_slice(b" 5");
+        assert_eq!(decoder.decode(&second), "€ 5");
+    }
+
+    #[test]
+    fn test_scrollback_keeps_recent_output() {
+        let mut scrollback = Scrollback::new(8);
+        scrollback.push("abc");
+        assert_eq!(scrollback.contents(), "abc");
+        for _ in 0..10 {
+            scrollback.push("0123456789");
+        }
+        assert_eq!(scrollback.contents(), "23456789");
+        assert!(scrollback.text.len() <= 10 + 10);
+
+        // Never cut inside a character
+        scrollback.push("ab€€€");
+        assert_eq!(scrollback.contents(), "€€");
+
+        let mut disabled = Scrollback::new(0);
+        disabled.push("abc");
+        assert_eq!(disabled.contents(), "");
+    }
+
+    #[test]
+    fn test_poll_registration_wakes_on_data_and_notify() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        let poller = Arc::new(Poller::new().unwrap());
+        let registration = PollRegistration::new(poller.clone(), &client).unwrap();
+
+        // Nothing to read: times out
+        let start = Instant::now();
+        registration.wait(BlockDirections::Inbound, Duration::from_millis(50)).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(40));
+
+        // Data waiting, or a notify from `close`, returns right away
+        server.write_all(b"x").unwrap();
+        let start = Instant::now();
+        registration.wait(BlockDirections::Inbound, Duration::from_secs(10)).unwrap();
+        registration.wait(BlockDirections::Inbound, Duration::from_secs(10)).unwrap();
+        let mut byte = [0u8; 1];
+        (&client).read_exact(&mut byte).unwrap();
+        poller.notify().unwrap();
+        registration.wait(BlockDirections::Inbound, Duration::from_secs(10)).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_reconnect_backoff() {
+        let delays: Vec<u64> = (1..=7).map(|attempt| reconnect_delay(attempt).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 30, 30]);
+
+        let shutdown = AtomicBool::new(false);
+        assert!(sleep_unless_shutdown(&shutdown, Duration::from_millis(5)));
+        shutdown.store(true, Ordering::SeqCst);
+        assert!(!sleep_unless_shutdown(&shutdown, Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_invalid_bytes_become_replacement() {
+        let mut decoder = Utf8StreamDecoder::default();
+        assert_eq!(decoder.decode(b"a\xffb"), "a\u{FFFD}b");
+        assert_eq!(decoder.decode("中文".as_bytes()), "中文");
+    }
+}
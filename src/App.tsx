@@ -1,12 +1,30 @@
-import { useState } from "react";
+import { useState, useEffect } from "react";
+import { invoke } from "@tauri-apps/api/core";
 import { AppLayout } from "./components/Layout/AppLayout";
+import { UnlockScreen } from "./components/UnlockScreen/UnlockScreen";
 import { ServerConfig } from "./pages/ServerConfig/ServerConfig";
 import { LogSearch } from "./pages/LogSearch/LogSearch";
 import { Overview } from "./pages/Overview/Overview";
 import { Settings } from "./pages/Settings/Settings";
+import { LOCKED_EVENT } from "./config/lock";
 
 function App() {
   const [activePage, setActivePage] = useState("overview");
+  // null until the backend has said whether the server store is locked
+  const [isLocked, setIsLocked] = useState<boolean | null>(null);
+
+  useEffect(() => {
+    invoke<boolean>("is_locked")
+      .then(setIsLocked)
+      .catch((error) => {
+        console.error("Failed to check the lock state:", error);
+        setIsLocked(true);
+      });
+
+    const onLocked = () => setIsLocked(true);
+    window.addEventListener(LOCKED_EVENT, onLocked);
+    return () => window.removeEventListener(LOCKED_EVENT, onLocked);
+  }, []);
 
   const renderPage = () => {
     switch (activePage) {
@@ -23,6 +41,15 @@ function App() {
     }
   };
 
+  if (isLocked === null) {
+    return null;
+  }
+
+  // Pages load servers when they mount, so they only mount once unlocked
+  if (isLocked) {
+    return <UnlockScreen onUnlocked={() => setIsLocked(false)} />;
+  }
+
   return (
     <AppLayout activePage={activePage} onNavigate={setActivePage}>
       {renderPage()}
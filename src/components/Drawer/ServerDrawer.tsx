@@ -1,6 +1,7 @@
 import { X, RefreshCw, Loader2, Info, CheckCircle, XCircle } from "lucide-react";
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { handleLockedError } from "../../config/lock";
 import "./ServerDrawer.css";
 
 interface ServerConfig {
@@ -81,11 +82,8 @@ export function ServerDrawer({ isOpen, onClose, onSuccess, editServer }: ServerD
         const startTime = Date.now();
 
         try {
-            const result = await invoke<string>("test_ssh_connection", {
-                host,
-                port,
-                username,
-                password,
+            const result = await invoke<{ success: boolean; message: string }>("test_ssh_connection", {
+                target: { host, port, username, password },
             });
 
             // Ensure animation plays for at least 600ms
@@ -94,7 +92,7 @@ export function ServerDrawer({ isOpen, onClose, onSuccess, editServer }: ServerD
                 await new Promise(resolve => setTimeout(resolve, 600 - elapsed));
             }
 
-            setTestResult({ success: true, message: result });
+            setTestResult({ success: result.success, message: result.message });
         } catch (error) {
             // Ensure animation plays for at least 600ms even on error
             const elapsed = Date.now() - startTime;
@@ -135,6 +133,7 @@ export function ServerDrawer({ isOpen, onClose, onSuccess, editServer }: ServerD
                 onSuccess?.(serverConfig);
             }, 600);
         } catch (error) {
+            if (handleLockedError(error)) return;
             setTestResult({ success: false, message: String(error) });
         } finally {
             setIsSaving(false);
@@ -177,10 +177,7 @@ export function TerminalModal({ isOpen, onClose, server }: TerminalModalProps) {
             const rows = terminalInstance.current?.rows || 24;
 
             const newSessionId = await invoke<string>("start_pty_session", {
-                host: server.host,
-                port: server.port,
-                username: server.username,
-                password: server.password,
+                target: server,
                 cols,
                 rows,
             });
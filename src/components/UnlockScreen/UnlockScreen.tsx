@@ -0,0 +1,76 @@
+import { Lock, Loader2, XCircle } from "lucide-react";
+import { useState, FormEvent } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import "./UnlockScreen.css";
+
+interface UnlockScreenProps {
+    onUnlocked: () => void;
+}
+
+export function UnlockScreen({ onUnlocked }: UnlockScreenProps) {
+    const [masterPassword, setMasterPassword] = useState("");
+    const [isUnlocking, setIsUnlocking] = useState(false);
+    const [error, setError] = useState<string | null>(null);
+
+    const handleUnlock = async (e: FormEvent) => {
+        e.preventDefault();
+        if (!masterPassword) {
+            setError("Please enter the master password");
+            return;
+        }
+
+        setIsUnlocking(true);
+        setError(null);
+        try {
+            await invoke("unlock", { masterPassword });
+            setMasterPassword("");
+            onUnlocked();
+        } catch (err) {
+            setError(String(err));
+        } finally {
+            setIsUnlocking(false);
+        }
+    };
+
+    return (
+        <div className="unlock-overlay">
+            <form className="unlock-panel" onSubmit={handleUnlock}>
+                <div className="unlock-icon">
+                    <Lock size={24} />
+                </div>
+                <h3 className="unlock-title">Unlock Server Store</h3>
+                <p className="unlock-hint">
+                    服务器配置已加密保存。首次使用时，此处输入的密码将成为主密码，请妥善保管。
+                </p>
+
+                <input
+                    type="password"
+                    className="unlock-input"
+                    placeholder="Master password"
+                    value={masterPassword}
+                    onChange={(e) => setMasterPassword(e.target.value)}
+                    autoComplete="current-password"
+                    autoFocus
+                />
+
+                {error && (
+                    <div className="unlock-error">
+                        <XCircle size={16} />
+                        <span>{error}</span>
+                    </div>
+                )}
+
+                <button type="submit" className="unlock-btn" disabled={isUnlocking}>
+                    {isUnlocking ? (
+                        <>
+                            <Loader2 size={16} className="spin" />
+                            Unlocking...
+                        </>
+                    ) : (
+                        "Unlock"
+                    )}
+                </button>
+            </form>
+        </div>
+    );
+}
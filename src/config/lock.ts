@@ -0,0 +1,16 @@
+// Server store commands (list_servers, save_server, ...) fail with this error
+// until the master password has been entered through `unlock`
+export const LOCKED_ERROR = "locked";
+
+// Dispatched on `window` to bring the unlock prompt back
+export const LOCKED_EVENT = "store-locked";
+
+// True when `error` is the backend's "locked" error, in which case the unlock
+// prompt is shown again and the caller can skip its own error handling
+export function handleLockedError(error: unknown): boolean {
+    if (String(error) !== LOCKED_ERROR) {
+        return false;
+    }
+    window.dispatchEvent(new Event(LOCKED_EVENT));
+    return true;
+}
@@ -1,5 +1,6 @@
 import { useState, useEffect, useRef } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { handleLockedError } from "../../config/lock";
 import { open } from '@tauri-apps/plugin-dialog';
 import {
     History, Play, SlidersHorizontal, Fingerprint, FolderOpen,
@@ -182,6 +183,7 @@ export function LogSearch() {
             const servers = await invoke<ServerInfo[]>("list_servers");
             setAvailableServers(servers);
         } catch (error) {
+            if (handleLockedError(error)) return;
             console.error("Failed to load servers:", error);
         }
     };
@@ -362,10 +364,7 @@ export function LogSearch() {
         const searchPromises = selectedServers.map(async (server) => {
             try {
                 const result = await invoke<LogSearchResult>("search_log_files", {
-                    host: server.host,
-                    port: server.port,
-                    username: server.username,
-                    password: server.password,
+                    target: server,
                     serverId: server.id,
                     logPath: logPath,
                     traceId: traceId,
@@ -461,10 +460,7 @@ export function LogSearch() {
                 try {
                     // Read file content (using a larger limit for export)
                     const content = await invoke<string>("read_log_file", {
-                        host: item.serverInfo.host,
-                        port: item.serverInfo.port,
-                        username: item.serverInfo.username,
-                        password: item.serverInfo.password,
+                        target: item.serverInfo,
                         filePath: item.file.path,
                         traceId: traceId,
                         maxLines: 50000,
@@ -772,10 +768,7 @@ export function LogSearch() {
                     duration_ms: number;
                     error: string | null;
                 }>('trace_server_chain', {
-                    host: server.host,
-                    port: server.port,
-                    username: server.username,
-                    password: server.password,
+                    target: server,
                     traceId: serverChainTraceId,
                     logPath: serverChainLogPath,
                     knownServers: availableServers
@@ -1744,10 +1737,7 @@ function LogDetailModal({ data, onClose }: LogDetailModalProps) {
 
         try {
             const content = await invoke<string>("read_log_file", {
-                host: data.serverInfo.host,
-                port: data.serverInfo.port,
-                username: data.serverInfo.username,
-                password: data.serverInfo.password,
+                target: data.serverInfo,
                 filePath: file.path,
                 traceId: data.traceId || "",
                 maxLines: 5000,
@@ -1,5 +1,6 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { handleLockedError } from "../../config/lock";
 import { RefreshCw, ArrowRight, Settings, Download, Info, AlertTriangle, WifiOff, CheckCircle } from "lucide-react";
 import "./Overview.css";
 
@@ -74,6 +75,7 @@ export function Overview() {
             const serverList = await invoke<ServerInfo[]>("list_servers");
             setServers(serverList);
         } catch (error) {
+            if (handleLockedError(error)) return;
             console.error("Failed to load servers:", error);
         }
     };
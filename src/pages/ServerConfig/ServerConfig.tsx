@@ -4,6 +4,7 @@ import { invoke } from "@tauri-apps/api/core";
 import { save } from "@tauri-apps/plugin-dialog";
 import { ServerDrawer } from "../../components/Drawer/ServerDrawer";
 import { TerminalModal } from "../../components/Terminal/TerminalModal";
+import { handleLockedError } from "../../config/lock";
 import "./ServerConfig.css";
 
 interface ServerInfo {
@@ -32,6 +33,7 @@ export function ServerConfig() {
             const result = await invoke<ServerInfo[]>("list_servers");
             setServers(result);
         } catch (error) {
+            if (handleLockedError(error)) return;
             console.error("Failed to load servers:", error);
         }
     };
@@ -46,6 +48,7 @@ export function ServerConfig() {
             await invoke("delete_server", { id });
             loadServers();
         } catch (error) {
+            if (handleLockedError(error)) return;
             console.error("Failed to delete server:", error);
         }
     };
@@ -68,21 +71,28 @@ export function ServerConfig() {
     const handleTestConnection = async (server: ServerInfo) => {
         setTestingServerId(server.id);
         try {
-            await invoke<string>("test_ssh_connection", {
-                host: server.host,
-                port: server.port,
-                username: server.username,
-                password: server.password,
+            const result = await invoke<{ success: boolean; message: string }>("test_ssh_connection", {
+                target: server,
             });
+            if (!result.success) {
+                throw new Error(result.message);
+            }
             await invoke("save_server", {
                 server: { ...server, status: "online" },
             });
             loadServers();
         } catch (error) {
-            await invoke("save_server", {
-                server: { ...server, status: "offline" },
-            });
-            loadServers();
+            if (handleLockedError(error)) return;
+            try {
+                await invoke("save_server", {
+                    server: { ...server, status: "offline" },
+                });
+                loadServers();
+            } catch (saveError) {
+                if (!handleLockedError(saveError)) {
+                    console.error("Failed to save server status:", saveError);
+                }
+            }
         } finally {
             setTestingServerId(null);
         }
@@ -147,6 +157,7 @@ export function ServerConfig() {
             });
             alert("服务器配置导出成功! (密码已加密)");
         } catch (error) {
+            if (handleLockedError(error)) return;
             console.error("Export failed:", error);
             alert("导出失败: " + error);
         }
@@ -195,6 +206,7 @@ export function ServerConfig() {
                 alert(`成功导入 ${importedCount} 台服务器`);
                 loadServers();
             } catch (error) {
+                if (handleLockedError(error)) return;
                 console.error("Import failed:", error);
                 alert("导入失败: " + error);
             }